@@ -0,0 +1,60 @@
+//! Time of concurrent requests on a fresh client, as made by every fetch,
+//! over HTTP/1.1 versus HTTP/2 with prior knowledge
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::future::join_all;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::runtime::Runtime;
+
+const REQUESTS: usize = 10;
+
+const GRADE_LIST: &str = include_str!("../tests/fixtures/grade_list.json");
+
+/// Starts a server answering every request with a grade list, in whichever
+/// of HTTP/1.1 and HTTP/2 the client speaks. It only runs while `runtime`
+/// is blocking on something.
+fn serve(runtime: &mut Runtime) -> SocketAddr {
+    runtime.block_on(async {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_| async {
+                Ok::<_, Infallible>(Response::new(Body::from(GRADE_LIST)))
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    })
+}
+
+fn concurrent_requests(c: &mut Criterion) {
+    let mut runtime = Runtime::new().unwrap();
+    let url = format!(
+        "http://{}/for-std/grade/sheet/getGradeList",
+        serve(&mut runtime)
+    );
+
+    let mut group = c.benchmark_group("10 concurrent requests on a new client");
+    for &(name, http2) in &[("HTTP/1.1", false), ("HTTP/2", true)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut builder = reqwest::Client::builder();
+                if http2 {
+                    builder = builder.http2_prior_knowledge();
+                }
+                let client = builder.build().unwrap();
+                runtime.block_on(join_all((0..REQUESTS).map(|_| async {
+                    let response = client.get(&url).send().await.unwrap();
+                    response.text().await.unwrap()
+                })))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_requests);
+criterion_main!(benches);
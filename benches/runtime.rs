@@ -0,0 +1,29 @@
+//! Cost of creating a runtime per blocking call versus sharing one
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+use ustc_get_grade::blocking;
+
+const CALLS: usize = 100;
+
+fn blocking_calls(c: &mut Criterion) {
+    let mut group = c.benchmark_group("100 sequential blocking calls");
+    group.bench_function("new runtime per call", |b| {
+        b.iter(|| {
+            for _ in 0..CALLS {
+                Runtime::new().unwrap().block_on(tokio::task::yield_now());
+            }
+        })
+    });
+    group.bench_function("shared runtime", |b| {
+        b.iter(|| {
+            for _ in 0..CALLS {
+                blocking::block_on(tokio::task::yield_now());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, blocking_calls);
+criterion_main!(benches);
@@ -0,0 +1,99 @@
+//! The latest grade on demand, e.g. for a status page
+//!
+//! `GET /grade` returns the grade last fetched by the main loop as JSON, and
+//! 503 before the first fetch. `GET /healthz` always returns 200. Only
+//! served when built with the `http` feature.
+
+#![cfg_attr(not(feature = "http"), allow(dead_code))]
+
+use crate::http::Response;
+use std::sync::{Arc, Mutex};
+use ustc_get_grade::Grade;
+
+/// The grade shared between the main loop and the server
+pub type GradeCache = Arc<Mutex<Option<Grade>>>;
+
+pub fn store(cache: &GradeCache, grade: &Grade) {
+    *cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(grade.clone());
+}
+
+fn respond(cache: &GradeCache, path: &str) -> Response {
+    match path {
+        "/grade" => match &*cache.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(grade) => Response::new(
+                "200 OK",
+                "application/json",
+                serde_json::to_string(grade).unwrap_or_default(),
+            ),
+            None => Response::new(
+                "503 Service Unavailable",
+                "text/plain",
+                "No grade fetched yet\n".to_owned(),
+            ),
+        },
+        "/healthz" => Response::new("200 OK", "text/plain", "OK\n".to_owned()),
+        _ => Response::not_found(),
+    }
+}
+
+/// Serves `/grade` and `/healthz` on `listen` in a background thread
+#[cfg(feature = "http")]
+pub fn serve(listen: &str, cache: GradeCache) -> anyhow::Result<()> {
+    crate::http::serve(listen, move |path| respond(&cache, path))?;
+    log::info!("Serving the grade on {}", listen);
+    Ok(())
+}
+
+#[cfg(not(feature = "http"))]
+pub fn serve(_listen: &str, _cache: GradeCache) -> anyhow::Result<()> {
+    anyhow::bail!("Built without the `http` feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use ustc_get_grade::test_utils::mock_grade;
+
+    #[test]
+    fn grade_once_fetched() {
+        let cache = GradeCache::default();
+        assert_eq!(respond(&cache, "/grade").status, "503 Service Unavailable");
+        assert_eq!(respond(&cache, "/healthz").status, "200 OK");
+        assert_eq!(respond(&cache, "/").status, "404 Not Found");
+
+        store(&cache, &mock_grade(&["2019年夏季学期"]));
+        let response = respond(&cache, "/grade");
+        assert_eq!(response.status, "200 OK");
+        let body: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body["gpa"], 3.52);
+        assert_eq!(body["scores"][0]["name"], "2019年夏季学期");
+        assert_eq!(body["scores"][0]["courses"][0]["name"], "军事技能");
+        assert_eq!(body["scores"][0]["courses"][0]["status"], "normal");
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn serve_grade() {
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let cache = GradeCache::default();
+        store(&cache, &mock_grade(&[]));
+        serve(&addr.to_string(), cache).unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /grade HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let grade: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(grade["credits"], 18.5);
+        assert_eq!(grade["scores"].as_array().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,533 @@
+//! Documentation of the config file, printed by `--help-config`
+//!
+//! Kept by hand next to `config_example.toml`; the tests check that both
+//! list the same fields and that the defaults match the deserialized ones.
+
+struct Field {
+    name: &'static str,
+    ty: &'static str,
+    /// `None` for required fields, `Some("")` for optional ones without a
+    /// default
+    default: Option<&'static str>,
+    description: &'static str,
+}
+
+struct Section {
+    name: &'static str,
+    required: bool,
+    description: &'static str,
+    fields: &'static [Field],
+}
+
+const fn required(name: &'static str, ty: &'static str, description: &'static str) -> Field {
+    Field {
+        name,
+        ty,
+        default: None,
+        description,
+    }
+}
+
+const fn optional(
+    name: &'static str,
+    ty: &'static str,
+    default: &'static str,
+    description: &'static str,
+) -> Field {
+    Field {
+        name,
+        ty,
+        default: Some(default),
+        description,
+    }
+}
+
+const SECTIONS: &[Section] = &[
+    Section {
+        name: "mail",
+        required: false,
+        description: "The SMTP account that sends the reports, and who receives them; without it \
+                      no email is sent",
+        fields: &[
+            required("username", "string", "Login name of the SMTP account"),
+            optional(
+                "password",
+                "string or { keyring = string }",
+                "",
+                "Password of the account, or the OS keyring service it is stored in under \
+                 username; one of password, pass_exec and keyring_service is required",
+            ),
+            optional(
+                "pass_exec",
+                "string",
+                "",
+                "Command printing the password, run once when the config is loaded",
+            ),
+            optional(
+                "keyring_service",
+                "string",
+                "",
+                "Service of the password in the OS keyring, needs the `keyring` feature",
+            ),
+            optional(
+                "keyring_user",
+                "string",
+                "",
+                "User of the password in the OS keyring, set with keyring_service",
+            ),
+            required(
+                "server",
+                "string",
+                "SMTP server, connected to with TLS on port 465",
+            ),
+            required(
+                "sendto",
+                "array of string or { address = string, format = string }",
+                "Recipients; format is text, html or alternative, the default",
+            ),
+            optional(
+                "from_address",
+                "string",
+                "username",
+                "Address in the From header, while username still logs in",
+            ),
+            optional(
+                "from_name",
+                "string",
+                "",
+                "Display name in the From header, e.g. \"Grade Bot\"",
+            ),
+            optional(
+                "bilingual_email",
+                "bool",
+                "false",
+                "Appends an English version of the report",
+            ),
+            optional(
+                "max_name_width",
+                "integer",
+                "40",
+                "Display width after which course names are cut in text emails",
+            ),
+            optional(
+                "email_max_size_kb",
+                "integer",
+                "",
+                "Size limit in KB of each part of an email, beyond which courses are cut",
+            ),
+            optional(
+                "error_email_cooldown",
+                "float",
+                "3600",
+                "Seconds during which an identical error email is not sent again",
+            ),
+            optional(
+                "recovery_email",
+                "bool",
+                "false",
+                "Sends an email when fetching succeeds again after an error email",
+            ),
+        ],
+    },
+    Section {
+        name: "ustc",
+        required: true,
+        description: "The USTC account and when to fetch its grade",
+        fields: &[
+            required("username", "string", "Student ID"),
+            optional(
+                "password",
+                "string or { keyring = string }",
+                "",
+                "Same as in [mail]",
+            ),
+            optional("pass_exec", "string", "", "Same as in [mail]"),
+            optional("keyring_service", "string", "", "Same as in [mail]"),
+            optional("keyring_user", "string", "", "Same as in [mail]"),
+            optional(
+                "tgc",
+                "string",
+                "",
+                "TGC cookie of a browser login to passport.ustc.edu.cn, used instead of the \
+                 password, which is then not needed",
+            ),
+            optional(
+                "tgc_exec",
+                "string",
+                "",
+                "Command printing the TGC cookie, like pass_exec",
+            ),
+            required(
+                "semesters",
+                "array of string or integer",
+                "Semesters to track, e.g. \"2019年秋季学期\" or the ID 121, or [\"all\"], or \
+                 [\"current\"] for the ongoing one",
+            ),
+            optional(
+                "interval",
+                "float",
+                "",
+                "Minutes between fetches, at least 10; exactly one of interval and \
+                 schedule is required",
+            ),
+            optional(
+                "interval_jitter_percent",
+                "float",
+                "10",
+                "Percentage in [0, 50] by which each wait of interval is randomly changed, \
+                 recommended so that users do not fetch in lock-step",
+            ),
+            optional(
+                "interval_jitter",
+                "float",
+                "",
+                "Same as interval_jitter_percent but as a fraction in [0, 1), which overrides it",
+            ),
+            optional(
+                "schedule",
+                "string",
+                "",
+                "Cron expression of when to fetch, e.g. \"*/10 8-23 * * *\"",
+            ),
+            optional(
+                "timezone",
+                "string",
+                "local",
+                "Timezone of schedule: local, UTC or an offset like +08:00",
+            ),
+            optional(
+                "send_first",
+                "bool",
+                "false",
+                "Sends the grade after the first fetch",
+            ),
+            optional(
+                "log_heartbeat",
+                "bool",
+                "false",
+                "Logs the GPA and the time of the next fetch after every fetch",
+            ),
+            optional(
+                "concurrent_notify",
+                "bool",
+                "false",
+                "Notifies through all the channels at once instead of one by one",
+            ),
+            optional(
+                "watch_courses",
+                "array of string",
+                "[]",
+                "Only notifies about courses whose names contain one of these, ignoring case; \
+                 empty watches all courses",
+            ),
+            optional(
+                "min_login_interval",
+                "float",
+                "60",
+                "Minimum seconds between two logins",
+            ),
+            optional(
+                "captcha_cooldown",
+                "float",
+                "1800",
+                "Seconds to wait before logging in again once a captcha is required",
+            ),
+            optional(
+                "max_retries",
+                "integer",
+                "3",
+                "Times a fetch is retried after a network error or a 5xx response, at most 10",
+            ),
+            optional(
+                "retry_base_delay_seconds",
+                "float",
+                "5",
+                "Seconds before the first retry, doubling on every retry",
+            ),
+            optional(
+                "fetch_rank",
+                "bool",
+                "false",
+                "Fetches the rank in the major after the grade, notifying when it changes",
+            ),
+            optional(
+                "http2",
+                "bool",
+                "false",
+                "Speaks HTTP/2 without negotiating it, failing if a server only speaks HTTP/1.1",
+            ),
+            optional(
+                "per_semester_requests",
+                "bool",
+                "false",
+                "Fetches every selected semester with a request of its own, all at once; the GPA \
+                 of the selection is then not known if more than one semester is selected",
+            ),
+            optional(
+                "user_agent",
+                "string",
+                "",
+                "User-Agent of the requests, else USTC_USER_AGENT or a built-in one",
+            ),
+            optional(
+                "api_base_path",
+                "string",
+                "\"\"",
+                "Path under which the jw.ustc.edu.cn endpoints are served",
+            ),
+        ],
+    },
+    Section {
+        name: "apns_relay",
+        required: false,
+        description: "A relay server forwarding notifications to iOS devices",
+        fields: &[
+            required("relay_url", "string", "URL of the relay server"),
+            required("device_token", "string", "Token of the device to notify"),
+        ],
+    },
+    Section {
+        name: "webhook",
+        required: false,
+        description: "A URL to POST the grade and its changes to as JSON",
+        fields: &[required("url", "string", "URL of the webhook")],
+    },
+    Section {
+        name: "telegram",
+        required: false,
+        description: "A Telegram bot sending the notifications",
+        fields: &[
+            required("bot_token", "string", "Token of the bot"),
+            required("chat_id", "string", "Chat to send the notifications to"),
+        ],
+    },
+    Section {
+        name: "filter",
+        required: false,
+        description: "Courses whose changes do not trigger notifications",
+        fields: &[
+            optional(
+                "ignore_scores",
+                "array of string",
+                "[]",
+                "Scores to ignore, e.g. \"通过\"",
+            ),
+            optional(
+                "ignore_courses",
+                "array of string",
+                "[]",
+                "Regular expressions matching whole names of courses to ignore",
+            ),
+            optional(
+                "only_courses",
+                "array of string",
+                "[]",
+                "Regular expressions matching whole names of the only courses to watch",
+            ),
+        ],
+    },
+    Section {
+        name: "log",
+        required: false,
+        description: "Writing the log to a file too",
+        fields: &[
+            optional("file", "string", "", "Path of the log file"),
+            optional(
+                "level",
+                "string",
+                "error",
+                "Level or env_logger filters, overridden by RUST_LOG",
+            ),
+            optional(
+                "max_size",
+                "integer",
+                "10485760",
+                "Size in bytes after which the log file is rotated",
+            ),
+            optional("keep", "integer", "5", "Number of rotated files to keep"),
+        ],
+    },
+    Section {
+        name: "metrics",
+        required: false,
+        description: "Prometheus metrics on /metrics, needs the `metrics` feature",
+        fields: &[required("listen", "string", "Address to listen on")],
+    },
+    Section {
+        name: "status",
+        required: false,
+        description: "A health check on /healthz",
+        fields: &[
+            required("listen", "string", "Address to listen on"),
+            optional(
+                "verbose",
+                "bool",
+                "false",
+                "Includes the courses in the response",
+            ),
+            optional(
+                "max_failures",
+                "integer",
+                "3",
+                "Failed fetches in a row after which it returns 503",
+            ),
+        ],
+    },
+    Section {
+        name: "http",
+        required: false,
+        description: "The latest grade as JSON on /grade, needs the `http` feature",
+        fields: &[required("listen", "string", "Address to listen on")],
+    },
+    Section {
+        name: "output",
+        required: false,
+        description: "Files the reports are written to after every fetch",
+        fields: &[
+            required(
+                "dir",
+                "string",
+                "Directory of the files, created if missing",
+            ),
+            optional(
+                "formats",
+                "array of \"html\", \"text\" or \"json\"",
+                "[\"html\", \"text\", \"json\"]",
+                "Formats of latest.html, latest.txt and latest.json; changed-<timestamp>.html \
+                 is also written when the grade changes",
+            ),
+            optional(
+                "history",
+                "boolean",
+                "false",
+                "Appends the GPA of every fetch to history.jsonl, drawn by --chart",
+            ),
+        ],
+    },
+];
+
+/// The documentation of every section and field of the config file
+pub fn render() -> String {
+    let mut out = String::new();
+    for section in SECTIONS {
+        let required = if section.required {
+            "required"
+        } else {
+            "optional"
+        };
+        out += &format!(
+            "[{}] ({})\n    {}\n\n",
+            section.name, required, section.description
+        );
+        for field in section.fields {
+            let default = match field.default {
+                None => "required".to_owned(),
+                Some("") => "optional".to_owned(),
+                Some(default) => format!("default: {}", default),
+            };
+            out += &format!(
+                "    {}: {}, {}\n        {}\n",
+                field.name, field.ty, default, field.description
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(section: &str, name: &str) -> &'static Field {
+        SECTIONS
+            .iter()
+            .find(|s| s.name == section)
+            .and_then(|s| s.fields.iter().find(|f| f.name == name))
+            .unwrap_or_else(|| panic!("[{}] {} is not documented", section, name))
+    }
+
+    #[test]
+    fn fields_in_example_documented() {
+        let example = include_str!("../config_example.toml");
+        for section in SECTIONS {
+            assert!(
+                example.contains(&format!("[{}]", section.name)),
+                "[{}]",
+                section.name
+            );
+            for field in section.fields {
+                assert!(
+                    example.contains(&format!("{} = ", field.name)),
+                    "[{}] {}",
+                    section.name,
+                    field.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn example_is_valid() {
+        let example = include_str!("../config_example.toml");
+        if let Err(errors) = crate::read_config(example.as_bytes()) {
+            panic!("{:?}", errors);
+        }
+    }
+
+    #[test]
+    fn defaults_match() {
+        let config = crate::read_config(
+            r#"
+            [mail]
+            username = "me@example.com"
+            password = "mail-pass"
+            server = "smtp.example.com"
+            sendto = ["me@example.com"]
+
+            [ustc]
+            username = "PB19000000"
+            password = "ustc-pass"
+            semesters = ["all"]
+            interval = 30
+        "#
+            .as_bytes(),
+        )
+        .unwrap();
+        let mail = config.mail.as_ref().unwrap();
+        let defaults = [
+            ("mail", "max_name_width", mail.max_name_width as f64),
+            ("mail", "error_email_cooldown", mail.error_email_cooldown),
+            (
+                "ustc",
+                "interval_jitter_percent",
+                config.ustc.interval_jitter_percent,
+            ),
+            ("ustc", "min_login_interval", config.ustc.min_login_interval),
+            ("ustc", "captcha_cooldown", config.ustc.captcha_cooldown),
+            ("ustc", "max_retries", config.ustc.max_retries as f64),
+            (
+                "ustc",
+                "retry_base_delay_seconds",
+                config.ustc.retry_base_delay_seconds,
+            ),
+        ];
+        for (section, name, value) in defaults.iter() {
+            let default = field(section, name).default.unwrap();
+            assert_eq!(default.parse::<f64>().ok(), Some(*value), "{}", name);
+        }
+        assert_eq!(field("ustc", "send_first").default, Some("false"));
+        assert!(!config.ustc.send_first);
+    }
+
+    #[test]
+    fn rendered() {
+        let text = render();
+        assert!(text.starts_with("[mail] (optional)\n"));
+        assert!(text.contains("\n[ustc] (required)\n"));
+        assert!(text.contains("\n[webhook] (optional)\n"));
+        assert!(text.contains("\n    username: string, required\n        Student ID\n"));
+        assert!(text.contains("\n    max_retries: integer, default: 3\n"));
+        assert!(text.contains("\n    user_agent: string, optional\n"));
+    }
+}
@@ -0,0 +1,258 @@
+//! Changes between two fetches of a grade
+
+use crate::{numeric_score, CourseGrade, Grade};
+
+/// A course that appeared or whose score changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct CourseChange {
+    /// Name of the semester the course is in
+    pub semester: String,
+
+    /// The course in the older grade, `None` if the course is new
+    pub old: Option<CourseGrade>,
+
+    pub new: CourseGrade,
+}
+
+/// Differences from an older grade to a newer one
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeDiff {
+    /// See [`Grade::gpa_change`](../struct.Grade.html#method.gpa_change)
+    pub gpa_change: Option<f64>,
+
+    /// See [`Grade::sem_gpa_change`](../struct.Grade.html#method.sem_gpa_change)
+    pub sem_gpa_change: Option<f64>,
+
+    /// New and changed courses, in the order of the newer grade
+    pub courses: Vec<CourseChange>,
+
+    /// Whether the [rank](../struct.Grade.html#structfield.rank) changed,
+    /// was published or was withdrawn
+    pub rank_changed: bool,
+}
+
+impl GradeDiff {
+    /// Compares `old` to the newer grade `new`. Courses are matched by their
+    /// semester and code, or name if the code is unknown, see
+    /// [`CourseGrade::same_course`](../struct.CourseGrade.html#method.same_course).
+    pub fn between(old: &Grade, new: &Grade) -> GradeDiff {
+        let mut courses = Vec::new();
+        for semester in new.scores.iter() {
+            let old_semester = old.semester(&semester.name);
+            for course in semester.courses.iter() {
+                let old_course =
+                    old_semester.and_then(|s| s.courses.iter().find(|c| c.same_course(course)));
+                if old_course.map(|c| &c.score) != Some(&course.score) {
+                    courses.push(CourseChange {
+                        semester: semester.name.clone(),
+                        old: old_course.cloned(),
+                        new: course.clone(),
+                    });
+                }
+            }
+        }
+
+        GradeDiff {
+            gpa_change: old.gpa_change(new),
+            sem_gpa_change: old.sem_gpa_change(new),
+            courses,
+            rank_changed: old.rank != new.rank,
+        }
+    }
+
+    /// Whether there is no new or changed course and the GPAs and the rank
+    /// are the same, counting a GPA being published as a change
+    pub fn is_empty(&self) -> bool {
+        self.courses.is_empty()
+            && self.gpa_change == Some(0.)
+            && self.sem_gpa_change == Some(0.)
+            && !self.rank_changed
+    }
+
+    /// Courses that did not exist in the older grade
+    pub fn new_courses(&self) -> impl Iterator<Item = &CourseChange> {
+        self.courses.iter().filter(|c| c.old.is_none())
+    }
+
+    /// Courses whose scores changed
+    pub fn changed_courses(&self) -> impl Iterator<Item = &CourseChange> {
+        self.courses.iter().filter(|c| c.old.is_some())
+    }
+
+    /// New and changed courses, the most recently graded first. Courses
+    /// without [`graded_at`](../struct.CourseGrade.html#structfield.graded_at)
+    /// come last, in the order of the newer grade.
+    pub fn by_recency(&self) -> Vec<&CourseChange> {
+        let mut courses: Vec<_> = self.courses.iter().collect();
+        // `graded_at` is in the same format for every course, so comparing
+        // the strings orders them by time
+        courses.sort_by(|a, b| b.new.graded_at.cmp(&a.new.graded_at));
+        courses
+    }
+
+    /// The change of `course` in `semester`, if any
+    pub fn change(&self, semester: &str, course: &CourseGrade) -> Option<&CourseChange> {
+        self.courses
+            .iter()
+            .find(|c| c.semester == semester && c.new.same_course(course))
+    }
+}
+
+/// Formats a score change as "old → new", prefixed by "↑" or "↓" if the
+/// score rose or fell, see [`numeric_score`](../fn.numeric_score.html), or
+/// by "*" if one score is a number and the other is not, e.g. "85 → A"
+pub fn format_score_change(old: &str, new: &str) -> String {
+    let is_number = |score: &str| score.trim().parse::<f64>().is_ok();
+    let prefix = if is_number(old) != is_number(new) {
+        "* "
+    } else {
+        match (numeric_score(old), numeric_score(new)) {
+            (Some(old), Some(new)) if new > old => "↑ ",
+            (Some(old), Some(new)) if new < old => "↓ ",
+            _ => "",
+        }
+    };
+    format!("{}{} → {}", prefix, old, new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+    use crate::{Rank, Semester};
+
+    #[test]
+    fn same_grade() {
+        let grade = mock_grade(&[]);
+        assert!(GradeDiff::between(&grade, &grade).is_empty());
+    }
+
+    #[test]
+    fn new_semester_and_course() {
+        let old = mock_grade(&["2019年春季学期"]);
+        let mut new = mock_grade(&["2019年春季学期", "2019年夏季学期"]);
+        new.scores[0]
+            .courses
+            .push(CourseGrade::new("复变函数(B)", "88", 3.0));
+
+        let diff = GradeDiff::between(&old, &new);
+        let names: Vec<_> = diff.new_courses().map(|c| c.new.name.as_str()).collect();
+        assert_eq!(names, ["复变函数(B)", "军事技能"]);
+        assert_eq!(diff.courses[1].semester, "2019年夏季学期");
+        assert_eq!(diff.changed_courses().count(), 0);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn score_changes() {
+        assert_eq!(format_score_change("78", "81"), "↑ 78 → 81");
+        assert_eq!(format_score_change("81", "78.5"), "↓ 81 → 78.5");
+        assert_eq!(format_score_change("B+", "A-"), "↑ B+ → A-");
+        assert_eq!(format_score_change("85", "A"), "* 85 → A");
+        assert_eq!(format_score_change("通过", "85"), "* 通过 → 85");
+        assert_eq!(format_score_change("良好", "良"), "良好 → 良");
+        assert_eq!(format_score_change("通过", "不通过"), "通过 → 不通过");
+    }
+
+    #[test]
+    fn changed_score() {
+        let old = mock_grade(&["2019年秋季学期"]);
+        let mut new = old.clone();
+        new.scores[0].courses[0].score = "81".to_owned();
+        new.gpa = new.gpa.map(|gpa| gpa + 0.1);
+
+        let diff = GradeDiff::between(&old, &new);
+        assert_eq!(diff.new_courses().count(), 0);
+        let change = diff.changed_courses().next().unwrap();
+        assert_eq!(change.old.as_ref().unwrap().score, "78");
+        assert_eq!(change.new.score, "81");
+        assert_eq!(diff.change("2019年秋季学期", &change.new), Some(change));
+        assert_eq!(diff.change("2019年春季学期", &change.new), None);
+        assert!((diff.gpa_change.unwrap() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn published_gpa_is_a_change() {
+        let mut old = mock_grade(&["2019年秋季学期"]);
+        old.gpa = None;
+        let new = mock_grade(&["2019年秋季学期"]);
+        let diff = GradeDiff::between(&old, &new);
+        assert!(diff.courses.is_empty());
+        assert_eq!(diff.gpa_change, None);
+        assert!(!diff.is_empty());
+        assert!(GradeDiff::between(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn rank_change_alone() {
+        let old = mock_grade(&["2019年秋季学期"]);
+        let mut new = old.clone();
+        new.rank = Some(Rank::new(12, 143, "专业"));
+        let diff = GradeDiff::between(&old, &new);
+        assert!(diff.courses.is_empty());
+        assert!(diff.rank_changed);
+        assert!(!diff.is_empty());
+
+        let mut newer = new.clone();
+        newer.rank = Some(Rank::new(10, 143, "专业"));
+        assert!(!GradeDiff::between(&new, &newer).is_empty());
+        assert!(GradeDiff::between(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn changes_by_recency() {
+        let old = mock_grade(&["2019年春季学期"]);
+        let mut new = mock_grade(&[]);
+        let times = ["2020-01-15 10:23:45", "2020-01-16 09:00:00"];
+        for (course, time) in new.scores[2].courses.iter_mut().zip(times.iter()) {
+            course.graded_at = Some(time.to_string());
+        }
+
+        let diff = GradeDiff::between(&old, &new);
+        let names: Vec<_> = diff
+            .by_recency()
+            .iter()
+            .map(|c| c.new.name.as_str())
+            .collect();
+        assert_eq!(names, ["大学物理-基础实验A", "数学分析(B2)", "军事技能"]);
+    }
+
+    #[test]
+    fn removed_course_is_ignored() {
+        let old = mock_grade(&["2019年秋季学期"]);
+        let mut new = old.clone();
+        new.scores = vec![Semester::new("2019年秋季学期", Vec::new())];
+        assert!(GradeDiff::between(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn courses_matched_by_code() {
+        let code = |grade: &mut Grade, codes: &[&str]| {
+            for (course, &c) in grade.scores[0].courses.iter_mut().zip(codes) {
+                course.code = c.to_owned();
+            }
+        };
+        let mut old = mock_grade(&["2019年秋季学期"]);
+        code(&mut old, &["MATH1007", "PHYS1008A"]);
+
+        // A renamed course is still the same course
+        let mut new = old.clone();
+        new.scores[0].courses[0].name = "数学分析(B2)(重修)".to_owned();
+        assert!(GradeDiff::between(&old, &new).is_empty());
+
+        // Courses of the same name but different codes are different
+        let mut new = old.clone();
+        code(&mut new, &["MATH1008"]);
+        let diff = GradeDiff::between(&old, &new);
+        assert_eq!(diff.new_courses().count(), 1);
+        assert_eq!(
+            diff.change("2019年秋季学期", &new.scores[0].courses[0]),
+            diff.courses.first()
+        );
+
+        // Falls back to names if either code is unknown
+        let mut new = old.clone();
+        code(&mut new, &[""]);
+        assert!(GradeDiff::between(&old, &new).is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+//! Suppression of repeated error emails
+//!
+//! While Jiaowu is down every fetch fails the same way, so an error email is
+//! only sent again once the error changes or the cooldown has passed.
+
+use std::time::{Duration, Instant};
+
+/// Default time during which an identical error email is not sent again
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub struct ErrorEmails {
+    cooldown: Duration,
+    /// Content of the last error email sent, and when it was sent
+    last: Option<(String, Instant)>,
+}
+
+impl ErrorEmails {
+    pub fn new(cooldown: Duration) -> Self {
+        ErrorEmails {
+            cooldown,
+            last: None,
+        }
+    }
+
+    /// Whether an email with `content` should be sent at `now`, recording it
+    /// as sent if so
+    pub fn should_send(&mut self, content: &str, now: Instant) -> bool {
+        if let Some((last, at)) = &self.last {
+            if last == content && now.saturating_duration_since(*at) < self.cooldown {
+                return false;
+            }
+        }
+        self.last = Some((content.to_owned(), now));
+        true
+    }
+
+    /// Records a successful fetch, returning whether an error email was sent
+    /// since the last one
+    pub fn recovered(&mut self) -> bool {
+        self.last.take().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_errors_sent_once() {
+        let mut emails = ErrorEmails::new(DEFAULT_COOLDOWN);
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+        let sent = (0..6)
+            .filter(|i| emails.should_send("Get grade failed: timeout", minutes(i * 10)))
+            .count();
+        assert_eq!(sent, 1);
+
+        assert!(emails.should_send("Get grade failed: 502", minutes(55)));
+        assert!(!emails.should_send("Get grade failed: 502", minutes(100)));
+        assert!(emails.should_send("Get grade failed: 502", minutes(115)));
+
+        assert!(emails.recovered());
+        assert!(!emails.recovered());
+        assert!(emails.should_send("Get grade failed: 502", minutes(120)));
+    }
+}
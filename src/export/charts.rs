@@ -0,0 +1,275 @@
+//! Charts drawn with characters for terminal output
+
+use std::f64::consts::PI;
+
+const GLYPHS: &[char] = &['◤', '◥', '◢', '◣', '#', '*', '+', 'o'];
+
+/// Width in columns of a pie chart with `radius`
+///
+/// Terminal cells are about twice as tall as they are wide, so the pie is
+/// stretched horizontally to look round.
+pub fn pie_chart_width(radius: u8) -> usize {
+    4 * radius as usize + 1
+}
+
+/// Draws a pie chart of `slices` given as (label, value), followed by a legend
+///
+/// Slices start from 12 o'clock and go clockwise. Non-positive values are
+/// ignored. Returns an empty string if nothing is left to draw.
+pub fn ascii_pie_chart(slices: &[(String, f64)], radius: u8) -> String {
+    let slices: Vec<_> = slices.iter().filter(|(_, v)| *v > 0.).collect();
+    let total: f64 = slices.iter().map(|(_, v)| v).sum();
+    if slices.is_empty() || radius == 0 {
+        return String::new();
+    }
+
+    // Upper bounds of each slice, as fractions of the whole pie
+    let bounds: Vec<f64> = slices
+        .iter()
+        .scan(0., |acc, (_, v)| {
+            *acc += v / total;
+            Some(*acc)
+        })
+        .collect();
+
+    let r = radius as i32;
+    let mut chart = String::new();
+    for y in -r..=r {
+        let mut line = String::new();
+        for x in -2 * r..=2 * r {
+            let (x, y) = (x as f64 / 2., y as f64);
+            if x * x + y * y > (r * r) as f64 {
+                line.push(' ');
+                continue;
+            }
+            let angle = x.atan2(-y);
+            let fraction = if angle < 0. { angle + 2. * PI } else { angle } / (2. * PI);
+            let i = bounds
+                .iter()
+                .position(|&b| fraction < b)
+                .unwrap_or(slices.len() - 1);
+            line.push(GLYPHS[i % GLYPHS.len()]);
+        }
+        chart += line.trim_end();
+        chart.push('\n');
+    }
+
+    chart.push('\n');
+    for (i, (label, value)) in slices.iter().enumerate() {
+        chart += &format!(
+            "{} {} {:.1} ({:.1}%)\n",
+            GLYPHS[i % GLYPHS.len()],
+            label,
+            value,
+            value / total * 100.
+        );
+    }
+    chart
+}
+
+/// Draws a horizontal bar chart of `slices` given as (label, value), with the
+/// longest bar `width` columns long
+///
+/// Non-positive values are drawn as empty bars.
+pub fn ascii_bar_chart(slices: &[(String, f64)], width: usize) -> String {
+    let total: f64 = slices.iter().map(|(_, v)| v.max(0.)).sum();
+    let max = slices.iter().map(|(_, v)| *v).fold(0., f64::max);
+
+    let mut chart = String::new();
+    for (label, value) in slices {
+        let len = if max > 0. {
+            (value.max(0.) / max * width as f64).round() as usize
+        } else {
+            0
+        };
+        let percent = if total > 0. {
+            value.max(0.) / total * 100.
+        } else {
+            0.
+        };
+        chart += &format!(
+            "{:<width$} {:.1} ({:.1}%) {}\n",
+            "█".repeat(len),
+            value,
+            percent,
+            label,
+            width = width,
+        );
+    }
+    chart
+}
+
+/// Draws `points` given as (x, y) as a line chart `width` columns wide,
+/// including the labels, with `height` rows of plot
+///
+/// The y axis spans from the minimum to the maximum y rounded outwards to one
+/// decimal, so that small changes in GPA are not exaggerated. The first and
+/// last x are labeled under the axis with `format_x`. Points need not be
+/// sorted. Returns an empty string if there are no points or no room to draw.
+pub fn ascii_line_chart(
+    points: &[(f64, f64)],
+    width: usize,
+    height: usize,
+    format_x: impl Fn(f64) -> String,
+) -> String {
+    const LABEL_WIDTH: usize = 5;
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    if points.is_empty() || width <= LABEL_WIDTH + 1 || height < 2 {
+        return String::new();
+    }
+    let columns = width - LABEL_WIDTH - 1;
+
+    let ys = points.iter().map(|p| p.1);
+    let y_min = (ys.clone().fold(f64::INFINITY, f64::min) * 10.).floor() / 10.;
+    let mut y_max = (ys.fold(f64::NEG_INFINITY, f64::max) * 10.).ceil() / 10.;
+    if y_max <= y_min {
+        y_max = y_min + 0.1;
+    }
+    let (x_min, x_max) = (points[0].0, points[points.len() - 1].0);
+
+    // Row of the line in each column
+    let rows: Vec<usize> = (0..columns)
+        .map(|column| {
+            let x = if columns > 1 {
+                x_min + (x_max - x_min) * column as f64 / (columns - 1) as f64
+            } else {
+                x_min
+            };
+            // Interpolates between the points around x
+            let next = points
+                .iter()
+                .position(|p| p.0 >= x)
+                .unwrap_or(points.len() - 1);
+            let (x1, y1) = points[next.saturating_sub(1)];
+            let (x2, y2) = points[next];
+            let y = if x2 > x1 {
+                y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+            } else {
+                y2
+            };
+            ((y_max - y) / (y_max - y_min) * (height - 1) as f64).round() as usize
+        })
+        .collect();
+
+    let mut chart = String::new();
+    for i in 0..height {
+        let label = if i == 0 {
+            format!("{:.1}", y_max)
+        } else if i == height - 1 {
+            format!("{:.1}", y_min)
+        } else if i == (height - 1) / 2 {
+            format!("{:.2}", (y_max + y_min) / 2.)
+        } else {
+            String::new()
+        };
+        let tick = if label.is_empty() { '│' } else { '┤' };
+        let row: String = rows
+            .iter()
+            .map(|&r| if r.min(height - 1) == i { '*' } else { ' ' })
+            .collect();
+        chart += &format!("{:>w$}{}{}\n", label, tick, row.trim_end(), w = LABEL_WIDTH);
+    }
+    chart += &format!("{}└{}\n", " ".repeat(LABEL_WIDTH), "─".repeat(columns));
+
+    let (first, last) = (format_x(x_min), format_x(x_max));
+    let gap = columns.saturating_sub(first.chars().count() + last.chars().count());
+    chart += &format!(
+        "{}{}{}{}\n",
+        " ".repeat(LABEL_WIDTH + 1),
+        first,
+        " ".repeat(gap),
+        last
+    );
+    chart
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slices() -> Vec<(String, f64)> {
+        vec![
+            ("2019年春季学期".to_owned(), 30.),
+            ("2019年夏季学期".to_owned(), 10.),
+            ("2019年秋季学期".to_owned(), 20.),
+        ]
+    }
+
+    #[test]
+    fn pie_chart_shape() {
+        let chart = ascii_pie_chart(&slices(), 4);
+        let lines: Vec<_> = chart.lines().collect();
+
+        // 9 lines of pie, a blank line and 3 lines of legend
+        assert_eq!(lines.len(), 13);
+        assert!(lines[..9]
+            .iter()
+            .all(|l| l.chars().count() <= pie_chart_width(4)));
+        assert_eq!(lines[4].chars().count(), pie_chart_width(4));
+        assert_eq!(lines[10], "◤ 2019年春季学期 30.0 (50.0%)");
+        assert_eq!(lines[11], "◥ 2019年夏季学期 10.0 (16.7%)");
+        assert_eq!(lines[12], "◢ 2019年秋季学期 20.0 (33.3%)");
+    }
+
+    #[test]
+    fn pie_chart_slices() {
+        let chart = ascii_pie_chart(&slices(), 6);
+        let pie: String = chart.lines().take(13).collect();
+        let count = |c| pie.chars().filter(|&x| x == c).count() as f64;
+        let (a, b, c) = (count('◤'), count('◥'), count('◢'));
+
+        // The first slice covers the right half
+        assert!(chart.lines().nth(1).unwrap().trim_start().starts_with('◢'));
+        assert!((a / (a + b + c) - 0.5).abs() < 0.05);
+        assert!(a > c && c > b);
+    }
+
+    #[test]
+    fn pie_chart_nothing_to_draw() {
+        assert_eq!(ascii_pie_chart(&[], 4), "");
+        assert_eq!(ascii_pie_chart(&[("a".to_owned(), 0.)], 4), "");
+        assert_eq!(ascii_pie_chart(&slices(), 0), "");
+    }
+
+    #[test]
+    fn bar_chart() {
+        let chart = ascii_bar_chart(&slices(), 6);
+        let lines: Vec<_> = chart.lines().collect();
+        assert_eq!(lines[0], "██████ 30.0 (50.0%) 2019年春季学期");
+        assert_eq!(lines[1], "██     10.0 (16.7%) 2019年夏季学期");
+        assert_eq!(lines[2], "████   20.0 (33.3%) 2019年秋季学期");
+    }
+
+    #[test]
+    fn line_chart_of_gpas() {
+        let days = [0., 30., 60., 90., 120.];
+        let gpas = [3.32, 3.35, 3.41, 3.40, 3.52];
+        let points: Vec<_> = days.iter().cloned().zip(gpas.iter().cloned()).collect();
+        let chart = ascii_line_chart(&points, 80, 20, |x| format!("day {}", x));
+        let lines: Vec<_> = chart.lines().collect();
+
+        // 20 lines of plot, the x axis and its labels
+        assert_eq!(lines.len(), 22);
+        assert!(lines.iter().all(|l| l.chars().count() <= 80));
+        assert!(lines[0].starts_with("  3.6┤"));
+        assert!(lines[9].starts_with(" 3.45┤"));
+        assert_eq!(lines[19], "  3.3┤");
+        // 3.32 is drawn one row above 3.3
+        assert!(lines[18].starts_with("     │*"));
+        assert!(lines[20].starts_with("     └───"));
+        assert!(lines[21].trim_start().starts_with("day 0"));
+        assert!(lines[21].ends_with("day 120"));
+        // The last point is drawn at its value
+        assert!(lines[5].ends_with('*'));
+    }
+
+    #[test]
+    fn line_chart_nothing_to_draw() {
+        assert_eq!(ascii_line_chart(&[], 80, 20, |x| x.to_string()), "");
+        assert_eq!(ascii_line_chart(&[(0., 3.5)], 5, 20, |x| x.to_string()), "");
+        let flat = ascii_line_chart(&[(0., 3.5), (1., 3.5)], 20, 5, |x| x.to_string());
+        assert!(flat.starts_with("  3.6┤\n"));
+        assert!(flat.contains("  3.5┤**************\n"));
+    }
+}
@@ -0,0 +1,398 @@
+//! Whole-grade exports in common document formats
+
+use super::format_gpa;
+use crate::{CourseGrade, Grade};
+use serde_json::json;
+use unicode_width::UnicodeWidthStr;
+
+/// Format of [`Grade::export`](../struct.Grade.html#method.export)
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Plain text with aligned columns
+    Text,
+    /// An HTML fragment with one table per semester
+    Html,
+    /// A pretty-printed JSON object
+    Json,
+    /// One row per course, with the semester in the first column
+    Csv,
+    /// Markdown with one table per semester
+    Markdown,
+    /// A LaTeX fragment with one `tabular` per semester
+    Latex,
+}
+
+pub(crate) fn export(grade: &Grade, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Text => text(grade),
+        ExportFormat::Html => html(grade),
+        ExportFormat::Json => self::json(grade),
+        ExportFormat::Csv => csv(grade),
+        ExportFormat::Markdown => markdown(grade),
+        ExportFormat::Latex => latex(grade),
+    }
+}
+
+/// Headers and rows of (course, score, credits) in a semester, followed by
+/// the time graded if any course has it
+fn table(courses: &[CourseGrade]) -> (Vec<&'static str>, Vec<Vec<String>>) {
+    let graded_at = has_graded_at(courses.iter());
+    let mut headers = HEADERS.to_vec();
+    if graded_at {
+        headers.push(GRADED_AT_HEADER);
+    }
+    let rows = courses
+        .iter()
+        .map(|c| {
+            let mut row = vec![c.name.clone(), c.score.clone(), c.credits.to_string()];
+            if graded_at {
+                row.push(c.graded_at.clone().unwrap_or_default());
+            }
+            row
+        })
+        .collect();
+    (headers, rows)
+}
+
+fn has_graded_at<'a>(mut courses: impl Iterator<Item = &'a CourseGrade>) -> bool {
+    courses.any(|c| c.graded_at.is_some())
+}
+
+const HEADERS: [&str; 3] = ["Course", "Score", "Credits"];
+
+const GRADED_AT_HEADER: &str = "Graded at";
+
+fn text(grade: &Grade) -> String {
+    let mut out = format!(
+        "Total GPA: {}\nSemester GPA: {}\nCredits earned: {:.1}\n",
+        format_gpa(grade.gpa),
+        format_gpa(grade.sem_gpa),
+        grade.credits
+    );
+    for semester in grade.scores.iter() {
+        let (headers, rows) = table(&semester.courses);
+        let mut widths: Vec<_> = headers.iter().map(|h| h.width()).collect();
+        for row in rows.iter() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.width());
+            }
+        }
+
+        out += &format!("\n{}\n", semester.name);
+        let headers: Vec<_> = headers.into_iter().map(str::to_owned).collect();
+        for row in std::iter::once(&headers).chain(rows.iter()) {
+            let line: Vec<_> = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.width())))
+                .collect();
+            out += line.join("  ").trim_end();
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub(super) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html(grade: &Grade) -> String {
+    let mut out = format!(
+        "<p>Total GPA: {}<br />\nSemester GPA: {}<br />\nCredits earned: {:.1}</p>\n",
+        format_gpa(grade.gpa),
+        format_gpa(grade.sem_gpa),
+        grade.credits
+    );
+    for semester in grade.scores.iter() {
+        out += &format!("<h4>{}</h4>\n<table>\n<tr>", escape_html(&semester.name));
+        let (headers, rows) = table(&semester.courses);
+        for header in headers.iter() {
+            out += &format!("<th>{}</th>", header);
+        }
+        out += "</tr>\n";
+        for row in rows {
+            out += "<tr>";
+            for cell in row.iter() {
+                out += &format!("<td>{}</td>", escape_html(cell));
+            }
+            out += "</tr>\n";
+        }
+        out += "</table>\n";
+    }
+    out
+}
+
+fn json(grade: &Grade) -> String {
+    let semesters: Vec<_> = grade
+        .scores
+        .iter()
+        .map(|s| {
+            let courses: Vec<_> = s
+                .courses
+                .iter()
+                .map(|c| {
+                    json!({
+                        "name": c.name,
+                        "score": c.score,
+                        "letter": c.letter,
+                        "credits": c.credits,
+                        "graded_at": c.graded_at,
+                    })
+                })
+                .collect();
+            json!({ "name": s.name, "courses": courses })
+        })
+        .collect();
+    let value = json!({
+        "gpa": grade.gpa,
+        "sem_gpa": grade.sem_gpa,
+        "credits": grade.credits,
+        "semesters": semesters,
+    });
+    serde_json::to_string_pretty(&value).unwrap_or_default()
+}
+
+/// Quotes `field` if it contains a comma, quote or line break, as in RFC 4180
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// The `graded_at` column is only added if any course has the time graded
+fn csv(grade: &Grade) -> String {
+    let graded_at = has_graded_at(grade.all_courses());
+    let mut out = "semester,course,score,letter,credits".to_owned();
+    if graded_at {
+        out += ",graded_at";
+    }
+    out += "\r\n";
+    for semester in grade.scores.iter() {
+        for course in semester.courses.iter() {
+            let mut fields = vec![
+                csv_field(&semester.name),
+                csv_field(&course.name),
+                csv_field(&course.score),
+                course.letter.unwrap_or_default().to_owned(),
+                course.credits.to_string(),
+            ];
+            if graded_at {
+                fields.push(csv_field(course.graded_at.as_deref().unwrap_or_default()));
+            }
+            out += &fields.join(",");
+            out += "\r\n";
+        }
+    }
+    out
+}
+
+/// Escapes the characters that would end a table cell or start inline code
+fn escape_markdown(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace('`', "\\`")
+}
+
+fn markdown(grade: &Grade) -> String {
+    let mut out = format!(
+        "## Grade report\n\n- Total GPA: {}\n- Semester GPA: {}\n- Credits earned: {:.1}\n",
+        format_gpa(grade.gpa),
+        format_gpa(grade.sem_gpa),
+        grade.credits
+    );
+    for semester in grade.scores.iter() {
+        out += &format!("\n### {}\n\n", escape_markdown(&semester.name));
+        let (headers, rows) = table(&semester.courses);
+        out += &format!("| {} |\n| --- | ---: | ---: |", headers.join(" | "));
+        if headers.len() > HEADERS.len() {
+            out += " --- |";
+        }
+        out.push('\n');
+        for row in rows {
+            let cells: Vec<_> = row.iter().map(|c| escape_markdown(c)).collect();
+            out += &format!("| {} |\n", cells.join(" | "));
+        }
+    }
+    out
+}
+
+fn escape_latex(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str(r"\textasciitilde{}"),
+            '^' => escaped.push_str(r"\textasciicircum{}"),
+            '\\' => escaped.push_str(r"\textbackslash{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn latex(grade: &Grade) -> String {
+    let mut out = format!(
+        "Total GPA: {} \\\\\nSemester GPA: {} \\\\\nCredits earned: {:.1}\n",
+        format_gpa(grade.gpa),
+        format_gpa(grade.sem_gpa),
+        grade.credits
+    );
+    for semester in grade.scores.iter() {
+        out += &format!("\n\\subsection*{{{}}}\n", escape_latex(&semester.name));
+        let (headers, rows) = table(&semester.courses);
+        let spec = if headers.len() > HEADERS.len() {
+            "lrrl"
+        } else {
+            "lrr"
+        };
+        out += &format!("\\begin{{tabular}}{{{}}}\n", spec);
+        out += &format!("{} \\\\\n\\hline\n", headers.join(" & "));
+        for row in rows {
+            let cells: Vec<_> = row.iter().map(|c| escape_latex(c)).collect();
+            out += &format!("{} \\\\\n", cells.join(" & "));
+        }
+        out += "\\end{tabular}\n";
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+    use crate::{CourseGrade, Grade, Semester};
+
+    #[test]
+    fn text_aligns_wide_characters() {
+        let text = mock_grade(&["2019年春季学期"]).export(ExportFormat::Text);
+        assert_eq!(
+            text,
+            "\
+Total GPA: 3.52
+Semester GPA: 3.30
+Credits earned: 18.5
+
+2019年春季学期
+Course        Score  Credits
+数学分析(B1)  92     6
+线性代数(B1)  85     4
+"
+        );
+    }
+
+    #[test]
+    fn html_escapes() {
+        let mut grade = mock_grade(&["2019年夏季学期"]);
+        grade.scores = vec![Semester::new(
+            "2019年夏季学期",
+            vec![CourseGrade::new("Rust & C", "<90>", 3.0)],
+        )];
+        let html = grade.export(ExportFormat::Html);
+        assert!(html.contains("<h4>2019年夏季学期</h4>"));
+        assert!(html.contains("<tr><td>Rust &amp; C</td><td>&lt;90&gt;</td><td>3</td></tr>"));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let json = mock_grade(&["2019年秋季学期"]).export(ExportFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["gpa"], 3.52);
+        assert_eq!(value["semesters"][0]["name"], "2019年秋季学期");
+        assert_eq!(value["semesters"][0]["courses"][1]["score"], "优秀");
+        assert_eq!(value["semesters"][0]["courses"][1]["letter"], "A");
+    }
+
+    #[test]
+    fn csv_quotes() {
+        let mut grade = mock_grade(&["2019年夏季学期"]);
+        grade.scores[0]
+            .courses
+            .push(CourseGrade::new("Say \"hi\", world", "60", 1.0));
+        assert_eq!(
+            grade.export(ExportFormat::Csv),
+            "semester,course,score,letter,credits\r\n\
+             2019年夏季学期,军事技能,通过,,1\r\n\
+             2019年夏季学期,\"Say \"\"hi\"\", world\",60,D,1\r\n"
+        );
+    }
+
+    #[test]
+    fn graded_at_column() {
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        assert!(!grade.export(ExportFormat::Text).contains("Graded at"));
+        assert!(!grade.export(ExportFormat::Csv).contains("graded_at"));
+
+        grade.scores[0].courses[0].graded_at = Some("2020-01-15 10:23:45".to_owned());
+        let text = grade.export(ExportFormat::Text);
+        assert!(text.contains("Credits  Graded at\n"), "{}", text);
+        assert!(
+            text.contains("  6        2020-01-15 10:23:45\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("  1.5\n"), "{}", text);
+        assert!(grade.export(ExportFormat::Csv).ends_with(
+            ",6,2020-01-15 10:23:45\r\n2019年秋季学期,大学物理-基础实验A,优秀,A,1.5,\r\n"
+        ));
+        let markdown = grade.export(ExportFormat::Markdown);
+        assert!(markdown.contains("| Graded at |\n| --- | ---: | ---: | --- |\n"));
+        assert!(markdown.contains("| 大学物理-基础实验A | 优秀 | 1.5 |  |\n"));
+        assert!(grade
+            .export(ExportFormat::Latex)
+            .contains("\\begin{tabular}{lrrl}\n"));
+        let json: serde_json::Value =
+            serde_json::from_str(&grade.export(ExportFormat::Json)).unwrap();
+        assert_eq!(
+            json["semesters"][0]["courses"][0]["graded_at"],
+            "2020-01-15 10:23:45"
+        );
+    }
+
+    #[test]
+    fn markdown_golden() {
+        let grade = Grade::builder()
+            .gpa(3.52)
+            .sem_gpa(3.3)
+            .credits(10.0)
+            .add_semester(
+                "2019年秋季学期",
+                vec![
+                    CourseGrade::new("数学分析(B2)", "78", 6.0),
+                    CourseGrade::new("Rust | C++", "A", 3.0),
+                    CourseGrade::new("`unsafe` 编程", "通过", 1.0),
+                ],
+            )
+            .build();
+        assert_eq!(
+            grade.to_markdown(),
+            include_str!("../../tests/golden/report.md")
+        );
+    }
+
+    #[test]
+    fn markdown_and_latex_tables() {
+        let grade = mock_grade(&["2019年秋季学期"]);
+        let markdown = grade.export(ExportFormat::Markdown);
+        assert!(markdown.contains("### 2019年秋季学期\n\n| Course | Score | Credits |\n"));
+        assert!(markdown.contains("| 大学物理-基础实验A | 优秀 | 1.5 |\n"));
+
+        let mut grade = grade;
+        grade.scores[0]
+            .courses
+            .push(CourseGrade::new("C# & 100%_", "90", 2.0));
+        let latex = grade.export(ExportFormat::Latex);
+        assert!(latex.contains("\\subsection*{2019年秋季学期}\n\\begin{tabular}{lrr}\n"));
+        assert!(latex.contains("C\\# \\& 100\\%\\_ & 90 & 2 \\\\\n"));
+        assert!(latex.ends_with("\\end{tabular}\n"));
+    }
+}
@@ -0,0 +1,75 @@
+//! Formatters of the grade reports sent to users
+
+use super::formats::escape_html;
+use crate::{notify, ExportFormat, Grade, GradeDiff};
+
+/// Formats reports of a grade, showing the changes in `diff` if given
+///
+/// Implement it to replace the reports sent by email with your own.
+pub trait GradeFormatter: Send + Sync {
+    fn format_text(&self, grade: &Grade, diff: Option<&GradeDiff>) -> String;
+
+    fn format_html(&self, grade: &Grade, diff: Option<&GradeDiff>) -> String;
+}
+
+/// The whole grade as [`ExportFormat::Text`](enum.ExportFormat.html) and
+/// [`ExportFormat::Html`](enum.ExportFormat.html), without the changes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFormatter;
+
+impl GradeFormatter for DefaultFormatter {
+    fn format_text(&self, grade: &Grade, _diff: Option<&GradeDiff>) -> String {
+        grade.export(ExportFormat::Text)
+    }
+
+    fn format_html(&self, grade: &Grade, _diff: Option<&GradeDiff>) -> String {
+        grade.export(ExportFormat::Html)
+    }
+}
+
+/// Only the GPAs and the changed courses, see
+/// [`notify::summary`](../notify/fn.summary.html)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompactFormatter;
+
+impl GradeFormatter for CompactFormatter {
+    fn format_text(&self, grade: &Grade, diff: Option<&GradeDiff>) -> String {
+        notify::summary(grade, diff)
+    }
+
+    fn format_html(&self, grade: &Grade, diff: Option<&GradeDiff>) -> String {
+        let lines: Vec<_> = notify::summary(grade, diff)
+            .lines()
+            .map(escape_html)
+            .collect();
+        format!("<p>{}</p>\n", lines.join("<br />\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+
+    #[test]
+    fn compact_lists_changes() {
+        let old = mock_grade(&["2019年春季学期"]);
+        let mut new = old.clone();
+        new.scores[0].courses[1].score = "<88>".to_owned();
+        let diff = GradeDiff::between(&old, &new);
+
+        let formatters: [Box<dyn GradeFormatter>; 2] =
+            [Box::new(DefaultFormatter), Box::new(CompactFormatter)];
+        assert!(formatters[0]
+            .format_text(&new, Some(&diff))
+            .contains("数学分析(B1)"));
+        assert_eq!(
+            formatters[1].format_text(&new, Some(&diff)),
+            "Total GPA: 3.52, Semester GPA: 3.30\n线性代数(B1): * 85 → <88>"
+        );
+        assert_eq!(
+            formatters[1].format_html(&new, Some(&diff)),
+            "<p>Total GPA: 3.52, Semester GPA: 3.30<br />\n线性代数(B1): * 85 → &lt;88&gt;</p>\n"
+        );
+    }
+}
@@ -0,0 +1,23 @@
+//! Presentations of a [Grade](../struct.Grade.html) other than emails
+
+pub mod charts;
+mod formats;
+mod formatter;
+pub mod report;
+pub mod table;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+pub(crate) use formats::export;
+pub use formats::ExportFormat;
+pub use formatter::{CompactFormatter, DefaultFormatter, GradeFormatter};
+#[cfg(feature = "xlsx")]
+pub use xlsx::grade_to_xlsx;
+
+/// `gpa` with two decimals, or "N/A" if it is not published yet
+pub fn format_gpa(gpa: Option<f64>) -> String {
+    match gpa {
+        Some(gpa) => format!("{:.2}", gpa),
+        None => "N/A".to_owned(),
+    }
+}
@@ -0,0 +1,813 @@
+//! The text and HTML reports of a grade, as sent by email
+//!
+//! The functions here are pure: the same grade always gives the same report.
+
+use super::table::TextTable;
+use crate::diff::CourseChange;
+use crate::{
+    calculate_gpa, semester_stats, CourseGrade, CourseStatus, Grade, GradeDiff, Semester,
+    SemesterStats,
+};
+use itertools::Itertools;
+
+const TABLE_STYLE: &str = "border-collapse: collapse; border: 1px solid #999;";
+const CELL_STYLE: &str = "border: 1px solid #999; padding: 4px 8px;";
+const STRIPE_STYLE: &str = "background-color: #f2f2f2;";
+const CHANGED_STYLE: &str = "background-color: #fff3b0;";
+const FAILED_STYLE: &str = " color: #c00; font-weight: bold;";
+
+/// Language of the labels in a report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    Chinese,
+    English,
+}
+
+impl Language {
+    /// Headers of the course, score, letter grade and credits columns
+    pub fn headers(self) -> [&'static str; 4] {
+        match self {
+            Language::Chinese => ["课程", "成绩", "等级", "学分"],
+            Language::English => ["Course", "Score", "Letter", "Credits"],
+        }
+    }
+
+    fn code_header(self) -> &'static str {
+        match self {
+            Language::Chinese => "课程代码",
+            Language::English => "Code",
+        }
+    }
+
+    fn graded_at_header(self) -> &'static str {
+        match self {
+            Language::Chinese => "录入时间",
+            Language::English => "Graded at",
+        }
+    }
+
+    /// Title of the table of pass/fail courses
+    fn pass_fail_title(self) -> &'static str {
+        match self {
+            Language::Chinese => "通过制课程",
+            Language::English => "Pass/Fail Courses",
+        }
+    }
+
+    fn format_stats(self, stats: &SemesterStats) -> String {
+        let labels = match self {
+            Language::Chinese => ["平均", "中位数", "标准差", "最低", "最高"],
+            Language::English => ["Mean", "Median", "SD", "Min", "Max"],
+        };
+        format!(
+            "{} {:.2} / {} {:.2} / {} {:.2} / {} {} / {} {}",
+            labels[0],
+            stats.mean,
+            labels[1],
+            stats.median,
+            labels[2],
+            stats.std_dev,
+            labels[3],
+            stats.min,
+            labels[4],
+            stats.max,
+        )
+    }
+
+    /// Summary of a semester, e.g. "2 courses, GPA 3.85"
+    fn format_summary(self, courses: usize, gpa: &str) -> String {
+        match self {
+            Language::Chinese => format!("{} 门课程，GPA {}", courses, gpa),
+            Language::English if courses == 1 => format!("1 course, GPA {}", gpa),
+            Language::English => format!("{} courses, GPA {}", courses, gpa),
+        }
+    }
+
+    /// Translates semester names like "2019年秋季学期" to "2019 Fall"
+    pub fn semester_name(self, name: &str) -> String {
+        if self == Language::Chinese {
+            return name.to_owned();
+        }
+        let translated = Some(name).filter(|s| s.ends_with("季学期")).and_then(|s| {
+            let mut chars = s[..s.len() - "季学期".len()].chars();
+            let season = match chars.next_back()? {
+                '春' => "Spring",
+                '夏' => "Summer",
+                '秋' => "Fall",
+                _ => return None,
+            };
+            let year = chars.as_str().trim_end_matches('年');
+            Some(format!("{} {}", year, season))
+        });
+        translated.unwrap_or_else(|| name.to_owned())
+    }
+}
+
+/// Formats the GPAs of `grade`, e.g. "3.72 → 3.85 (+0.13)" if they changed
+/// in `diff`, or "N/A → 3.85" if they were just published
+fn format_gpas(grade: &Grade, diff: Option<&GradeDiff>) -> (String, String) {
+    fn format_gpa(new: Option<f64>, change: Option<f64>) -> String {
+        match (new, change) {
+            (Some(new), Some(change)) if change.abs() >= 0.005 => {
+                format!("{:.2} → {:.2} ({:+.2})", new - change, new, change)
+            }
+            (Some(new), None) => format!("N/A → {:.2}", new),
+            (new, _) => super::format_gpa(new),
+        }
+    }
+
+    match diff {
+        Some(diff) => (
+            format_gpa(grade.gpa, diff.gpa_change),
+            format_gpa(grade.sem_gpa, diff.sem_gpa_change),
+        ),
+        None => (
+            super::format_gpa(grade.gpa),
+            super::format_gpa(grade.sem_gpa),
+        ),
+    }
+}
+
+/// The summary line of `semester` in `grade`. A semester alone in `grade`
+/// has the semester GPA of Jiaowu, `sem_gpa`, instead of the computed one.
+fn semester_summary(grade: &Grade, semester: &Semester, sem_gpa: &str, lang: Language) -> String {
+    let gpa = if grade.scores.len() == 1 {
+        sem_gpa.to_owned()
+    } else {
+        super::format_gpa(calculate_gpa(&semester.courses))
+    };
+    lang.format_summary(semester.courses.len(), &gpa)
+}
+
+/// Formats `grade` as an HTML document with a section for each of
+/// `languages`, highlighting the courses changed in `diff` if given
+///
+/// Every semester has a summary line, and with a single semester the
+/// semester GPA is only in its summary.
+pub fn format_grade_html(
+    grade: &Grade,
+    diff: Option<&GradeDiff>,
+    languages: &[Language],
+) -> String {
+    let (gpa, sem_gpa) = format_gpas(grade, diff);
+    let rank = grade
+        .rank
+        .as_ref()
+        .map(|rank| format!("\n        Rank: {}<br />", rank))
+        .unwrap_or_default();
+    let other_gpas: String = grade
+        .other_gpas()
+        .into_iter()
+        .map(|(name, gpa)| format!("\n        GPA ({}): {:.2}<br />", escape_html(name), gpa))
+        .collect();
+    let sem_gpa_line = if grade.scores.len() == 1 {
+        String::new()
+    } else {
+        format!("\n        Semester GPA: {}<br />", sem_gpa)
+    };
+    let preface = format!(
+        "<p>Total GPA: {}<br />{}
+        Credits earned: {:.1}<br />{}{}</p>",
+        gpa, sem_gpa_line, grade.credits, rank, other_gpas,
+    );
+
+    let sections = languages
+        .iter()
+        .map(|&lang| {
+            let grades = grade
+                .scores
+                .iter()
+                .map(|semester| {
+                    let summary = semester_summary(grade, semester, &sem_gpa, lang);
+                    format_semester_html(semester, diff, lang, &summary)
+                })
+                .join("");
+            preface.clone() + &grades
+        })
+        .join("\n<hr />\n");
+
+    html_document(&sections)
+}
+
+/// Wraps `body` in a complete HTML document
+///
+/// All the styles are inlined since many mail clients strip `<style>`.
+fn html_document(body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Grade Report</title>
+</head>
+<body style="font-family: sans-serif;">
+{}
+</body>
+</html>"#,
+        body
+    )
+}
+
+/// Formats a semester as a table below its name and `summary`,
+/// highlighting the courses in `diff`
+///
+/// Pass/fail courses are listed in a separate table below the graded ones.
+/// The course code and the time graded columns are left out if no course in
+/// `semester` has them.
+fn format_semester_html(
+    semester: &Semester,
+    diff: Option<&GradeDiff>,
+    lang: Language,
+    summary: &str,
+) -> String {
+    let headers = lang.headers();
+    let has_codes = has_codes(semester);
+    let has_graded_at = has_graded_at(semester);
+    let rows = |courses: &[&CourseGrade]| {
+        courses
+            .iter()
+            .enumerate()
+            .map(|(i, course)| {
+                let change = diff.and_then(|d| d.change(&semester.name, course));
+                let score = match change {
+                    Some(CourseChange { old: Some(old), .. }) => format!(
+                        "<s>{}</s> <b>{}</b>",
+                        escape_html(&old.score),
+                        escape_html(&course.score)
+                    ),
+                    Some(_) => format!("<b>{}</b>", escape_html(&course.score)),
+                    None => escape_html(&course.score),
+                };
+                let failed = if course.status == CourseStatus::Failed {
+                    FAILED_STYLE
+                } else {
+                    ""
+                };
+                let row = if change.is_some() {
+                    CHANGED_STYLE
+                } else if i % 2 == 1 {
+                    STRIPE_STYLE
+                } else {
+                    ""
+                };
+                let code = if has_codes {
+                    format!(
+                        r#"<td style="{}">{}</td>"#,
+                        CELL_STYLE,
+                        escape_html(&course.code)
+                    )
+                } else {
+                    String::new()
+                };
+                let graded_at = if has_graded_at {
+                    format!(
+                        r#"<td style="{}">{}</td>"#,
+                        CELL_STYLE,
+                        escape_html(course.graded_at.as_deref().unwrap_or_default())
+                    )
+                } else {
+                    String::new()
+                };
+                format!(
+                    r#"<tr style="{row}">
+                {code}<td style="{cell}">{}</td>
+                <td style="{cell} text-align: center;{failed}">{}</td>
+                <td style="{cell} text-align: center;">{}</td>
+                <td style="{cell} text-align: right;">{}</td>{graded_at}
+                </tr>"#,
+                    escape_html(&course.name),
+                    score,
+                    course.letter.unwrap_or_default(),
+                    course.credits,
+                    row = row,
+                    code = code,
+                    graded_at = graded_at,
+                    cell = CELL_STYLE,
+                    failed = failed,
+                )
+            })
+            .join("")
+    };
+    let table = |content: String| {
+        format!(
+            r#"
+        <table style="{table}">
+          <tr style="{row}">
+            {code}<th style="{cell}">{}</th>
+            <th style="{cell}">{}</th>
+            <th style="{cell}">{}</th>
+            <th style="{cell}">{}</th>{graded_at}
+          </tr>
+          {}
+        </table>"#,
+            headers[0],
+            headers[1],
+            headers[2],
+            headers[3],
+            content,
+            code = if has_codes {
+                format!(r#"<th style="{}">{}</th>"#, CELL_STYLE, lang.code_header())
+            } else {
+                String::new()
+            },
+            graded_at = if has_graded_at {
+                format!(
+                    r#"<th style="{}">{}</th>"#,
+                    CELL_STYLE,
+                    lang.graded_at_header()
+                )
+            } else {
+                String::new()
+            },
+            table = TABLE_STYLE,
+            row = STRIPE_STYLE,
+            cell = CELL_STYLE,
+        )
+    };
+
+    let (pass_fail, graded): (Vec<_>, Vec<_>) =
+        semester.courses.iter().partition(|c| c.is_pass_fail());
+    let mut html = format!(
+        "<h4>{}</h4>\n        <p>{}</p>",
+        escape_html(&lang.semester_name(&semester.name)),
+        escape_html(summary)
+    );
+    if !graded.is_empty() || pass_fail.is_empty() {
+        let stats = semester_stats(&semester.courses)
+            .map(|stats| {
+                format!(
+                    r#"<tr style="{row}"><td style="{cell}" colspan="{}">{}</td></tr>"#,
+                    4 + has_codes as usize + has_graded_at as usize,
+                    lang.format_stats(&stats),
+                    row = STRIPE_STYLE,
+                    cell = CELL_STYLE,
+                )
+            })
+            .unwrap_or_default();
+        html += &table(rows(&graded) + &stats);
+    }
+    if !pass_fail.is_empty() {
+        html += &format!("\n        <h5>{}</h5>", lang.pass_fail_title());
+        html += &table(rows(&pass_fail));
+    }
+    html
+}
+
+/// Whether any course in `semester` has a course code
+fn has_codes(semester: &Semester) -> bool {
+    semester.courses.iter().any(|c| !c.code.is_empty())
+}
+
+/// Whether any course in `semester` has the time it was graded
+fn has_graded_at(semester: &Semester) -> bool {
+    semester.courses.iter().any(|c| c.graded_at.is_some())
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats `grade` as plain text tables with a section for each of
+/// `languages`, marking the courses changed in `diff` if given
+///
+/// Course names wider than `max_name_width` are truncated, and the letter
+/// grades are left out unless `show_letters`. Every semester has a summary
+/// line, and with a single semester the semester GPA is only in its summary.
+pub fn format_grade_text(
+    grade: &Grade,
+    diff: Option<&GradeDiff>,
+    languages: &[Language],
+    show_letters: bool,
+    max_name_width: usize,
+) -> String {
+    languages
+        .iter()
+        .map(|&lang| format_grade_text_in(grade, diff, lang, show_letters, max_name_width))
+        .join(&format!("\n{}\n\n", "-".repeat(40)))
+}
+
+fn format_grade_text_in(
+    grade: &Grade,
+    diff: Option<&GradeDiff>,
+    lang: Language,
+    show_letters: bool,
+    max_name_width: usize,
+) -> String {
+    let headers = lang.headers();
+    let (gpa, sem_gpa) = format_gpas(grade, diff);
+    let mut grades = String::new();
+    for semester in grade.scores.iter() {
+        // Drops the letter grade column unless `show_letters`, and adds the
+        // course code and the time graded columns if any course has them
+        let has_codes = has_codes(semester);
+        let has_graded_at = has_graded_at(semester);
+        let columns = |code: &str, row: [String; 4], graded_at: &str| -> Vec<String> {
+            let code = Some(code.to_owned()).filter(|_| has_codes);
+            let graded_at = Some(graded_at.to_owned()).filter(|_| has_graded_at);
+            code.into_iter()
+                .chain(
+                    IntoIterator::into_iter(row)
+                        .enumerate()
+                        .filter(|&(i, _)| show_letters || i != 2)
+                        .map(|(_, c)| c),
+                )
+                .chain(graded_at)
+                .collect()
+        };
+
+        let name_column = if has_codes { 1 } else { 0 };
+        let table = |courses: &[&CourseGrade]| {
+            let mut table = TextTable::new().max_width(name_column, max_name_width);
+            table.add_row(columns(
+                lang.code_header(),
+                headers.map(str::to_owned),
+                lang.graded_at_header(),
+            ));
+            for course in courses {
+                // Marks new courses with `NEW`, changed ones with `*` and
+                // failed ones with `⚠️`
+                let mut name = match course.status {
+                    CourseStatus::Failed => format!("⚠️ {}", course.name),
+                    _ => course.name.clone(),
+                };
+                match diff.and_then(|d| d.change(&semester.name, course)) {
+                    Some(CourseChange { old: Some(_), .. }) => name.insert_str(0, "* "),
+                    Some(_) => name.insert_str(0, "NEW "),
+                    None => {}
+                }
+                table.add_row(columns(
+                    &course.code,
+                    [
+                        name,
+                        course.score.clone(),
+                        course.letter.unwrap_or_default().to_owned(),
+                        course.credits.to_string(),
+                    ],
+                    course.graded_at.as_deref().unwrap_or_default(),
+                ));
+            }
+            table
+        };
+
+        // Pass/fail courses are listed in a separate table below
+        let (pass_fail, graded): (Vec<_>, Vec<_>) =
+            semester.courses.iter().partition(|c| c.is_pass_fail());
+        grades += &format!(
+            "{}\n{}\n",
+            lang.semester_name(&semester.name),
+            semester_summary(grade, semester, &sem_gpa, lang)
+        );
+        if !graded.is_empty() || pass_fail.is_empty() {
+            let mut table = table(&graded);
+            if let Some(stats) = semester_stats(&semester.courses) {
+                table.add_span(lang.format_stats(&stats));
+            }
+            grades += &table.to_string();
+        }
+        if !pass_fail.is_empty() {
+            grades += &format!("{}\n{}", lang.pass_fail_title(), table(&pass_fail));
+        }
+    }
+
+    let sem_gpa_line = if grade.scores.len() == 1 {
+        String::new()
+    } else {
+        format!("Semester GPA: {}\n", sem_gpa)
+    };
+    let rank = grade
+        .rank
+        .as_ref()
+        .map(|rank| format!("Rank: {}\n", rank))
+        .unwrap_or_default();
+    let other_gpas: String = grade
+        .other_gpas()
+        .into_iter()
+        .map(|(name, gpa)| format!("GPA ({}): {:.2}\n", name, gpa))
+        .collect();
+    format!(
+        "\
+Total GPA: {}
+{}Credits earned: {:.1}
+{}{}
+{}",
+        gpa, sem_gpa_line, grade.credits, rank, other_gpas, grades,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+    use crate::Rank;
+
+    #[test]
+    fn grade_golden() {
+        // CJK names, fractional credits and a semester of pass/fail courses
+        let grade = mock_grade(&[]);
+        assert_eq!(
+            grade.to_text(),
+            include_str!("../../tests/golden/grade.txt")
+        );
+        assert_eq!(
+            grade.to_html(),
+            include_str!("../../tests/golden/grade.html")
+        );
+    }
+
+    #[test]
+    fn html_is_self_contained_and_escaped() {
+        let semester = Semester::new(
+            "2019年秋季学期",
+            vec![CourseGrade::new("Rust & C", "<90>", 3.0)],
+        );
+        let html = html_document(&format_semester_html(
+            &semester,
+            None,
+            Language::Chinese,
+            "1 门课程",
+        ));
+
+        assert!(html.contains(r#"<meta charset="utf-8">"#));
+        assert!(html.contains("Rust &amp; C"));
+        assert!(html.contains("&lt;90&gt;"));
+        assert!(!html.contains("Rust & C"));
+    }
+
+    #[test]
+    fn html_contains_every_course() {
+        let grade = mock_grade(&["2019年春季学期", "2019年秋季学期"]);
+        let html = format_grade_html(&grade, None, &[Language::Chinese]);
+
+        assert!(html.contains("Semester GPA: 3.30"));
+        for semester in grade.scores.iter() {
+            assert!(html.contains(&format!("<h4>{}</h4>", semester.name)));
+        }
+        for course in grade.all_courses() {
+            assert!(html.contains(course.name.as_str()));
+            assert!(html.contains(course.score.as_str()));
+        }
+    }
+
+    #[test]
+    fn bilingual_html() {
+        let grade = mock_grade(&["2019年秋季学期"]);
+        let html = format_grade_html(&grade, None, &[Language::Chinese, Language::English]);
+
+        let (zh, en) = html.split_at(html.find("<hr />").unwrap());
+        assert!(zh.contains("<h4>2019年秋季学期</h4>"));
+        assert!(zh.contains(">课程</th>"));
+        assert!(en.contains("<h4>2019 Fall</h4>"));
+        assert!(en.contains(">Course</th>"));
+    }
+
+    #[test]
+    fn single_semester_summary() {
+        let single = mock_grade(&["2019年秋季学期"]);
+        let text = format_grade_text(&single, None, &[Language::English], false, 40);
+        assert!(!text.contains("Semester GPA"), "{}", text);
+        // The semester GPA of Jiaowu, not the computed 3.20
+        assert!(
+            text.contains("2019 Fall\n2 courses, GPA 3.30\n"),
+            "{}",
+            text
+        );
+        let html = format_grade_html(&single, None, &[Language::Chinese]);
+        assert!(!html.contains("Semester GPA"));
+        assert!(html.contains("<h4>2019年秋季学期</h4>\n        <p>2 门课程，GPA 3.30</p>"));
+
+        let multi = mock_grade(&["2019年夏季学期", "2019年秋季学期"]);
+        let text = format_grade_text(&multi, None, &[Language::English], false, 40);
+        assert!(text.contains("Semester GPA: 3.30\n"), "{}", text);
+        assert!(
+            text.contains("2019 Summer\n1 course, GPA N/A\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("2019 Fall\n2 courses, GPA 3.20\n"),
+            "{}",
+            text
+        );
+        let html = format_grade_html(&multi, None, &[Language::Chinese]);
+        assert!(html.contains("Semester GPA: 3.30"));
+        assert!(html.contains("<p>2 门课程，GPA 3.20</p>"));
+    }
+
+    #[test]
+    fn html_golden() {
+        let old = mock_grade(&["2019年春季学期", "2019年秋季学期"]);
+        assert_eq!(
+            format_grade_html(&old, None, &[Language::Chinese]),
+            include_str!("../../tests/golden/report.html")
+        );
+
+        let mut new = old.clone();
+        new.gpa = new.gpa.map(|gpa| gpa + 0.05);
+        new.scores[0].courses[1].score = "88".to_owned();
+        new.scores[1]
+            .courses
+            .push(CourseGrade::new("复变函数(B)", "90", 3.0));
+        let diff = GradeDiff::between(&old, &new);
+        assert_eq!(
+            format_grade_html(&new, Some(&diff), &[Language::Chinese]),
+            include_str!("../../tests/golden/report_changed.html")
+        );
+    }
+
+    #[test]
+    fn text_report_aligns() {
+        use unicode_width::UnicodeWidthStr;
+
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        grade.scores[0].courses.push(CourseGrade::new(
+            "Introduction to Computing Systems",
+            "A",
+            4.0,
+        ));
+        let text = format_grade_text(&grade, None, &[Language::Chinese], true, 20);
+        let widths: Vec<_> = text
+            .lines()
+            .filter(|l| l.starts_with(&['|', '+'][..]))
+            .map(UnicodeWidthStr::width)
+            .collect();
+        assert!(widths.len() > 4);
+        assert!(widths.iter().all(|&w| w == widths[0]), "{}", text);
+        assert!(text.contains("| Introduction to Com… |"));
+    }
+
+    #[test]
+    fn failed_courses_highlighted() {
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        grade.scores[0]
+            .courses
+            .push(CourseGrade::new("复变函数(B)", "55", 3.0));
+        let text = format_grade_text(&grade, None, &[Language::Chinese], false, 40);
+        assert!(text.contains("| ⚠️ 复变函数(B) "));
+        assert!(!text.contains("⚠️ 数学分析"));
+        let html = format_semester_html(&grade.scores[0], None, Language::Chinese, "");
+        assert_eq!(html.matches(FAILED_STYLE).count(), 1);
+        assert!(html.contains(&format!("text-align: center;{}\">55</td>", FAILED_STYLE)));
+    }
+
+    #[test]
+    fn course_code_column() {
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        let html = format_semester_html(&grade.scores[0], None, Language::English, "");
+        assert!(!html.contains(">Code</th>"));
+        assert!(html.contains(r#"colspan="4""#));
+
+        grade.scores[0].courses[0].code = "MATH1007".to_owned();
+        let html = format_semester_html(&grade.scores[0], None, Language::English, "");
+        assert!(html.contains(">Code</th>"));
+        assert!(html.contains(">MATH1007</td>"));
+        assert!(html.contains(r#"colspan="5""#));
+
+        // Long names are still truncated with the code column in front
+        grade.scores[0].courses[0].name = "Mathematical Analysis B2".to_owned();
+        let text = format_grade_text(&grade, None, &[Language::English], false, 10);
+        assert!(
+            text.contains("| Code     | Course     | Score |"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("| MATH1007 | Mathemati… | 78    |"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn rank_in_summary() {
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        let text = format_grade_text(&grade, None, &[Language::English], false, 40);
+        assert!(
+            text.contains("Credits earned: 18.5\n\n2019 Fall\n"),
+            "{}",
+            text
+        );
+        assert!(!text.contains("Rank"));
+
+        grade.rank = Some(Rank::new(12, 143, "专业"));
+        let text = format_grade_text(&grade, None, &[Language::English], false, 40);
+        assert!(
+            text.contains("Credits earned: 18.5\nRank: 12/143\n\n2019 Fall\n"),
+            "{}",
+            text
+        );
+        let html = format_grade_html(&grade, None, &[Language::English]);
+        assert!(html.contains("Rank: 12/143<br /></p>"));
+
+        grade.gpa_variants.insert("bestGpa".to_owned(), 3.6);
+        let text = format_grade_text(&grade, None, &[Language::English], false, 40);
+        assert!(
+            text.contains("Rank: 12/143\nGPA (bestGpa): 3.60\n\n2019 Fall\n"),
+            "{}",
+            text
+        );
+        let html = format_grade_html(&grade, None, &[Language::English]);
+        assert!(html.contains("GPA (bestGpa): 3.60<br /></p>"));
+    }
+
+    #[test]
+    fn graded_at_column() {
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        grade.scores[0].courses[0].graded_at = Some("2020-01-15 10:23".to_owned());
+        let html = format_semester_html(&grade.scores[0], None, Language::English, "");
+        assert!(html.contains(">Graded at</th>"));
+        assert!(html.contains(">2020-01-15 10:23</td>"));
+        assert!(html.contains(r#"colspan="5""#));
+
+        let text = format_grade_text(&grade, None, &[Language::Chinese], false, 40);
+        assert!(text.contains("| 学分 | 录入时间 "), "{}", text);
+        assert!(text.contains("| 6    | 2020-01-15 10:23 "), "{}", text);
+    }
+
+    #[test]
+    fn pass_fail_courses_separate() {
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        grade.scores[0]
+            .courses
+            .push(CourseGrade::new("新生研讨课", "通过", 1.0));
+        let text = format_grade_text(&grade, None, &[Language::English], false, 40);
+        let (graded, pass_fail) = text.split_once("Pass/Fail Courses\n").unwrap();
+        assert!(graded.contains("| 数学分析(B2)"), "{}", text);
+        assert!(!graded.contains("新生研讨课"), "{}", text);
+        assert!(
+            pass_fail.contains("| 新生研讨课 | 通过  | 1       |"),
+            "{}",
+            text
+        );
+        assert!(!pass_fail.contains("数学分析(B2)"), "{}", text);
+
+        let html = format_semester_html(&grade.scores[0], None, Language::Chinese, "");
+        let (graded, pass_fail) = html.split_once("<h5>通过制课程</h5>").unwrap();
+        assert!(graded.contains(">数学分析(B2)</td>"));
+        assert!(pass_fail.contains(">新生研讨课</td>"));
+        assert!(!pass_fail.contains(">数学分析(B2)</td>"));
+
+        // Semesters of pass/fail courses only have no empty graded table
+        let html = format_semester_html(
+            &mock_grade(&["2019年夏季学期"]).scores[0],
+            None,
+            Language::Chinese,
+            "",
+        );
+        assert_eq!(html.matches("<table").count(), 1);
+    }
+
+    #[test]
+    fn english_semester_name() {
+        let en = Language::English;
+        assert_eq!(en.semester_name("2020年春季学期"), "2020 Spring");
+        assert_eq!(en.semester_name("2020年夏季学期"), "2020 Summer");
+        assert_eq!(en.semester_name("研究生学期"), "研究生学期");
+    }
+
+    #[test]
+    fn html_stats_row() {
+        let grade = mock_grade(&["2019年春季学期", "2019年夏季学期"]);
+        let html = format_grade_html(&grade, None, &[Language::Chinese]);
+
+        assert!(html.contains(
+            r#"colspan="4">平均 88.50 / 中位数 88.50 / 标准差 3.50 / 最低 85 / 最高 92<"#
+        ));
+        assert_eq!(html.matches("colspan").count(), 1);
+    }
+
+    #[test]
+    fn gpa_change() {
+        let old = mock_grade(&["2019年秋季学期"]);
+        let mut new = old.clone();
+        new.gpa = Some(3.65);
+
+        assert_eq!(
+            format_gpas(&new, Some(&GradeDiff::between(&old, &new))),
+            ("3.52 → 3.65 (+0.13)".to_owned(), "3.30".to_owned())
+        );
+        assert_eq!(
+            format_gpas(&old, Some(&GradeDiff::between(&new, &old))).0,
+            "3.65 → 3.52 (-0.13)".to_owned()
+        );
+        assert_eq!(format_gpas(&new, None).0, "3.65");
+
+        let mut unpublished = old.clone();
+        unpublished.gpa = None;
+        let diff = GradeDiff::between(&unpublished, &old);
+        assert_eq!(format_gpas(&old, Some(&diff)).0, "N/A → 3.52");
+        assert_eq!(format_gpas(&unpublished, None).0, "N/A");
+        let text = format_grade_text(&unpublished, None, &[Language::English], false, 40);
+        assert!(text.starts_with("Total GPA: N/A\n"), "{}", text);
+    }
+}
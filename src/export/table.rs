@@ -0,0 +1,195 @@
+//! Plain text tables aligned by display width
+//!
+//! CJK characters take two columns in monospace fonts, so padding by the
+//! number of characters, as prettytable does, leaves the columns zigzagging.
+
+use std::fmt;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+enum Row {
+    Cells(Vec<String>),
+    /// A single cell spanning all the columns
+    Span(String),
+}
+
+#[derive(Default)]
+pub struct TextTable {
+    rows: Vec<Row>,
+    max_widths: Vec<Option<usize>>,
+}
+
+impl TextTable {
+    pub fn new() -> Self {
+        TextTable::default()
+    }
+
+    /// Truncates the cells in `column` wider than `width` with an ellipsis
+    pub fn max_width(mut self, column: usize, width: usize) -> Self {
+        if self.max_widths.len() <= column {
+            self.max_widths.resize(column + 1, None);
+        }
+        self.max_widths[column] = Some(width);
+        self
+    }
+
+    pub fn add_row(&mut self, cells: Vec<String>) {
+        let cells = cells
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| match self.max_widths.get(i) {
+                Some(&Some(width)) => truncate(&cell, width),
+                _ => cell,
+            })
+            .collect();
+        self.rows.push(Row::Cells(cells));
+    }
+
+    pub fn add_span(&mut self, text: String) {
+        self.rows.push(Row::Span(text));
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths = Vec::new();
+        for row in self.rows.iter() {
+            if let Row::Cells(cells) = row {
+                widths.resize(widths.len().max(cells.len()), 0);
+                for (width, cell) in widths.iter_mut().zip(cells.iter()) {
+                    *width = (*width).max(cell.width());
+                }
+            }
+        }
+
+        // Widens the last column if a spanning row does not fit
+        let inner = |widths: &[usize]| widths.iter().sum::<usize>() + 3 * widths.len().max(1) - 3;
+        for row in self.rows.iter() {
+            if let Row::Span(text) = row {
+                let missing = text.width().saturating_sub(inner(&widths));
+                match widths.last_mut() {
+                    Some(last) => *last += missing,
+                    None => widths.push(missing),
+                }
+            }
+        }
+        widths
+    }
+}
+
+impl fmt::Display for TextTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let widths = self.widths();
+        let separator = widths
+            .iter()
+            .map(|&w| "-".repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join("+");
+        let separator = format!("+{}+", separator);
+
+        writeln!(f, "{}", separator)?;
+        for row in self.rows.iter() {
+            match row {
+                Row::Cells(cells) => {
+                    for (i, width) in widths.iter().enumerate() {
+                        let cell = cells.get(i).map(String::as_str).unwrap_or_default();
+                        write!(f, "| {} ", pad(cell, *width))?;
+                    }
+                    writeln!(f, "|")?;
+                }
+                Row::Span(text) => {
+                    let inner = separator.width() - 4;
+                    writeln!(f, "| {} |", pad(text, inner))?;
+                }
+            }
+            writeln!(f, "{}", separator)?;
+        }
+        Ok(())
+    }
+}
+
+fn pad(s: &str, width: usize) -> String {
+    format!("{}{}", s, " ".repeat(width.saturating_sub(s.width())))
+}
+
+/// `s` cut to at most `width` columns, ending with an ellipsis if cut
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Display columns of the `|` and `+` in `line`
+    fn boundaries(line: &str) -> Vec<usize> {
+        let mut column = 0;
+        let mut boundaries = Vec::new();
+        for c in line.chars() {
+            if c == '|' || c == '+' {
+                boundaries.push(column);
+            }
+            column += c.width().unwrap_or(0);
+        }
+        boundaries
+    }
+
+    #[test]
+    fn columns_line_up() {
+        let mut table = TextTable::new();
+        for &(name, score) in &[
+            ("课程", "成绩"),
+            ("Linear Algebra", "A"),
+            ("大学物理-基础实验", "优秀"),
+            ("数学分析(B1)", "92"),
+        ] {
+            table.add_row(vec![name.to_owned(), score.to_owned(), "4".to_owned()]);
+        }
+        table.add_span("GPA 3.52".to_owned());
+
+        let text = table.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 11);
+        let expected = boundaries(lines[0]);
+        assert_eq!(expected, [0, 20, 27, 31]);
+        for line in lines.iter().filter(|l| !l.contains("GPA")) {
+            assert_eq!(boundaries(line), expected, "{}", line);
+        }
+        assert_eq!(lines[7], "| 数学分析(B1)      | 92   | 4 |");
+        assert_eq!(lines[9], "| GPA 3.52                     |");
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        assert_eq!(truncate("数学分析(B1)", 20), "数学分析(B1)");
+        assert_eq!(truncate("数学分析(B1)", 8), "数学分…");
+        assert_eq!(truncate("Mathematical Analysis", 8), "Mathema…");
+
+        let mut table = TextTable::new().max_width(0, 6);
+        table.add_row(vec!["大学物理".to_owned(), "优秀".to_owned()]);
+        assert_eq!(table.to_string().lines().nth(1), Some("| 大学… | 优秀 |"));
+    }
+
+    #[test]
+    fn wide_span_widens_last_column() {
+        let mut table = TextTable::new();
+        table.add_row(vec!["a".to_owned(), "b".to_owned()]);
+        table.add_span("本学期 GPA 3.52".to_owned());
+        let text = table.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "+---+-------------+");
+        assert_eq!(lines[1], "| a | b           |");
+        assert_eq!(lines[3], "| 本学期 GPA 3.52 |");
+    }
+}
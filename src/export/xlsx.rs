@@ -0,0 +1,384 @@
+//! Excel workbooks of a grade
+//!
+//! An XLSX file is a zip archive of SpreadsheetML parts. The few parts needed
+//! here are written directly, and stored in the archive uncompressed.
+
+use super::format_gpa;
+use super::formats::escape_html as escape_xml;
+use crate::{CourseStatus, Error, Grade, Semester};
+use std::convert::TryFrom;
+use std::path::Path;
+use unicode_width::UnicodeWidthStr;
+
+/// Writes `grade` to a workbook at `path`: a summary sheet with the GPAs and
+/// credits, then a sheet for each semester with its courses. Headers are
+/// bold and failed courses have their score filled in red.
+pub fn grade_to_xlsx(grade: &Grade, path: &Path) -> Result<(), Error> {
+    std::fs::write(path, workbook(grade)?)?;
+    Ok(())
+}
+
+/// Style indices into `cellXfs` of `STYLES`
+const PLAIN: usize = 0;
+const BOLD: usize = 1;
+const FAILED: usize = 2;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+<fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts>
+<fills count="3"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill><fill><patternFill patternType="solid"><fgColor rgb="FFFFC7CE"/><bgColor indexed="64"/></patternFill></fill></fills>
+<borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders>
+<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>
+<cellXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/><xf numFmtId="0" fontId="0" fillId="2" borderId="0" xfId="0" applyFill="1"/></cellXfs>
+</styleSheet>
+"#;
+
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+struct Cell {
+    value: Value,
+    style: usize,
+}
+
+impl Cell {
+    fn text(s: impl Into<String>, style: usize) -> Self {
+        Cell {
+            value: Value::Text(s.into()),
+            style,
+        }
+    }
+
+    fn number(n: f64, style: usize) -> Self {
+        Cell {
+            value: Value::Number(n),
+            style,
+        }
+    }
+
+    /// Display width of the value, counting CJK characters as two columns
+    fn width(&self) -> usize {
+        match &self.value {
+            Value::Text(s) => s.width(),
+            Value::Number(n) => n.to_string().len(),
+        }
+    }
+}
+
+struct Sheet {
+    name: String,
+    rows: Vec<Vec<Cell>>,
+}
+
+fn summary(grade: &Grade) -> Sheet {
+    let row = |label: &str, value: Option<f64>| {
+        vec![
+            Cell::text(label, BOLD),
+            match value {
+                Some(v) => Cell::number(v, PLAIN),
+                None => Cell::text(format_gpa(None), PLAIN),
+            },
+        ]
+    };
+    Sheet {
+        name: "Summary".to_owned(),
+        rows: vec![
+            row("Total GPA", grade.gpa),
+            row("Semester GPA", grade.sem_gpa),
+            row("Credits earned", Some(grade.credits)),
+        ],
+    }
+}
+
+fn semester_sheet(semester: &Semester) -> Sheet {
+    let header = ["Course", "Score", "Credits", "Letter"]
+        .iter()
+        .map(|&h| Cell::text(h, BOLD))
+        .collect();
+    let courses = semester.courses.iter().map(|c| {
+        let style = match c.status {
+            CourseStatus::Failed => FAILED,
+            _ => PLAIN,
+        };
+        let score = match c.score.trim().parse() {
+            Ok(score) => Cell::number(score, style),
+            Err(_) => Cell::text(c.score.as_str(), style),
+        };
+        vec![
+            Cell::text(c.name.as_str(), PLAIN),
+            score,
+            Cell::number(c.credits, PLAIN),
+            Cell::text(c.letter.unwrap_or_default(), PLAIN),
+        ]
+    });
+    Sheet {
+        name: semester.name.clone(),
+        rows: std::iter::once(header).chain(courses).collect(),
+    }
+}
+
+/// Column name of the 0-based `index`, e.g. "A" or "AB"
+fn column_name(index: usize) -> String {
+    let mut name = Vec::new();
+    let mut n = index + 1;
+    while n > 0 {
+        name.push(b'A' + ((n - 1) % 26) as u8);
+        n = (n - 1) / 26;
+    }
+    name.iter().rev().map(|&b| b as char).collect()
+}
+
+fn sheet_xml(sheet: &Sheet) -> String {
+    let columns = sheet.rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths = (0..columns).map(|i| {
+        sheet
+            .rows
+            .iter()
+            .filter_map(|r| r.get(i).map(Cell::width))
+            .max()
+            .unwrap_or(0)
+    });
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\">\n",
+    );
+    if columns > 0 {
+        xml += "<cols>";
+        for (i, width) in widths.enumerate() {
+            xml += &format!(
+                "<col min=\"{0}\" max=\"{0}\" width=\"{1}\" customWidth=\"1\"/>",
+                i + 1,
+                width + 2
+            );
+        }
+        xml += "</cols>\n";
+    }
+    xml += "<sheetData>\n";
+    for (r, row) in sheet.rows.iter().enumerate() {
+        xml += &format!("<row r=\"{}\">", r + 1);
+        for (c, cell) in row.iter().enumerate() {
+            let reference = format!("{}{}", column_name(c), r + 1);
+            xml += &match &cell.value {
+                Value::Text(s) => format!(
+                    "<c r=\"{}\" s=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>",
+                    reference,
+                    cell.style,
+                    escape_xml(s)
+                ),
+                Value::Number(n) => {
+                    format!(
+                        "<c r=\"{}\" s=\"{}\"><v>{}</v></c>",
+                        reference, cell.style, n
+                    )
+                }
+            };
+        }
+        xml += "</row>\n";
+    }
+    xml += "</sheetData>\n</worksheet>\n";
+    xml
+}
+
+/// `name` without the characters Excel forbids in sheet names, cut to its
+/// limit of 31 characters
+fn sheet_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !"[]:*?/\\".contains(*c))
+        .take(31)
+        .collect()
+}
+
+fn workbook(grade: &Grade) -> Result<Vec<u8>, Error> {
+    let mut sheets = vec![summary(grade)];
+    sheets.extend(grade.scores.iter().map(semester_sheet));
+    for sheet in sheets.iter_mut() {
+        sheet.name = sheet_name(&sheet.name);
+    }
+    for (i, sheet) in sheets.iter().enumerate() {
+        if sheet.name.is_empty() || sheets[..i].iter().any(|s| s.name == sheet.name) {
+            return Err(Error::XlsxError(format!(
+                "invalid or duplicate sheet name `{}`",
+                sheet.name
+            )));
+        }
+    }
+
+    let numbered = || (1..).zip(sheets.iter());
+    let mut content_types = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+         <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+         <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+         <Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+         <Override PartName=\"/xl/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml\"/>",
+    );
+    let mut workbook = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" \
+         xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\"><sheets>",
+    );
+    let mut workbook_rels = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    );
+    for (i, sheet) in numbered() {
+        content_types += &format!(
+            "<Override PartName=\"/xl/worksheets/sheet{}.xml\" \
+             ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>",
+            i
+        );
+        workbook += &format!(
+            "<sheet name=\"{}\" sheetId=\"{1}\" r:id=\"rId{1}\"/>",
+            escape_xml(&sheet.name),
+            i
+        );
+        workbook_rels += &format!(
+            "<Relationship Id=\"rId{0}\" \
+             Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" \
+             Target=\"worksheets/sheet{0}.xml\"/>",
+            i
+        );
+    }
+    content_types += "</Types>\n";
+    workbook += "</sheets></workbook>\n";
+    workbook_rels += &format!(
+        "<Relationship Id=\"rId{}\" \
+         Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" \
+         Target=\"styles.xml\"/></Relationships>\n",
+        sheets.len() + 1
+    );
+    let rels = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+                <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+                <Relationship Id=\"rId1\" \
+                Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" \
+                Target=\"xl/workbook.xml\"/></Relationships>\n";
+
+    let mut zip = ZipWriter::default();
+    zip.add("[Content_Types].xml", content_types.as_bytes())?;
+    zip.add("_rels/.rels", rels.as_bytes())?;
+    zip.add("xl/workbook.xml", workbook.as_bytes())?;
+    zip.add("xl/_rels/workbook.xml.rels", workbook_rels.as_bytes())?;
+    zip.add("xl/styles.xml", STYLES.as_bytes())?;
+    for (i, sheet) in numbered() {
+        zip.add(
+            &format!("xl/worksheets/sheet{}.xml", i),
+            sheet_xml(sheet).as_bytes(),
+        )?;
+    }
+    zip.finish()
+}
+
+/// A zip archive of uncompressed files, without zip64 extensions
+#[derive(Default)]
+struct ZipWriter {
+    out: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+/// Jan 1 1980, the earliest date in the DOS format of zip timestamps
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+impl ZipWriter {
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let too_large = || Error::XlsxError("workbook too large".to_owned());
+        let offset = u32::try_from(self.out.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        self.entries = self.entries.checked_add(1).ok_or_else(too_large)?;
+        let crc = crc32(data);
+
+        // Fields shared by the local header and the central directory:
+        // version needed, flags, method, time, date, CRC-32, sizes and the
+        // length of the name
+        let mut common = Vec::new();
+        for field in &[20u16, 0, 0, 0, DOS_DATE] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in &[crc, size, size] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+        self.out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        self.out.extend_from_slice(&common);
+        self.out.extend_from_slice(&0u16.to_le_bytes());
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+
+        let cd = &mut self.central_directory;
+        cd.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        cd.extend_from_slice(&20u16.to_le_bytes());
+        cd.extend_from_slice(&common);
+        // Lengths of the extra field and comment, disk number, attributes
+        for field in &[0u16, 0, 0, 0] {
+            cd.extend_from_slice(&field.to_le_bytes());
+        }
+        cd.extend_from_slice(&0u32.to_le_bytes());
+        cd.extend_from_slice(&offset.to_le_bytes());
+        cd.extend_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, Error> {
+        let too_large = || Error::XlsxError("workbook too large".to_owned());
+        let offset = u32::try_from(self.out.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(self.central_directory.len()).map_err(|_| too_large())?;
+        self.out.extend_from_slice(&self.central_directory);
+        self.out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        for field in &[0u16, 0, self.entries, self.entries] {
+            self.out.extend_from_slice(&field.to_le_bytes());
+        }
+        self.out.extend_from_slice(&size.to_le_bytes());
+        self.out.extend_from_slice(&offset.to_le_bytes());
+        self.out.extend_from_slice(&0u16.to_le_bytes());
+        Ok(self.out)
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+    use crate::CourseGrade;
+
+    #[test]
+    fn workbook_written() {
+        let mut grade = mock_grade(&[]);
+        grade.scores[2]
+            .courses
+            .push(CourseGrade::new("电路分析 <A>", "55", 3.0));
+        let path = std::env::temp_dir().join(format!("grade-{}.xlsx", std::process::id()));
+        grade_to_xlsx(&grade, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!bytes.is_empty());
+        assert!(bytes.starts_with(b"PK\x03\x04"));
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let sheet = sheet_xml(&semester_sheet(&grade.scores[2]));
+        assert!(sheet.contains(r#"<c r="A1" s="1" t="inlineStr"><is><t>Course</t></is></c>"#));
+        assert!(sheet.contains(r#"<c r="B2" s="0"><v>78</v></c>"#));
+        assert!(sheet.contains(r#"<t>电路分析 &lt;A&gt;</t>"#));
+        assert!(sheet.contains(r#"<c r="B4" s="2"><v>55</v></c>"#));
+        assert!(sheet.contains(r#"<col min="1" max="1" width="20" customWidth="1"/>"#));
+
+        let summary = sheet_xml(&summary(&grade));
+        assert!(summary.contains("<v>3.52</v>"));
+        assert!(summary.contains("<v>18.5</v>"));
+        assert_eq!(column_name(27), "AB");
+        assert_eq!(sheet_name("2019/2020: [秋]"), "20192020 秋");
+    }
+}
@@ -0,0 +1,194 @@
+//! Filters for the courses worth a notification
+
+use crate::{CourseGrade, Error, GradeDiff};
+use regex::Regex;
+
+/// Decides which courses count when looking for changes
+///
+/// Course patterns are regular expressions that must match the whole course
+/// name, so `体育.*` matches `体育(1)` but not `大学体育`. Watched courses are
+/// matched by substrings of their names instead.
+#[derive(Debug, Clone, Default)]
+pub struct GradeFilter {
+    ignore_scores: Vec<String>,
+    ignore_courses: Vec<Regex>,
+    only_courses: Vec<Regex>,
+    /// Lowercased
+    watch_courses: Vec<String>,
+}
+
+impl GradeFilter {
+    /// A filter letting every course through
+    pub fn new() -> Self {
+        GradeFilter::default()
+    }
+
+    /// Ignores courses with any of `scores`, e.g. "通过"
+    pub fn ignore_scores<S: Into<String>>(mut self, scores: impl IntoIterator<Item = S>) -> Self {
+        self.ignore_scores
+            .extend(scores.into_iter().map(Into::into));
+        self
+    }
+
+    /// Ignores courses whose names match any of `patterns`
+    pub fn ignore_courses<S: AsRef<str>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Result<Self, Error> {
+        self.ignore_courses.extend(compile(patterns)?);
+        Ok(self)
+    }
+
+    /// Ignores courses whose names match none of `patterns`
+    pub fn only_courses<S: AsRef<str>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Result<Self, Error> {
+        self.only_courses.extend(compile(patterns)?);
+        Ok(self)
+    }
+
+    /// Ignores courses whose names contain none of `names`, ignoring case
+    pub fn watch_courses<S: AsRef<str>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.watch_courses
+            .extend(names.into_iter().map(|n| n.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Whether `course` passes the filter
+    pub fn matches(&self, course: &CourseGrade) -> bool {
+        let name = course.name.as_str();
+        !self.ignore_scores.contains(&course.score)
+            && !self.ignore_courses.iter().any(|r| r.is_match(name))
+            && (self.only_courses.is_empty() || self.only_courses.iter().any(|r| r.is_match(name)))
+            && watched(&self.watch_courses, name)
+    }
+
+    /// `diff` with only the changed courses that pass the filter
+    pub fn apply_diff(&self, diff: &GradeDiff) -> GradeDiff {
+        GradeDiff {
+            courses: diff
+                .courses
+                .iter()
+                .filter(|c| self.matches(&c.new))
+                .cloned()
+                .collect(),
+            ..diff.clone()
+        }
+    }
+
+    /// Whether `diff` changes only courses that do not pass the filter. A
+    /// diff without course changes, e.g. a GPA change alone, or with a rank
+    /// change is not ignored.
+    pub fn ignores(&self, diff: &GradeDiff) -> bool {
+        !diff.courses.is_empty()
+            && !diff.rank_changed
+            && !diff.courses.iter().any(|c| self.matches(&c.new))
+    }
+}
+
+/// Whether `name` contains any of the lowercase `watched` names ignoring
+/// case, or `watched` is empty
+fn watched(watched: &[String], name: &str) -> bool {
+    let name = name.to_lowercase();
+    watched.is_empty() || watched.iter().any(|w| name.contains(w.as_str()))
+}
+
+fn compile<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Result<Vec<Regex>, Error> {
+    patterns
+        .into_iter()
+        .map(|p| Ok(Regex::new(&format!("^(?:{})$", p.as_ref()))?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+
+    fn diff_with(courses: &[(&str, &str)]) -> GradeDiff {
+        let old = mock_grade(&[]);
+        let mut new = old.clone();
+        for &(name, score) in courses {
+            new.scores[0]
+                .courses
+                .push(CourseGrade::new(name, score, 1.0));
+        }
+        GradeDiff::between(&old, &new)
+    }
+
+    #[test]
+    fn ignore_scores_and_courses() {
+        let filter = GradeFilter::new()
+            .ignore_scores(vec!["通过", "不通过"])
+            .ignore_courses(["体育.*"])
+            .unwrap();
+
+        let ignored = diff_with(&[("体育(1)", "90"), ("新生研讨课", "通过")]);
+        assert!(filter.ignores(&ignored));
+        assert!(filter.apply_diff(&ignored).courses.is_empty());
+
+        let mixed = diff_with(&[("体育(1)", "90"), ("复变函数(B)", "88"), ("大学体育", "85")]);
+        assert!(!filter.ignores(&mixed));
+        let names: Vec<_> = filter
+            .apply_diff(&mixed)
+            .courses
+            .into_iter()
+            .map(|c| c.new.name)
+            .collect();
+        assert_eq!(names, ["复变函数(B)", "大学体育"]);
+    }
+
+    #[test]
+    fn only_courses() {
+        let filter = GradeFilter::new()
+            .only_courses(["数学分析.*", "复变函数.*"])
+            .unwrap();
+        assert!(filter.matches(&CourseGrade::new("复变函数(B)", "88", 3.0)));
+        assert!(!filter.matches(&CourseGrade::new("体育(1)", "90", 1.0)));
+        assert!(filter.ignores(&diff_with(&[("体育(1)", "90")])));
+    }
+
+    #[test]
+    fn watch_courses() {
+        let watched = |names: &[&str], course: &str| {
+            let names: Vec<_> = names.iter().map(|n| n.to_lowercase()).collect();
+            watched(&names, course)
+        };
+        assert!(watched(&["数学分析"], "数学分析(B2)"));
+        assert!(watched(&["物理", "Rust"], "大学物理-基础实验A"));
+        assert!(watched(&["rust"], "Rust 程序设计"));
+        assert!(watched(&["RUST"], "rust 程序设计"));
+        assert!(!watched(&["数学分析"], "线性代数(B1)"));
+        assert!(watched(&[], "线性代数(B1)"));
+
+        let filter = GradeFilter::new().watch_courses(["Rust"]);
+        assert!(filter.ignores(&diff_with(&[("体育(1)", "90")])));
+        let diff = diff_with(&[("体育(1)", "90"), ("RUST 程序设计", "95")]);
+        assert!(!filter.ignores(&diff));
+        let names: Vec<_> = filter
+            .apply_diff(&diff)
+            .courses
+            .into_iter()
+            .map(|c| c.new.name)
+            .collect();
+        assert_eq!(names, ["RUST 程序设计"]);
+        assert!(!GradeFilter::new()
+            .watch_courses(Vec::<String>::new())
+            .ignores(&diff));
+    }
+
+    #[test]
+    fn gpa_change_is_not_ignored() {
+        let filter = GradeFilter::new().ignore_scores(vec!["通过"]);
+        let mut diff = diff_with(&[]);
+        diff.gpa_change = Some(0.1);
+        assert!(!filter.ignores(&diff));
+
+        let mut diff = diff_with(&[("新生研讨课", "通过")]);
+        assert!(filter.ignores(&diff));
+        diff.rank_changed = true;
+        assert!(!filter.ignores(&diff));
+        assert!(GradeFilter::new().ignore_courses(["("]).is_err());
+    }
+}
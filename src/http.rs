@@ -0,0 +1,112 @@
+//! A minimal HTTP/1.1 server for the metrics and status endpoints
+//!
+//! Only the request line is read, and every connection is closed after one
+//! response, which is all that scrapers and health checkers need.
+
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: &'static str, content_type: &'static str, body: String) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Response::new("404 Not Found", "text/plain", "Not Found\n".to_owned())
+    }
+}
+
+/// Serves `GET` requests on `listen` in a background thread, answering with
+/// `handler` called with the request path
+pub fn serve<F>(listen: &str, handler: F) -> Result<()>
+where
+    F: Fn(&str) -> Response + Send + 'static,
+{
+    let listener =
+        TcpListener::bind(listen).with_context(|| format!("Cannot listen on {}", listen))?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|s| respond(s, &handler)) {
+                log::warn!("Serve HTTP request failed: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn respond(stream: TcpStream, handler: impl Fn(&str) -> Response) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    let mut parts = request.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => handler(path),
+        _ => Response::new(
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_owned(),
+        ),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn request(request: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+
+        let (server, _) = listener.accept().unwrap();
+        respond(server, |path| match path {
+            "/ping" => Response::new("200 OK", "text/plain", "pong".to_owned()),
+            _ => Response::not_found(),
+        })
+        .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn get() {
+        let response = request("GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\nContent-Length: 4\r\n"));
+        assert!(response.ends_with("\r\n\r\npong"));
+    }
+
+    #[test]
+    fn not_found_and_not_allowed() {
+        let response = request("GET /nothing HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = request("POST /ping HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
@@ -1,11 +1,46 @@
-use futures::future::try_join;
+use futures::future::{try_join, try_join_all, Future, FutureExt};
+use futures::stream::{self, StreamExt};
 use itertools::Itertools;
-use log::info;
-use reqwest::Client;
-use serde_json::Value;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Client, RequestBuilder};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
-const UA: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:72.0) Gecko/20100101 Firefox/72.0";
+pub mod diff;
+pub mod export;
+pub mod filter;
+pub mod notify;
+mod response;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod throttle;
+
+pub use diff::{format_score_change, GradeDiff};
+#[cfg(feature = "xlsx")]
+pub use export::grade_to_xlsx;
+pub use export::{ExportFormat, GradeFormatter};
+pub use filter::GradeFilter;
+
+/// User-Agent sent to jw.ustc.edu.cn unless another one is given
+pub const DEFAULT_UA: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:72.0) Gecko/20100101 Firefox/72.0";
+
+/// Key of [`Grade::gpa`](struct.Grade.html#structfield.gpa) in
+/// [`Grade::gpa_variants`](struct.Grade.html#structfield.gpa_variants)
+pub const PRIMARY_GPA: &str = "gpa";
+
+/// Pass as the only semester to track all the semesters, in which case
+/// `sem_gpa` is the overall GPA
+pub const ALL_SEMESTERS: &str = "all";
+
+/// Pass as the only semester to track the ongoing one, see
+/// [`current_semesters`](fn.current_semesters.html). It is looked up again on
+/// every fetch, so that a new term is picked up.
+pub const CURRENT_SEMESTER: &str = "current";
 
 /// Error type for [get_grade](fn.get_grade.html)
 #[non_exhaustive]
@@ -13,144 +48,3453 @@ const UA: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:72.0) Gecko/20100101 Firefo
 pub enum Error {
     #[error("Jiaowu login failed")]
     JWLoginFailed,
-    #[error("Grade is malformed")]
-    GradeMalformed,
+    #[error("Wrong username or password: {0}")]
+    WrongPassword(String),
+    #[error("The account is locked by passport.ustc.edu.cn: {0}")]
+    AccountLocked(String),
+    #[error("A captcha is required by passport.ustc.edu.cn: {0}")]
+    CaptchaRequired(String),
+    #[error(
+        "The CAS TGC cookie expired, log in to passport.ustc.edu.cn in a browser and copy it again"
+    )]
+    TgcExpired,
+    #[error("Grade is malformed: {0}")]
+    GradeMalformed(String),
+    #[error("Jiaowu session expired")]
+    SessionExpired,
+    #[error("{}", unknown_semesters(.requested, .available))]
+    UnknownSemester {
+        requested: Vec<String>,
+        available: Vec<String>,
+    },
+    #[error("No semesters selected, use \"all\" to track all the semesters")]
+    NoSemesters,
     #[error("ReqwestError: {0}")]
     ReqwestError(#[from] reqwest::Error),
+    #[error("IoError: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Notification failed: {0}")]
+    NotifyFailed(String),
+    #[error("Conflicting options: {0}")]
+    ConflictingOptions(&'static str),
+    #[error("Invalid course pattern: {0}")]
+    InvalidCoursePattern(#[from] regex::Error),
+    #[error("Writing the workbook failed: {0}")]
+    XlsxError(String),
+}
+
+impl Error {
+    /// Whether the error may go away by trying again, i.e. a timeout, a
+    /// failed connection or a 5xx response
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::ReqwestError(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || matches!(e.status(), Some(s) if s.is_server_error())
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether passport rejected the credentials, so that logging in again
+    /// cannot succeed and only makes a lockout longer
+    pub fn is_credentials_rejected(&self) -> bool {
+        matches!(self, Error::WrongPassword(_) | Error::AccountLocked(_))
+    }
+}
+
+fn unknown_semesters(requested: &[String], available: &[String]) -> String {
+    let requested = requested
+        .iter()
+        .map(|r| match closest_semester(r, available) {
+            Some(closest) => format!("\"{}\" (did you mean \"{}\"?)", r, closest),
+            None => format!("\"{}\"", r),
+        })
+        .join(", ");
+    format!(
+        "Unknown semesters: {}. Available semesters: {}",
+        requested,
+        available.join(", ")
+    )
+}
+
+/// The name in `available` closest to `name` by edit distance, if it is
+/// close enough to be a typo of `name`
+pub fn closest_semester<'a>(name: &str, available: &'a [String]) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    available
+        .iter()
+        .map(|a| (strsim::levenshtein(name, a), a))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, a)| a.as_str())
 }
 
 /// The grade
 #[non_exhaustive]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Grade {
-    /// Overall GPA
-    pub gpa: f64,
+    /// Overall GPA, `None` until any grade is published
+    pub gpa: Option<f64>,
+
+    /// Every overall GPA Jiaowu sends, e.g. one counting only the best
+    /// attempts at retaken courses, keyed by the names of their fields. `gpa`
+    /// is the one above.
+    #[serde(default)]
+    pub gpa_variants: HashMap<String, f64>,
 
-    /// GPA of selected semesters
-    pub sem_gpa: f64,
+    /// GPA of selected semesters, `None` until any grade in them is published,
+    /// or if several semesters are fetched with `per_semester_requests`
+    pub sem_gpa: Option<f64>,
 
     /// All the credits earned
     pub credits: f64,
 
     /// Scores of selected semesters
-    pub scores: Vec<(String, SemesterGrade)>,
+    pub scores: Vec<Semester>,
+
+    /// Rank in the major, `None` unless fetched with
+    /// [`GradeClient::fetch_rank`](struct.GradeClient.html#method.fetch_rank)
+    /// and published
+    pub rank: Option<Rank>,
 }
 
-/// Courses formated as (name, score, credit)
-pub type SemesterGrade = Vec<(String, String, f64)>;
+/// Position of the student in a ranking, e.g. of the major
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Rank {
+    /// Position from 1
+    pub position: u32,
+
+    /// Number of students ranked
+    pub total: u32,
 
-#[allow(non_snake_case)]
-#[derive(serde::Deserialize, Debug)]
-struct Semesters {
-    id: usize,
-    nameZh: String,
-    nameEn: String,
-    schoolYear: String,
-    current: bool,
+    /// Who are ranked, e.g. "专业" for the major
+    pub scope: String,
 }
 
-/// Gets the grade of `user` in `semesters` from jw.ustc.edu.cn
-pub async fn get_grade(user: &str, passwd: &str, semesters: &[&str]) -> Result<Grade, Error> {
-    let client = Client::builder()
-        .user_agent(UA)
-        .cookie_store(true)
-        .build()?;
+impl Rank {
+    pub fn new(position: u32, total: u32, scope: impl Into<String>) -> Self {
+        Rank {
+            position,
+            total,
+            scope: scope.into(),
+        }
+    }
+}
 
-    // Login
-    let data = [
-        ("model", "uplogin.jsp"),
-        ("service", "https://jw.ustc.edu.cn/ucas-sso/login"),
-        ("warn", ""),
-        ("showCode", ""),
-        ("username", user),
-        ("password", passwd),
-        ("button", ""),
-    ];
+impl std::fmt::Display for Rank {
+    /// Formats the rank like "12/143"
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.position, self.total)
+    }
+}
 
-    let res = client
-        .post("https://passport.ustc.edu.cn/login")
-        .form(&data)
-        .send()
-        .await?;
-    if !res.url().as_str().contains("/home") {
-        return Err(Error::JWLoginFailed);
+impl Grade {
+    /// Parses a grade serialized by this crate, e.g. with `serde_json`. See
+    /// [`load_fixture`](fn.load_fixture.html) for Jiaowu responses.
+    pub fn from_json(json: &str) -> Result<Grade, Error> {
+        response::parse(json)
     }
-    info!("Logined");
 
-    // Get semesters
-    let res = client
-        .get("https://jw.ustc.edu.cn/for-std/grade/sheet/getSemesters")
-        .send()
-        .await?;
+    /// Number of courses in all the selected semesters
+    pub fn total_courses(&self) -> usize {
+        self.scores.iter().map(|s| s.courses.len()).sum()
+    }
 
-    let sems: Vec<Semesters> = res.json().await?;
-    info!("Semesters get");
+    /// The selected semester named `name`, e.g. "2019年秋季学期"
+    pub fn semester(&self, name: &str) -> Option<&Semester> {
+        self.scores.iter().find(|s| s.name == name)
+    }
+
+    /// Courses of all the selected semesters
+    pub fn all_courses(&self) -> impl Iterator<Item = &CourseGrade> {
+        self.scores.iter().flat_map(|s| s.courses.iter())
+    }
+
+    /// The first course named `course_name` in the selected semesters
+    pub fn grade_for_course(&self, course_name: &str) -> Option<&CourseGrade> {
+        self.all_courses().find(|c| c.name == course_name)
+    }
+
+    /// Whether a course named `course_name` is in the selected semesters
+    pub fn has_grade_for_course(&self, course_name: &str) -> bool {
+        self.grade_for_course(course_name).is_some()
+    }
+
+    /// Failed courses of all the selected semesters
+    pub fn failed_courses(&self) -> impl Iterator<Item = &CourseGrade> {
+        self.all_courses()
+            .filter(|c| c.status == CourseStatus::Failed)
+    }
+
+    /// Change of the overall GPA from `self` to the newer grade `other`,
+    /// `None` if the GPA was published or withdrawn in between
+    pub fn gpa_change(&self, other: &Grade) -> Option<f64> {
+        gpa_change(self.gpa, other.gpa)
+    }
+
+    /// The GPA variants other than `gpa`, sorted by name
+    pub fn other_gpas(&self) -> Vec<(&str, f64)> {
+        let mut gpas: Vec<_> = self
+            .gpa_variants
+            .iter()
+            .filter(|&(name, _)| name != PRIMARY_GPA)
+            .map(|(name, &gpa)| (name.as_str(), gpa))
+            .collect();
+        gpas.sort_by(|a, b| a.0.cmp(b.0));
+        gpas
+    }
+
+    /// Change of the semester GPA from `self` to the newer grade `other`,
+    /// `None` if the GPA was published or withdrawn in between
+    pub fn sem_gpa_change(&self, other: &Grade) -> Option<f64> {
+        gpa_change(self.sem_gpa, other.sem_gpa)
+    }
+
+    /// Renders the grade in `format`
+    pub fn export(&self, format: ExportFormat) -> String {
+        export::export(self, format)
+    }
+
+    /// Renders the grade as Markdown with a table per semester, the same as
+    /// `export(ExportFormat::Markdown)`
+    pub fn to_markdown(&self) -> String {
+        self.export(ExportFormat::Markdown)
+    }
+
+    /// Renders the grade as the plain text report sent by email, in Chinese
+    /// and without letter grades. See
+    /// [`report::format_grade_text`](export/report/fn.format_grade_text.html)
+    /// for the other options.
+    pub fn to_text(&self) -> String {
+        export::report::format_grade_text(
+            self,
+            None,
+            &[export::report::Language::Chinese],
+            false,
+            40,
+        )
+    }
+
+    /// Renders the grade as the HTML report sent by email, in Chinese. See
+    /// [`report::format_grade_html`](export/report/fn.format_grade_html.html)
+    /// for the other options.
+    pub fn to_html(&self) -> String {
+        export::report::format_grade_html(self, None, &[export::report::Language::Chinese])
+    }
+
+    /// A builder of grades for tests of code using them
+    pub fn builder() -> GradeBuilder {
+        GradeBuilder::default()
+    }
+}
 
-    let ids = sems
+/// Change from `old` to `new`, `None` if only one of them is known
+fn gpa_change(old: Option<f64>, new: Option<f64>) -> Option<f64> {
+    match (old, new) {
+        (Some(old), Some(new)) => Some(new - old),
+        (None, None) => Some(0.),
+        _ => None,
+    }
+}
+
+/// Whether any score has been published in `grade`. Until then, e.g. at
+/// the start of a semester, Jiaowu reports a GPA of 0 that is not worth
+/// comparing.
+pub fn is_grade_meaningful(grade: &Grade) -> bool {
+    grade.all_courses().any(|c| !c.score.trim().is_empty())
+}
+
+/// Courses whose name contains `query`, ignoring case, with the names of
+/// their semesters
+///
+/// With the `regex-search` feature, a query containing any of `.*+?()[]{}|^$\\`
+/// is a regular expression searched for in the names, and a substring again
+/// if it is not a valid one.
+pub fn search_courses<'a>(grade: &'a Grade, query: &str) -> Vec<(&'a str, &'a CourseGrade)> {
+    let matches = course_matcher(query);
+    grade
+        .scores
         .iter()
-        .filter(|s| semesters.contains(&s.nameZh.as_str()))
-        .map(|s| s.id)
-        .join(",");
-    let all = client
-        .get("https://jw.ustc.edu.cn/for-std/grade/sheet/getGradeList")
-        .query(&[("trainTypeId", "1"), ("semesterIds", "")])
-        .send();
-    let sem = client
-        .get("https://jw.ustc.edu.cn/for-std/grade/sheet/getGradeList")
-        .query(&[("trainTypeId", "1"), ("semesterIds", &ids)])
-        .send();
-
-    let (all, sem) = try_join(all, sem).await?;
-    let (all, sem) = try_join(all.text(), sem.text()).await?;
-    info!("Grade get");
+        .flat_map(|s| s.courses.iter().map(move |c| (s.name.as_str(), c)))
+        .filter(|(_, c)| matches(&c.name))
+        .collect()
+}
+
+#[cfg(feature = "regex-search")]
+fn course_matcher(query: &str) -> Box<dyn Fn(&str) -> bool> {
+    if query.contains(|c| ".*+?()[]{}|^$\\".contains(c)) {
+        if let Ok(re) = regex::RegexBuilder::new(query)
+            .case_insensitive(true)
+            .build()
+        {
+            return Box::new(move |name| re.is_match(name));
+        }
+    }
+    substring_matcher(query)
+}
+
+#[cfg(not(feature = "regex-search"))]
+fn course_matcher(query: &str) -> Box<dyn Fn(&str) -> bool> {
+    substring_matcher(query)
+}
 
-    let sem_map = sems.iter().map(|s| (s.id, s.nameZh.clone())).collect();
-    extract_grade(all, sem, sem_map).ok_or(Error::GradeMalformed)
+fn substring_matcher(query: &str) -> Box<dyn Fn(&str) -> bool> {
+    let query = query.to_lowercase();
+    Box::new(move |name| name.to_lowercase().contains(&query))
 }
 
-fn extract_grade(all: String, sem: String, sem_map: HashMap<usize, String>) -> Option<Grade> {
-    let all: Value = serde_json::from_str(&all).ok()?;
-    let sem: Value = serde_json::from_str(&sem).ok()?;
+/// Builder of a [`Grade`](struct.Grade.html)
+///
+/// ```
+/// # use ustc_get_grade::{CourseGrade, Grade};
+/// let grade = Grade::builder()
+///     .gpa(3.7)
+///     .sem_gpa(3.7)
+///     .credits(4.0)
+///     .add_semester("2019年秋季学期", vec![CourseGrade::new("线性代数(B1)", "90", 4.0)])
+///     .build();
+/// assert_eq!(grade.total_courses(), 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GradeBuilder {
+    gpa: Option<f64>,
+    gpa_variants: HashMap<String, f64>,
+    sem_gpa: Option<f64>,
+    credits: f64,
+    scores: Vec<Semester>,
+    rank: Option<Rank>,
+}
 
-    let overview = all.get("overview")?;
-    let gpa = overview.get("gpa")?.as_f64()?;
-    let sem_gpa = sem.get("overview")?.get("gpa")?.as_f64()?;
-    let credits = overview.get("passedCredits")?.as_f64()?;
+impl GradeBuilder {
+    pub fn gpa(mut self, gpa: f64) -> Self {
+        self.gpa = Some(gpa);
+        self
+    }
 
-    let mut scores = Vec::new();
-    for s in sem.get("semesters")?.as_array()? {
-        let name = sem_map.get(&(s.get("id")?.as_u64()? as usize))?.to_owned();
-        let score = s
-            .get("scores")?
-            .as_array()?
+    /// Adds a GPA variant, see [`Grade::gpa_variants`](struct.Grade.html#structfield.gpa_variants)
+    pub fn gpa_variant(mut self, name: impl Into<String>, gpa: f64) -> Self {
+        self.gpa_variants.insert(name.into(), gpa);
+        self
+    }
+
+    pub fn sem_gpa(mut self, sem_gpa: f64) -> Self {
+        self.sem_gpa = Some(sem_gpa);
+        self
+    }
+
+    pub fn credits(mut self, credits: f64) -> Self {
+        self.credits = credits;
+        self
+    }
+
+    pub fn add_semester(mut self, name: &str, courses: SemesterGrade) -> Self {
+        self.scores.push(Semester::new(name, courses));
+        self
+    }
+
+    pub fn rank(mut self, rank: Rank) -> Self {
+        self.rank = Some(rank);
+        self
+    }
+
+    /// Builds the grade, warning if the credits differ from those of the
+    /// passed courses
+    pub fn build(self) -> Grade {
+        let passed = self.passed_credits();
+        if (passed - self.credits).abs() > 1e-6 {
+            warn!(
+                "Credits earned {} differ from {} credits of the passed courses",
+                self.credits, passed
+            );
+        }
+        let mut gpa_variants = self.gpa_variants;
+        if let Some(gpa) = self.gpa {
+            gpa_variants.insert(PRIMARY_GPA.to_owned(), gpa);
+        }
+        Grade {
+            gpa: self.gpa,
+            gpa_variants,
+            sem_gpa: self.sem_gpa,
+            credits: self.credits,
+            scores: self.scores,
+            rank: self.rank,
+        }
+    }
+
+    fn passed_credits(&self) -> f64 {
+        self.scores
             .iter()
-            .map(|s| {
-                Some((
-                    s.get("courseNameCh")?.as_str()?.to_owned(),
-                    s.get("scoreCh")?.as_str()?.to_owned(),
-                    s.get("credits")?.as_f64()?,
-                ))
+            .flat_map(|s| s.courses.iter())
+            .filter(|c| match c.numeric_score() {
+                Some(score) => score >= 60.,
+                None => pass_fail(&c.score) == Some(true),
             })
-            .collect::<Option<Vec<_>>>()?;
-        scores.push((name, score));
+            .map(|c| c.credits)
+            .sum()
     }
+}
 
-    Some(Grade {
-        gpa,
-        sem_gpa,
-        credits,
-        scores,
+/// A semester and its courses
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Semester {
+    /// Semester name, e.g. "2019年秋季学期"
+    pub name: String,
+
+    pub courses: SemesterGrade,
+}
+
+impl Semester {
+    pub fn new(name: impl Into<String>, courses: SemesterGrade) -> Self {
+        Semester {
+            name: name.into(),
+            courses,
+        }
+    }
+
+    /// Courses by [`numeric_score`](struct.CourseGrade.html#method.numeric_score)
+    /// in descending order, followed by the courses without one
+    pub fn sorted_by_score(&self) -> Vec<&CourseGrade> {
+        let mut courses: Vec<_> = self.courses.iter().collect();
+        courses.sort_by(|a, b| match (a.numeric_score(), b.numeric_score()) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+        courses
+    }
+}
+
+/// A course and its score
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CourseGrade {
+    /// Course name in Chinese
+    pub name: String,
+
+    /// Score, either a number or a grade like "优秀" or "通过"
+    pub score: String,
+
+    /// Letter grade of `score`, see [`score_to_letter`](fn.score_to_letter.html)
+    #[serde(deserialize_with = "deserialize_letter")]
+    pub letter: Letter,
+
+    pub credits: f64,
+
+    /// Whether the course is failed or retaken
+    pub status: CourseStatus,
+
+    /// Course code like "MATH1006", empty if not known
+    pub code: String,
+
+    /// Course type like "必修" or "选修"
+    pub course_type: Option<String>,
+
+    /// Exam type like "正常考试" or "重修"
+    pub exam_type: Option<String>,
+
+    /// How the course is graded, detected from `score`
+    pub grading_mode: GradingMode,
+
+    /// When the score was entered, e.g. "2020-01-15 10:23:45", if Jiaowu
+    /// sent it
+    pub graded_at: Option<String>,
+}
+
+/// How a course is graded
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GradingMode {
+    /// Scores from 0 to 100
+    Numeric,
+    /// Grades like "优秀" and "良好"
+    Chinese,
+    /// Grades like "A−"
+    Letter,
+    /// "通过" or "不通过", not counted in the GPA
+    PassFail,
+    /// Anything else, e.g. "缓考"
+    Unknown,
+}
+
+impl GradingMode {
+    pub fn from_score(score: &str) -> Self {
+        let score = score.trim();
+        if pass_fail(score).is_some() {
+            GradingMode::PassFail
+        } else if numeric_score(score).is_none() {
+            GradingMode::Unknown
+        } else if score.parse::<f64>().is_ok() {
+            GradingMode::Numeric
+        } else if score.starts_with(|c: char| c.is_ascii_uppercase()) {
+            GradingMode::Letter
+        } else {
+            GradingMode::Chinese
+        }
+    }
+}
+
+/// Whether a pass/fail score like "通过" or "未通过" is a pass, or `None` for
+/// other scores
+fn pass_fail(score: &str) -> Option<bool> {
+    match score.trim() {
+        "通过" | "合格" | "P" => Some(true),
+        "不通过" | "未通过" | "不合格" | "NP" => Some(false),
+        _ => None,
+    }
+}
+
+/// Status of a course, given by Jiaowu or inferred from the score
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CourseStatus {
+    Normal,
+    Failed,
+    /// A retake of a course failed before
+    Retake,
+}
+
+impl CourseStatus {
+    /// `Failed` for scores below 60, "不及格" and failed pass/fail scores like
+    /// "不通过", `Normal` otherwise
+    pub fn from_score(score: &str) -> Self {
+        let failed = match numeric_score(score) {
+            Some(score) => score < 60.,
+            None => pass_fail(score) == Some(false),
+        };
+        if failed {
+            CourseStatus::Failed
+        } else {
+            CourseStatus::Normal
+        }
+    }
+}
+
+impl CourseGrade {
+    /// A course with the status inferred from `score`, see
+    /// [`CourseStatus::from_score`](enum.CourseStatus.html#method.from_score)
+    pub fn new(name: impl Into<String>, score: impl Into<String>, credits: f64) -> Self {
+        let score = score.into();
+        CourseGrade {
+            name: name.into(),
+            letter: score_to_letter(&score),
+            status: CourseStatus::from_score(&score),
+            grading_mode: GradingMode::from_score(&score),
+            score,
+            credits,
+            code: String::new(),
+            course_type: None,
+            exam_type: None,
+            graded_at: None,
+        }
+    }
+
+    pub fn with_status(mut self, status: CourseStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = code.into();
+        self
+    }
+
+    /// Whether `self` and `other` are the same course, compared by code, or
+    /// by name if either code is unknown
+    pub fn same_course(&self, other: &CourseGrade) -> bool {
+        if self.code.is_empty() || other.code.is_empty() {
+            self.name == other.name
+        } else {
+            self.code == other.code
+        }
+    }
+
+    /// The score as a comparable number, see [`numeric_score`](fn.numeric_score.html)
+    pub fn numeric_score(&self) -> Option<f64> {
+        numeric_score(&self.score)
+    }
+
+    pub fn is_pass_fail(&self) -> bool {
+        self.grading_mode == GradingMode::PassFail
+    }
+}
+
+/// Converts a score to a number between 0 and 100 so that scores of all
+/// kinds can be compared. This is the raw score, not the grade point.
+///
+/// Numbers are returned as they are. Grades are mapped to the middle of
+/// their ranges:
+///
+/// | Grade      | Score | Grade | Score |
+/// |------------|-------|-------|-------|
+/// | 优秀 / 优  | 95    | A+    | 97.5  |
+/// | 良好 / 良  | 85    | A     | 92    |
+/// | 中等 / 中  | 75    | A−    | 87    |
+/// | 及格       | 65    | B+    | 82    |
+/// | 不及格     | 30    | B     | 77    |
+/// |            |       | B−    | 72    |
+/// |            |       | C+    | 68    |
+/// |            |       | C     | 64.5  |
+/// |            |       | D     | 61    |
+/// |            |       | F     | 30    |
+///
+/// Returns `None` for pass/fail scores like "通过" and "P", and for anything
+/// unrecognized.
+pub fn numeric_score(score: &str) -> Option<f64> {
+    let score = score.trim();
+    let numeric = match score {
+        "优秀" | "优" => 95.,
+        "良好" | "良" => 85.,
+        "中等" | "中" => 75.,
+        "及格" => 65.,
+        "不及格" => 30.,
+        "A+" => 97.5,
+        "A" => 92.,
+        "A-" | "A−" => 87.,
+        "B+" => 82.,
+        "B" => 77.,
+        "B-" | "B−" => 72.,
+        "C+" => 68.,
+        "C" => 64.5,
+        "D" => 61.,
+        "F" => 30.,
+        _ => score
+            .parse::<f64>()
+            .ok()
+            .filter(|s| (0. ..=100.).contains(s))?,
+    };
+    Some(numeric)
+}
+
+/// Maps a score to its letter grade
+///
+/// | Score    | Letter | Score  | Letter |
+/// |----------|--------|--------|--------|
+/// | 95–100   | A+     | 70–74  | B−     |
+/// | 90–94    | A      | 67–69  | C+     |
+/// | 85–89    | A−     | 63–66  | C      |
+/// | 80–84    | B+     | 60–62  | D      |
+/// | 75–79    | B      | 0–59   | F      |
+///
+/// "优秀", "良好", "中等", "及格" and "不及格" map to "A", "B+", "C+", "D"
+/// and "F". Returns `None` for other scores like "通过".
+pub fn score_to_letter(score: &str) -> Option<&'static str> {
+    let score = score.trim();
+    let letter = match score {
+        "优秀" => "A",
+        "良好" => "B+",
+        "中等" => "C+",
+        "及格" => "D",
+        "不及格" => "F",
+        _ => match score
+            .parse::<f64>()
+            .ok()
+            .filter(|s| (0. ..=100.).contains(s))?
+        {
+            s if s >= 95. => "A+",
+            s if s >= 90. => "A",
+            s if s >= 85. => "A−",
+            s if s >= 80. => "B+",
+            s if s >= 75. => "B",
+            s if s >= 70. => "B−",
+            s if s >= 67. => "C+",
+            s if s >= 63. => "C",
+            s if s >= 60. => "D",
+            _ => "F",
+        },
+    };
+    Some(letter)
+}
+
+/// A letter grade. Written out in the field, the `'static` lifetime would
+/// make serde only deserialize `CourseGrade` from `'static` data.
+type Letter = Option<&'static str>;
+
+/// Letters returned by [`score_to_letter`](fn.score_to_letter.html)
+const LETTERS: &[&str] = &["A+", "A", "A−", "B+", "B", "B−", "C+", "C", "D", "F"];
+
+fn deserialize_letter<'de, D>(deserializer: D) -> Result<Letter, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(letter) => LETTERS
+            .iter()
+            .find(|&&l| l == letter)
+            .map(|&l| Some(l))
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown letter `{}`", letter))),
+    }
+}
+
+/// Courses of a semester
+pub type SemesterGrade = Vec<CourseGrade>;
+
+/// Statistics of the numeric scores in a semester
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SemesterStats {
+    pub mean: f64,
+    pub median: f64,
+    /// Population standard deviation
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Computes statistics of the numeric scores in `courses`, ignoring scores
+/// like "优秀" or "通过". Returns `None` if no score is numeric.
+pub fn semester_stats(courses: &SemesterGrade) -> Option<SemesterStats> {
+    let mut scores: Vec<f64> = numeric_scores(courses).collect();
+    if scores.is_empty() {
+        return None;
+    }
+    scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let n = scores.len() as f64;
+    let mean = scores.iter().sum::<f64>() / n;
+    let len = scores.len();
+    let median = (scores[(len - 1) / 2] + scores[len / 2]) / 2.;
+    let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+
+    Some(SemesterStats {
+        mean,
+        median,
+        std_dev: variance.sqrt(),
+        min: scores[0],
+        max: scores[len - 1],
     })
 }
 
-#[cfg(feature = "blocking")]
-pub mod blocking {
-    use super::{Error, Grade};
+/// Arithmetic mean of the numeric scores in `courses`, or `None` if no
+/// score is numeric
+///
+/// Unlike the GPA, this is not weighted by credits.
+pub fn simple_average(courses: &SemesterGrade) -> Option<f64> {
+    let (sum, count) = numeric_scores(courses).fold((0., 0), |(sum, n), s| (sum + s, n + 1));
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f64)
+    }
+}
 
-    #[inline]
-    pub fn get_grade(user: &str, passwd: &str, semesters: &[&str]) -> Result<Grade, Error> {
-        tokio::runtime::Runtime::new()
-            .expect("Unable to create Tokio runtime")
-            .block_on(super::get_grade(user, passwd, semesters))
+/// GPA of `courses` on the 4.3 scale, weighted by credits, from the letter
+/// grades of their scores. Courses without a letter, like those passed with
+/// "通过", are left out. Returns `None` if no course has a letter.
+///
+/// This is an estimate: the GPA Jiaowu computes is in
+/// [`Grade::sem_gpa`](struct.Grade.html#structfield.sem_gpa).
+pub fn calculate_gpa(courses: &SemesterGrade) -> Option<f64> {
+    let (points, credits) = courses
+        .iter()
+        .filter_map(|c| Some((grade_point(c.letter?)?, c.credits)))
+        .fold((0., 0.), |(points, credits), (gp, cr)| {
+            (points + gp * cr, credits + cr)
+        });
+    Some(points / credits).filter(|_| credits > 0.)
+}
+
+/// Grade point of a letter returned by [`score_to_letter`](fn.score_to_letter.html)
+fn grade_point(letter: &str) -> Option<f64> {
+    let point = match letter {
+        "A+" => 4.3,
+        "A" => 4.0,
+        "A−" => 3.7,
+        "B+" => 3.3,
+        "B" => 3.0,
+        "B−" => 2.7,
+        "C+" => 2.3,
+        "C" => 2.0,
+        "D" => 1.3,
+        "F" => 0.,
+        _ => return None,
+    };
+    Some(point)
+}
+
+fn numeric_scores(courses: &SemesterGrade) -> impl Iterator<Item = f64> + '_ {
+    courses
+        .iter()
+        .filter_map(|c| c.score.trim().parse::<f64>().ok())
+        .filter(|score| score.is_finite())
+}
+
+/// The overview part of a grade list, without per-course data
+#[non_exhaustive]
+#[derive(Debug, PartialEq)]
+pub struct OverviewData {
+    /// GPA, `None` until any grade is published
+    pub gpa: Option<f64>,
+
+    /// Credits of passed courses
+    pub passed_credits: f64,
+
+    /// Credits of failed courses
+    pub failed_credits: f64,
+
+    /// Number of passed courses
+    pub passed_course_count: u64,
+}
+
+/// A semester listed by `getSemesters`
+#[non_exhaustive]
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemesterInfo {
+    pub id: usize,
+    /// Name in Chinese, e.g. `2019年秋季学期`
+    pub name_zh: String,
+    pub name_en: String,
+    pub school_year: String,
+    /// Whether this is the ongoing semester
+    pub current: bool,
+}
+
+impl SemesterInfo {
+    /// See [`is_graduate_semester`](fn.is_graduate_semester.html)
+    pub fn is_graduate(&self) -> bool {
+        is_graduate_semester(&self.name_zh)
+    }
+}
+
+/// Guesses from its Chinese name whether a semester is one of graduate
+/// students, e.g. `2020年研究生秋季学期`. Jiaowu does not say so otherwise.
+pub fn is_graduate_semester(name_zh: &str) -> bool {
+    name_zh.contains("研究生")
+}
+
+/// Trims a course name and collapses the runs of whitespace in it, such as
+/// U+00A0 and U+3000 which Jiaowu leaves in some names, to single spaces so
+/// that it compares equal across fetches
+pub(crate) fn normalize_course_name(name: &str) -> String {
+    name.split(char::is_whitespace)
+        .filter(|s| !s.is_empty())
+        .join(" ")
+}
+
+static SEMESTER_CACHE: Lazy<Mutex<Option<Vec<SemesterInfo>>>> = Lazy::new(|| Mutex::new(None));
+
+fn semester_cache() -> MutexGuard<'static, Option<Vec<SemesterInfo>>> {
+    SEMESTER_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Gets the semesters from jw.ustc.edu.cn with a logged in `client`. The
+/// first successful response is cached for the rest of the process, until
+/// [`clear_semester_cache`](fn.clear_semester_cache.html) is called.
+pub async fn get_available_semesters_cached(client: &Client) -> Result<Vec<SemesterInfo>, Error> {
+    semesters_cached(client, &GradeClient::default().jw_url()).await
+}
+
+/// Drops the semesters cached by
+/// [`get_available_semesters_cached`](fn.get_available_semesters_cached.html)
+pub fn clear_semester_cache() {
+    *semester_cache() = None;
+}
+
+async fn semesters_cached(client: &Client, jw: &str) -> Result<Vec<SemesterInfo>, Error> {
+    if let Some(sems) = semester_cache().clone() {
+        return Ok(sems);
+    }
+    fetch_semesters(client, jw).await
+}
+
+/// Gets the semesters bypassing the cache, and caches them
+async fn fetch_semesters(client: &Client, jw: &str) -> Result<Vec<SemesterInfo>, Error> {
+    let sems: Vec<SemesterInfo> = client
+        .get(&format!("{}/for-std/grade/sheet/getSemesters", jw))
+        .send()
+        .await?
+        .json()
+        .await?;
+    *semester_cache() = Some(sems.clone());
+    Ok(sems)
+}
+
+/// Builds the HTTP client used to talk to jw.ustc.edu.cn, with a cookie
+/// store for the login session
+pub fn build_client(ua: &str, timeout: Option<Duration>) -> Result<Client, Error> {
+    Ok(client_builder(ua, timeout).build()?)
+}
+
+fn client_builder(ua: &str, timeout: Option<Duration>) -> reqwest::ClientBuilder {
+    let mut builder = Client::builder().user_agent(ua).cookie_store(true);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder
+}
+
+/// Gets the grade of `user` in `semesters` from jw.ustc.edu.cn. `semesters`
+/// must not be empty, see [`ALL_SEMESTERS`](constant.ALL_SEMESTERS.html).
+/// Other options are set with [`GradeRequest`](struct.GradeRequest.html).
+pub async fn get_grade(user: &str, passwd: &str, semesters: &[&str]) -> Result<Grade, Error> {
+    GradeRequest::new(user, passwd)
+        .semesters(semesters)
+        .send()
+        .await
+}
+
+/// Gets the grade of `user` in the ongoing semesters along with their names,
+/// see [`GradeClient::get_grade_current`](struct.GradeClient.html#method.get_grade_current)
+pub async fn get_grade_current(user: &str, passwd: &str) -> Result<(Vec<String>, Grade), Error> {
+    GradeClient::default()
+        .get_grade_current(&Credentials::password(user, passwd))
+        .await
+}
+
+/// Gets the grade of `user` in the semesters with `semester_ids`, which are
+/// named as in the grade list, see [`GradeClient::get_grade_by_ids`](struct.GradeClient.html#method.get_grade_by_ids)
+pub async fn get_grade_semester_only(
+    user: &str,
+    passwd: &str,
+    semester_ids: &[usize],
+) -> Result<Grade, Error> {
+    GradeClient::default()
+        .get_grade_by_ids(
+            &Credentials::password(user, passwd),
+            semester_ids,
+            &HashMap::new(),
+        )
+        .await
+}
+
+/// Default number of accounts [`get_grades`](fn.get_grades.html) fetches at
+/// the same time
+pub const DEFAULT_MAX_CONCURRENCY: usize = 3;
+
+/// Gets the grades of several `(user, passwd)` accounts, running at most
+/// `max_concurrency` fetches at a time. The results are in the order of
+/// `accounts`.
+///
+/// Every account has its own client and cookie store.
+pub async fn get_grades(
+    client: &GradeClient,
+    accounts: &[(&str, &str)],
+    semesters: &[&str],
+    max_concurrency: usize,
+) -> Vec<Result<Grade, Error>> {
+    map_concurrently(accounts, max_concurrency, |&(user, passwd)| async move {
+        client
+            .get_grade(&Credentials::password(user, passwd), semesters)
+            .await
+    })
+    .await
+}
+
+/// An account and the semesters to get its grade in, see
+/// [`get_grade_concurrent`](fn.get_grade_concurrent.html)
+#[derive(Clone)]
+pub struct ConcurrentRequest {
+    pub user: String,
+    pub passwd: String,
+    pub semesters: Vec<String>,
+}
+
+impl ConcurrentRequest {
+    pub fn new(user: impl Into<String>, passwd: impl Into<String>, semesters: &[&str]) -> Self {
+        ConcurrentRequest {
+            user: user.into(),
+            passwd: passwd.into(),
+            semesters: semesters.iter().map(|&s| s.to_owned()).collect(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ConcurrentRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ConcurrentRequest")
+            .field("user", &self.user)
+            .field("semesters", &self.semesters)
+            .finish()
+    }
+}
+
+/// Gets the grades of `requests`, which may select different semesters,
+/// running at most `max_concurrent` fetches at a time. The results are in
+/// the order of `requests`.
+///
+/// Unlike [`get_grades`](fn.get_grades.html), the default client settings
+/// are used.
+pub async fn get_grade_concurrent(
+    requests: Vec<ConcurrentRequest>,
+    max_concurrent: usize,
+) -> Vec<Result<Grade, Error>> {
+    map_concurrently(&requests, max_concurrent, |request| async move {
+        let semesters: Vec<_> = request.semesters.iter().map(String::as_str).collect();
+        GradeRequest::new(&request.user, &request.passwd)
+            .semesters(&semesters)
+            .send()
+            .await
+    })
+    .await
+}
+
+/// Maps `items` with `f`, polling at most `limit` futures at a time, and
+/// returns the outputs in the order of `items`
+async fn map_concurrently<'a, T, F, Fut>(items: &'a [T], limit: usize, f: F) -> Vec<Fut::Output>
+where
+    F: Fn(&'a T) -> Fut,
+    Fut: Future,
+{
+    let mut outputs: Vec<_> = stream::iter(items.iter().enumerate())
+        .map(|(i, item)| f(item).map(move |output| (i, output)))
+        .buffer_unordered(limit.max(1))
+        .collect()
+        .await;
+    outputs.sort_by_key(|&(i, _)| i);
+    outputs.into_iter().map(|(_, output)| output).collect()
+}
+
+/// What to log in to passport.ustc.edu.cn with
+#[derive(Clone, PartialEq)]
+pub enum Credentials {
+    /// The username and password of the account
+    Password { user: String, passwd: String },
+    /// The value of the `TGC` cookie set on passport.ustc.edu.cn by a login
+    /// in a browser, so that the password is never needed. It lasts for
+    /// weeks; once it expires, logging in fails with
+    /// [`Error::TgcExpired`](enum.Error.html#variant.TgcExpired).
+    Tgc(String),
+}
+
+impl Credentials {
+    pub fn password(user: impl Into<String>, passwd: impl Into<String>) -> Self {
+        Credentials::Password {
+            user: user.into(),
+            passwd: passwd.into(),
+        }
+    }
+
+    /// The password or the TGC, e.g. to redact it from logs
+    pub fn secret(&self) -> &str {
+        match self {
+            Credentials::Password { passwd, .. } => passwd,
+            Credentials::Tgc(tgc) => tgc,
+        }
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Credentials::Password { user, .. } => f
+                .debug_struct("Password")
+                .field("user", user)
+                .field("passwd", &"******")
+                .finish(),
+            Credentials::Tgc(_) => f.debug_tuple("Tgc").field(&"******").finish(),
+        }
+    }
+}
+
+/// URL of passport.ustc.edu.cn, the CAS server that Jiaowu logs in with
+const PASSPORT_URL: &str = "https://passport.ustc.edu.cn";
+
+/// Settings of the HTTP clients used to get grades
+///
+/// Every [`get_grade`](#method.get_grade) builds a fresh client, so that no
+/// session is shared between logins, unless one is given with
+/// [`with_http_client`](#method.with_http_client).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeClient {
+    http: Option<SharedClient>,
+    user_agent: String,
+    timeout: Option<Duration>,
+    base_path: String,
+    passport_url: String,
+    jw_origin: String,
+    proxy: Option<String>,
+    train_type: u32,
+    max_retries: u32,
+    retry_base_delay: Duration,
+    fetch_rank: bool,
+    http2: bool,
+    per_semester_requests: bool,
+}
+
+/// Default of [`GradeClient::retry_base_delay`](struct.GradeClient.html#method.retry_base_delay)
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// A client given by the user, equal only to its clones
+#[derive(Debug, Clone)]
+struct SharedClient(Arc<Client>);
+
+impl PartialEq for SharedClient {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Default for GradeClient {
+    fn default() -> Self {
+        GradeClient::new(DEFAULT_UA)
+    }
+}
+
+impl GradeClient {
+    pub fn new(user_agent: impl Into<String>) -> Self {
+        GradeClient {
+            http: None,
+            user_agent: user_agent.into(),
+            timeout: None,
+            base_path: String::new(),
+            passport_url: PASSPORT_URL.to_owned(),
+            jw_origin: "https://jw.ustc.edu.cn".to_owned(),
+            proxy: None,
+            train_type: 1,
+            max_retries: 0,
+            retry_base_delay: DEFAULT_RETRY_BASE_DELAY,
+            fetch_rank: false,
+            http2: false,
+            per_semester_requests: false,
+        }
+    }
+
+    /// Sets the path under which the jw.ustc.edu.cn endpoints are served,
+    /// e.g. "/grade-api"
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        let base_path = base_path.trim_matches('/');
+        self.base_path = if base_path.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", base_path)
+        };
+        self
+    }
+
+    /// URL of jw.ustc.edu.cn, including the base path
+    fn jw_url(&self) -> String {
+        format!("{}{}", self.jw_origin, self.base_path)
+    }
+
+    /// Sends the requests meant for passport.ustc.edu.cn and jw.ustc.edu.cn
+    /// to `passport` and `jw`, e.g. mock servers in tests. Together with
+    /// [`with_http_client`](#method.with_http_client), this runs the whole
+    /// of [`get_grade`](#method.get_grade) without network access.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn servers(mut self, passport: &str, jw: &str) -> Self {
+        self.passport_url = passport.trim_end_matches('/').to_owned();
+        self.jw_origin = jw.trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Sets the timeout of every request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sends every request through the proxy at `url`, e.g.
+    /// "socks5://127.0.0.1:1080"
+    pub fn proxy(mut self, url: &str) -> Self {
+        self.proxy = Some(url.to_owned());
+        self
+    }
+
+    /// Sets the training type of the grades, 1 for undergraduates (the
+    /// default) and 2 for graduates
+    pub fn train_type(mut self, train_type: u32) -> Self {
+        self.train_type = train_type;
+        self
+    }
+
+    /// Sets how many times a fetch is retried after a transient error, see
+    /// [`Error::is_transient`](enum.Error.html#method.is_transient). No fetch
+    /// is retried by default.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the wait before the first retry, which doubles on every retry
+    pub fn retry_base_delay(mut self, delay: Duration) -> Self {
+        self.retry_base_delay = delay;
+        self
+    }
+
+    /// Sets whether the rank in the major is fetched after the grade, see
+    /// [`Grade::rank`](struct.Grade.html#structfield.rank). Failing to
+    /// fetch it is only logged, leaving the rank `None`.
+    pub fn fetch_rank(mut self, fetch_rank: bool) -> Self {
+        self.fetch_rank = fetch_rank;
+        self
+    }
+
+    /// Sets whether requests are sent over HTTP/2 without negotiating it
+    /// first, which fails if a server only speaks HTTP/1.1. The TLS backend
+    /// does not negotiate HTTP/2, so HTTP/1.1 is used by default.
+    pub fn http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    /// Sets whether the grade of every selected semester is fetched with a
+    /// request of its own, all at the same time, instead of one request for
+    /// all of them. Jiaowu then sends no GPA of the selection, so
+    /// `Grade::sem_gpa` is `None` when more than one semester is selected.
+    pub fn per_semester_requests(mut self, per_semester_requests: bool) -> Self {
+        self.per_semester_requests = per_semester_requests;
+        self
+    }
+
+    /// Sends every request with `client`, e.g. one trusting the root
+    /// certificates of a campus network. It must have a cookie store, and
+    /// the user agent, timeout, proxy and HTTP/2 settings of this
+    /// `GradeClient` are not applied to it. Its cookies are shared by all
+    /// the logins.
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.http = Some(SharedClient(Arc::new(client)));
+        self
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    fn http_client(&self) -> Result<Client, Error> {
+        if let Some(SharedClient(client)) = &self.http {
+            return Ok(Client::clone(client));
+        }
+        let mut builder = client_builder(&self.user_agent, self.timeout);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        if self.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Gets the grade of `user` in `semesters` from jw.ustc.edu.cn. `semesters`
+    /// must not be empty, see [`ALL_SEMESTERS`](constant.ALL_SEMESTERS.html).
+    /// Semesters can also be given by ID, and if all of them are, they are
+    /// fetched with [`get_grade_by_ids`](#method.get_grade_by_ids).
+    pub async fn get_grade(
+        &self,
+        credentials: &Credentials,
+        semesters: &[&str],
+    ) -> Result<Grade, Error> {
+        if let Some(ids) = semester_ids(semesters).filter(|ids| !ids.is_empty()) {
+            return self
+                .get_grade_by_ids(credentials, &ids, &HashMap::new())
+                .await;
+        }
+        let jw = self.jw_url();
+        self.retry(|| async {
+            let client = self.http_client()?;
+            login(&client, &self.passport_url, &jw, credentials).await?;
+            let grade = relogin_once(
+                || login(&client, &self.passport_url, &jw, credentials),
+                || fetch_grade(&client, &jw, self.list_query(), semesters),
+            )
+            .await?;
+            Ok(self.with_rank(&client, &jw, grade).await)
+        })
+        .await
+    }
+
+    /// Gets the grade of `user` in the ongoing semesters, see
+    /// [`current_semesters`](fn.current_semesters.html), along with their
+    /// names. The semesters are looked up on every call.
+    pub async fn get_grade_current(
+        &self,
+        credentials: &Credentials,
+    ) -> Result<(Vec<String>, Grade), Error> {
+        let jw = self.jw_url();
+        self.retry(|| async {
+            let client = self.http_client()?;
+            login(&client, &self.passport_url, &jw, credentials).await?;
+            let (names, grade) = relogin_once(
+                || login(&client, &self.passport_url, &jw, credentials),
+                || fetch_current_grade(&client, &jw, self.list_query()),
+            )
+            .await?;
+            Ok((names, self.with_rank(&client, &jw, grade).await))
+        })
+        .await
+    }
+
+    /// Logs in to Jiaowu without fetching anything, to check the credentials
+    pub async fn check_login(&self, credentials: &Credentials) -> Result<(), Error> {
+        let client = self.http_client()?;
+        login(&client, &self.passport_url, &self.jw_url(), credentials).await
+    }
+
+    /// Logs in to Jiaowu and lists the semesters available to the user
+    pub async fn semesters(&self, credentials: &Credentials) -> Result<Vec<SemesterInfo>, Error> {
+        let client = self.http_client()?;
+        let jw = self.jw_url();
+        login(&client, &self.passport_url, &jw, credentials).await?;
+        semesters_cached(&client, &jw).await
+    }
+
+    /// Gets the grade of `user` in the semesters with `semester_ids`, skipping
+    /// the lookup of semester IDs. Semesters missing from `semester_names` are
+    /// named as in the grade list. Unknown IDs are not an error: they are
+    /// warned about and yield empty semesters named by their IDs.
+    pub async fn get_grade_by_ids(
+        &self,
+        credentials: &Credentials,
+        semester_ids: &[usize],
+        semester_names: &HashMap<usize, String>,
+    ) -> Result<Grade, Error> {
+        if semester_ids.is_empty() {
+            return Err(Error::NoSemesters);
+        }
+        let sem_map: HashMap<_, _> = semester_ids
+            .iter()
+            .filter_map(|id| Some((*id, semester_names.get(id)?.clone())))
+            .collect();
+
+        let jw = self.jw_url();
+        self.retry(|| async {
+            let client = self.http_client()?;
+            login(&client, &self.passport_url, &jw, credentials).await?;
+            let grade = relogin_once(
+                || login(&client, &self.passport_url, &jw, credentials),
+                || {
+                    fetch_grade_list(
+                        &client,
+                        &jw,
+                        self.list_query(),
+                        Some(semester_ids),
+                        sem_map.clone(),
+                        semester_ids,
+                    )
+                },
+            )
+            .await?;
+            Ok(self.with_rank(&client, &jw, grade).await)
+        })
+        .await
+    }
+
+    fn list_query(&self) -> ListQuery {
+        ListQuery {
+            train_type: self.train_type,
+            per_semester: self.per_semester_requests,
+        }
+    }
+
+    /// `grade` with its rank if `fetch_rank` is set, fetched with the logged
+    /// in `client`
+    async fn with_rank(&self, client: &Client, jw: &str, mut grade: Grade) -> Grade {
+        if self.fetch_rank {
+            match fetch_rank(client, jw, self.train_type).await {
+                Ok(rank) => grade.rank = rank,
+                Err(e) => warn!("Get rank failed, continuing without it: {}", e),
+            }
+        }
+        grade
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error that is not
+    /// transient, or has been retried `max_retries` times
+    async fn retry<T, F, Fut>(&self, mut attempt: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Err(e) if e.is_transient() && retries < self.max_retries => {
+                    let delay = retry_delay(self.retry_base_delay, retries);
+                    warn!("{}, retrying in {:.1} seconds", e, delay.as_secs_f64());
+                    if delay > Duration::from_secs(0) {
+                        tokio::time::delay_for(delay).await;
+                    }
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Runs `fetch`, and if the session expired, logs in again with `login` and
+/// runs it once more. Fails with `JWLoginFailed` if logging in again fails,
+/// and with `SessionExpired` if the session expired again.
+async fn relogin_once<T, L, LFut, F, FFut>(login: L, fetch: F) -> Result<T, Error>
+where
+    L: FnOnce() -> LFut,
+    LFut: Future<Output = Result<(), Error>>,
+    F: Fn() -> FFut,
+    FFut: Future<Output = Result<T, Error>>,
+{
+    match fetch().await {
+        Err(Error::SessionExpired) => {
+            warn!("Jiaowu session expired, logging in again");
+            login().await?;
+            fetch().await
+        }
+        result => result,
+    }
+}
+
+/// The wait before retrying after `retries` retries, doubling from `base`
+fn retry_delay(base: Duration, retries: u32) -> Duration {
+    base * 2u32.pow(retries.min(16))
+}
+
+/// Builder of a fetch of a grade, which can be sent again and again, e.g.
+/// once every polling interval
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use ustc_get_grade::GradeRequest;
+/// # async fn fetch() -> Result<(), ustc_get_grade::Error> {
+/// let grade = GradeRequest::new("PB19000000", "password")
+///     .timeout(Duration::from_secs(30))
+///     .semesters(&["2019年秋季学期"])
+///     .send()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Every [`send`](#method.send) logs in with a new session, whose HTTP
+/// client is built from the per-session options: `timeout`, `proxy` and
+/// `http2`. `max_retries` and `retry_base_delay` decide how many sessions
+/// a send may take. The other options are per request, choosing what is
+/// asked of Jiaowu in the session: `semesters` or `semester_ids`,
+/// `train_type`, `per_semester_requests` and `fetch_rank`. Options left
+/// unset send the same requests as [`get_grade`](fn.get_grade.html).
+#[derive(Clone)]
+pub struct GradeRequest {
+    credentials: Credentials,
+    client: GradeClient,
+    semesters: Vec<String>,
+    semester_ids: Vec<usize>,
+    semester_names: HashMap<usize, String>,
+}
+
+/// The former name of [`GradeRequest`](struct.GradeRequest.html)
+#[deprecated(note = "renamed to `GradeRequest`")]
+pub type GradeFetcher = GradeRequest;
+
+impl std::fmt::Debug for GradeRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("GradeRequest")
+            .field("credentials", &self.credentials)
+            .field("client", &self.client)
+            .field("semesters", &self.semesters)
+            .field("semester_ids", &self.semester_ids)
+            .finish()
+    }
+}
+
+impl GradeRequest {
+    pub fn new(user: impl Into<String>, passwd: impl Into<String>) -> Self {
+        GradeRequest::with_credentials(Credentials::password(user, passwd))
+    }
+
+    /// Logs in with `credentials`, e.g. a TGC cookie instead of a password
+    pub fn with_credentials(credentials: Credentials) -> Self {
+        GradeRequest {
+            credentials,
+            client: GradeClient::default(),
+            semesters: Vec::new(),
+            semester_ids: Vec::new(),
+            semester_names: HashMap::new(),
+        }
+    }
+
+    /// Replaces all the client settings with `client`
+    pub fn client(mut self, client: GradeClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// See [`GradeClient::timeout`](struct.GradeClient.html#method.timeout)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.timeout(timeout);
+        self
+    }
+
+    /// See [`GradeClient::proxy`](struct.GradeClient.html#method.proxy)
+    pub fn proxy(mut self, url: &str) -> Self {
+        self.client = self.client.proxy(url);
+        self
+    }
+
+    /// See [`GradeClient::train_type`](struct.GradeClient.html#method.train_type)
+    pub fn train_type(mut self, train_type: u32) -> Self {
+        self.client = self.client.train_type(train_type);
+        self
+    }
+
+    /// See [`GradeClient::max_retries`](struct.GradeClient.html#method.max_retries)
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.client = self.client.max_retries(max_retries);
+        self
+    }
+
+    /// See [`GradeClient::retry_base_delay`](struct.GradeClient.html#method.retry_base_delay)
+    pub fn retry_base_delay(mut self, delay: Duration) -> Self {
+        self.client = self.client.retry_base_delay(delay);
+        self
+    }
+
+    /// See [`GradeClient::fetch_rank`](struct.GradeClient.html#method.fetch_rank)
+    pub fn fetch_rank(mut self, fetch_rank: bool) -> Self {
+        self.client = self.client.fetch_rank(fetch_rank);
+        self
+    }
+
+    /// See [`GradeClient::http2`](struct.GradeClient.html#method.http2)
+    pub fn http2(mut self, http2: bool) -> Self {
+        self.client = self.client.http2(http2);
+        self
+    }
+
+    /// See [`GradeClient::per_semester_requests`](struct.GradeClient.html#method.per_semester_requests)
+    pub fn per_semester_requests(mut self, per_semester_requests: bool) -> Self {
+        self.client = self.client.per_semester_requests(per_semester_requests);
+        self
+    }
+
+    /// Selects semesters by name, see [`ALL_SEMESTERS`](constant.ALL_SEMESTERS.html)
+    /// and [`CURRENT_SEMESTER`](constant.CURRENT_SEMESTER.html)
+    pub fn semesters(mut self, semesters: &[&str]) -> Self {
+        self.semesters = semesters.iter().map(|&s| s.to_owned()).collect();
+        self
+    }
+
+    /// Selects semesters by ID, see
+    /// [`GradeClient::get_grade_by_ids`](struct.GradeClient.html#method.get_grade_by_ids)
+    pub fn semester_ids(mut self, ids: &[usize], names: &HashMap<usize, String>) -> Self {
+        self.semester_ids = ids.to_vec();
+        self.semester_names = names.clone();
+        self
+    }
+
+    /// Checks the options without sending any request
+    pub fn validate(&self) -> Result<(), Error> {
+        match (self.semesters.is_empty(), self.semester_ids.is_empty()) {
+            (true, true) => return Err(Error::NoSemesters),
+            (false, false) => {
+                return Err(Error::ConflictingOptions(
+                    "semesters and semester_ids cannot both be set",
+                ))
+            }
+            _ => {}
+        }
+        self.client.http_client()?;
+        Ok(())
+    }
+
+    #[deprecated(note = "renamed to `send`")]
+    pub async fn fetch(&self) -> Result<Grade, Error> {
+        self.send().await
+    }
+
+    /// Logs in and gets the grade
+    pub async fn send(&self) -> Result<Grade, Error> {
+        self.validate()?;
+        if self.semester_ids.is_empty() {
+            let semesters: Vec<_> = self.semesters.iter().map(String::as_str).collect();
+            self.client.get_grade(&self.credentials, &semesters).await
+        } else {
+            self.client
+                .get_grade_by_ids(&self.credentials, &self.semester_ids, &self.semester_names)
+                .await
+        }
+    }
+}
+
+/// How `getGradeList` is requested
+#[derive(Clone, Copy)]
+struct ListQuery {
+    train_type: u32,
+    /// Sends a request for every selected semester
+    per_semester: bool,
+}
+
+/// Gets the grade of `semesters` with a logged in `client`
+async fn fetch_grade(
+    client: &Client,
+    jw: &str,
+    query: ListQuery,
+    semesters: &[&str],
+) -> Result<Grade, Error> {
+    if semesters.is_empty() {
+        return Err(Error::NoSemesters);
+    }
+
+    // Get semesters, whose `current` flags change between terms
+    let sems = if semesters == [CURRENT_SEMESTER] {
+        fetch_semesters(client, jw).await?
+    } else {
+        semesters_cached(client, jw).await?
+    };
+    info!("Semesters get");
+
+    let ids = select_semesters(&sems, semesters)?;
+    let sem_map = sems.iter().map(|s| (s.id, s.name_zh.clone())).collect();
+    fetch_grade_list(client, jw, query, ids.as_deref(), sem_map, &[]).await
+}
+
+/// Gets the grade of the ongoing semesters and their names with a logged in
+/// `client`
+async fn fetch_current_grade(
+    client: &Client,
+    jw: &str,
+    query: ListQuery,
+) -> Result<(Vec<String>, Grade), Error> {
+    let sems = fetch_semesters(client, jw).await?;
+    let current = current_semesters(&sems);
+    if current.is_empty() {
+        return Err(Error::NoSemesters);
+    }
+    let ids: Vec<_> = current.iter().map(|s| s.id).collect();
+    let names = current.iter().map(|s| s.name_zh.clone()).collect();
+    let sem_map = sems.iter().map(|s| (s.id, s.name_zh.clone())).collect();
+    let grade = fetch_grade_list(client, jw, query, Some(&ids), sem_map, &[]).await?;
+    Ok((names, grade))
+}
+
+/// Gets the grade of semesters `ids`, or all the semesters if `None`, with a
+/// logged in `client`. `sem_map` maps semester IDs to names, and semesters
+/// in `expected` missing from the grade list are added as empty ones.
+async fn fetch_grade_list(
+    client: &Client,
+    jw: &str,
+    query: ListQuery,
+    ids: Option<&[usize]>,
+    sem_map: HashMap<usize, String>,
+    expected: &[usize],
+) -> Result<Grade, Error> {
+    let grade_list = format!("{}/for-std/grade/sheet/getGradeList", jw);
+    let request = |ids: String| {
+        jw_json(client.get(&grade_list).query(&[
+            ("trainTypeId", query.train_type.to_string()),
+            ("semesterIds", ids),
+        ]))
+    };
+    let all = request(String::new());
+    let (all, sems) = match ids {
+        // The grade of all semesters is also that of the selection
+        None => {
+            let all = all.await?;
+            (all.clone(), vec![all])
+        }
+        Some(ids) if query.per_semester => {
+            let sems = try_join_all(ids.iter().map(|id| request(id.to_string())));
+            try_join(all, sems).await?
+        }
+        Some(ids) => {
+            let (all, sem) = try_join(all, request(ids.iter().join(","))).await?;
+            (all, vec![sem])
+        }
+    };
+    info!("Grade get");
+
+    extract_grade_expecting(all, &sems, sem_map, expected)
+}
+
+/// The body of a JSON response from Jiaowu, failing with `SessionExpired` if
+/// the request was redirected to the login page or the body is not JSON
+async fn jw_json(request: RequestBuilder) -> Result<String, Error> {
+    let res = request.send().await?;
+    let url = res.url().to_string();
+    let body = res.text().await?;
+    if session_expired(&url, &body) {
+        return Err(Error::SessionExpired);
+    }
+    Ok(body)
+}
+
+fn session_expired(url: &str, body: &str) -> bool {
+    url.contains("passport.ustc.edu.cn")
+        || url.contains("ucas-sso/login")
+        || !body.trim_start().starts_with(['{', '['])
+}
+
+/// Gets the rank in the major with a logged in `client`, `None` if it is
+/// not published
+async fn fetch_rank(client: &Client, jw: &str, train_type: u32) -> Result<Option<Rank>, Error> {
+    let json = client
+        .get(&format!("{}/for-std/grade/sheet/getStdRank", jw))
+        .query(&[("trainTypeId", train_type)])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    info!("Rank get");
+    extract_rank(&json)
+}
+
+/// Logs in to jw.ustc.edu.cn through passport.ustc.edu.cn, keeping the
+/// session in the cookies of `client`
+async fn login(
+    client: &Client,
+    passport: &str,
+    jw: &str,
+    credentials: &Credentials,
+) -> Result<(), Error> {
+    let service = format!("{}/ucas-sso/login", jw);
+    let (user, passwd) = match credentials {
+        Credentials::Password { user, passwd } => (user, passwd),
+        Credentials::Tgc(tgc) => return login_tgc(client, passport, &service, tgc).await,
+    };
+    let data = [
+        ("model", "uplogin.jsp"),
+        ("service", &service),
+        ("warn", ""),
+        ("showCode", ""),
+        ("username", user),
+        ("password", passwd),
+        ("button", ""),
+    ];
+
+    let throttle = throttle::login_throttle();
+    let wait = throttle.reserve(Instant::now());
+    if wait > Duration::from_secs(0) {
+        info!(
+            "Waiting {:.0} seconds before logging in",
+            wait.as_secs_f64()
+        );
+        tokio::time::delay_for(wait).await;
+    }
+
+    let res = client
+        .post(&format!("{}/login", passport))
+        .form(&data)
+        .send()
+        .await?
+        .error_for_status()?;
+    if !res.url().as_str().contains("/home") {
+        let error = login_failure(&res.text().await?);
+        if let Error::CaptchaRequired(_) = error {
+            warn!("Captcha required by passport, backing off");
+            throttle.captcha_detected(Instant::now());
+        }
+        return Err(error);
+    }
+    info!("Logined");
+    Ok(())
+}
+
+/// Logs in to `service` with the TGC cookie of an earlier passport login,
+/// which passport answers with a ticket for `service` instead of its form
+async fn login_tgc(client: &Client, passport: &str, service: &str, tgc: &str) -> Result<(), Error> {
+    let res = client
+        .get(&format!("{}/login", passport))
+        .query(&[("service", service)])
+        .header(reqwest::header::COOKIE, format!("TGC={}", tgc))
+        .send()
+        .await?
+        .error_for_status()?;
+    if res.url().as_str().starts_with(passport) {
+        return Err(Error::TgcExpired);
+    }
+    info!("Logined with the TGC cookie");
+    Ok(())
+}
+
+/// Extracts the overview from a `getGradeList` response, ignoring the
+/// `semesters` array
+pub fn extract_overview(json: &str) -> Result<OverviewData, Error> {
+    response::parse::<response::OverviewOnly>(json)?
+        .overview
+        .into_data()
+}
+
+/// Extracts the rank from a `getStdRank` response, `None` if it is not
+/// published
+pub fn extract_rank(json: &str) -> Result<Option<Rank>, Error> {
+    Ok(response::parse::<response::RankResponse>(json)?
+        .rank
+        .map(response::RankData::into_rank))
+}
+
+/// Checks that every semester in `requested` is in `sems`, or that it is
+/// [`ALL_SEMESTERS`](constant.ALL_SEMESTERS.html) or
+/// [`CURRENT_SEMESTER`](constant.CURRENT_SEMESTER.html) alone
+pub fn check_semesters(sems: &[SemesterInfo], requested: &[&str]) -> Result<(), Error> {
+    select_semesters(sems, requested).map(|_| ())
+}
+
+/// IDs of the `requested` semesters, `None` for all of them, failing if any
+/// of them does not exist. Semesters are requested by name or by ID.
+fn select_semesters(
+    sems: &[SemesterInfo],
+    requested: &[&str],
+) -> Result<Option<Vec<usize>>, Error> {
+    match requested {
+        [] => return Err(Error::NoSemesters),
+        [r] if *r == ALL_SEMESTERS => return Ok(None),
+        [r] if *r == CURRENT_SEMESTER => {
+            let current = current_semesters(sems);
+            if current.is_empty() {
+                return Err(Error::NoSemesters);
+            }
+            info!(
+                "Current semester is {}",
+                current.iter().map(|s| &s.name_zh).join(", ")
+            );
+            return Ok(Some(current.iter().map(|s| s.id).collect()));
+        }
+        _ => {}
+    }
+    let unknown: Vec<String> = requested
+        .iter()
+        .filter(|&&r| !sems.iter().any(|s| semester_matches(s, r)))
+        .map(|&r| r.to_owned())
+        .collect();
+    if !unknown.is_empty() {
+        return Err(Error::UnknownSemester {
+            requested: unknown,
+            available: sems.iter().map(|s| s.name_zh.clone()).collect(),
+        });
+    }
+
+    Ok(Some(
+        sems.iter()
+            .filter(|s| requested.iter().any(|r| semester_matches(s, r)))
+            .map(|s| s.id)
+            .collect(),
+    ))
+}
+
+/// Whether `requested` is the name or the ID of `sem`
+fn semester_matches(sem: &SemesterInfo, requested: &str) -> bool {
+    sem.name_zh == requested || requested.parse() == Ok(sem.id)
+}
+
+/// The IDs of `requested` if all of them are semester IDs
+pub fn semester_ids(requested: &[&str]) -> Option<Vec<usize>> {
+    requested.iter().map(|r| r.parse().ok()).collect()
+}
+
+/// The semesters marked as ongoing, of which there can be two during the
+/// summer term. Between terms, when none is marked, the latest one by school
+/// year and then by ID. Empty only if `sems` is.
+pub fn current_semesters(sems: &[SemesterInfo]) -> Vec<&SemesterInfo> {
+    let current: Vec<_> = sems.iter().filter(|s| s.current).collect();
+    if !current.is_empty() {
+        return current;
+    }
+    warn!("No semester is marked current, using the latest one");
+    sems.iter()
+        .max_by_key(|s| (&s.school_year, s.id))
+        .into_iter()
+        .collect()
+}
+
+/// Name of the semester with the highest ID, the latest one
+pub fn latest_semester_name(sems: &[SemesterInfo]) -> Option<&str> {
+    sems.iter().max_by_key(|s| s.id).map(|s| s.name_zh.as_str())
+}
+
+/// Name of the semester marked as ongoing, `None` between terms
+pub fn current_semester_name(sems: &[SemesterInfo]) -> Option<&str> {
+    sems.iter().find(|s| s.current).map(|s| s.name_zh.as_str())
+}
+
+/// Elements of the passport login page that show why a login failed
+static LOGIN_MESSAGE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)<(?:span|div|p|font)\b[^>]*\b(?:id|class)\s*=\s*["'][^"']*(?:msg|err)[^"']*["'][^>]*>(.*?)</(?:span|div|p|font)>"#,
+    )
+    .unwrap()
+});
+
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// The error message shown on the passport login page `page`, if any
+fn login_message(page: &str) -> Option<String> {
+    LOGIN_MESSAGE
+        .captures_iter(page)
+        .map(|c| TAG.replace_all(&c[1], " ").split_whitespace().join(" "))
+        .find(|m| !m.is_empty())
+}
+
+/// The error of a login answered with the passport login page `page`,
+/// telling a wrong password, a locked account and a captcha apart by the
+/// message on the page
+fn login_failure(page: &str) -> Error {
+    let message = match login_message(page) {
+        Some(message) => message,
+        None if captcha_required(page) => return Error::CaptchaRequired("请输入验证码".to_owned()),
+        None => return Error::JWLoginFailed,
+    };
+    if message.contains("验证码") {
+        Error::CaptchaRequired(message)
+    } else if ["锁定", "冻结", "次数过多"]
+        .iter()
+        .any(|k| message.contains(k))
+    {
+        Error::AccountLocked(message)
+    } else if message.contains("密码") {
+        Error::WrongPassword(message)
+    } else {
+        warn!("Unknown login error: {}", message);
+        Error::JWLoginFailed
+    }
+}
+
+fn captcha_required(page: &str) -> bool {
+    page.contains("验证码错误") || page.contains("请输入验证码")
+}
+
+fn extract_grade(
+    all: String,
+    sem: String,
+    sem_map: HashMap<usize, String>,
+) -> Result<Grade, Error> {
+    extract_grade_expecting(all, &[sem], sem_map, &[])
+}
+
+/// The grade in the responses of all and of the selected semesters, which
+/// are merged in order if they are fetched separately. Semesters missing
+/// from `sem_map` are named by their `nameZh`, or by their IDs if it is
+/// missing. Every semester in `expected` missing from the grade list, which
+/// is the case for unknown IDs and for semesters without any grade, is
+/// warned about and added as an empty semester.
+fn extract_grade_expecting(
+    all: String,
+    sems: &[String],
+    sem_map: HashMap<usize, String>,
+    expected: &[usize],
+) -> Result<Grade, Error> {
+    let all: response::GradeList = response::parse(&all)?;
+    let sems = sems
+        .iter()
+        .map(|sem| response::parse(sem))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sem = response::GradeList::merge(sems);
+
+    let name_of = |id: usize, name_zh: Option<String>| match sem_map.get(&id) {
+        Some(name) => name.clone(),
+        None => name_zh.unwrap_or_else(|| id.to_string()),
+    };
+    let missing: Vec<_> = expected
+        .iter()
+        .filter(|&&id| !sem.semesters.iter().any(|s| s.id == id))
+        .map(|&id| name_of(id, None))
+        .collect();
+    if !missing.is_empty() {
+        warn!(
+            "No grade in semesters {}, check that they exist",
+            missing.iter().join(", ")
+        );
+    }
+
+    let mut scores = Vec::new();
+    for s in sem.semesters {
+        let name = name_of(s.id, s.name_zh.clone());
+        scores.push(s.into_semester(name));
+    }
+    scores.extend(
+        missing
+            .into_iter()
+            .map(|name| Semester::new(name, Vec::new())),
+    );
+
+    Ok(Grade {
+        gpa: all.overview.gpa,
+        gpa_variants: all.overview.gpa_variants(),
+        sem_gpa: sem.overview.gpa,
+        credits: response::required(all.overview.passed_credits, "passedCredits")?,
+        scores,
+        rank: None,
+    })
+}
+
+/// Reads the grade from a recorded `getGradeList` response at `path`
+/// without any request, e.g. to try out notifications offline. Semesters
+/// are named by their `nameZh`, or by their IDs if it is missing.
+pub fn load_fixture(path: impl AsRef<Path>) -> Result<Grade, Error> {
+    let json = std::fs::read_to_string(path)?;
+    extract_grade(json.clone(), json, HashMap::new())
+}
+
+#[cfg(feature = "blocking")]
+pub mod blocking {
+    use super::{Credentials, Error, Grade, GradeClient, SemesterInfo};
+    use futures::Future;
+    use once_cell::sync::Lazy;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tokio::runtime::Runtime;
+
+    static RUNTIME: Lazy<Mutex<Runtime>> =
+        Lazy::new(|| Mutex::new(Runtime::new().expect("Unable to create Tokio runtime")));
+
+    /// Runs `future` to completion on a runtime shared by the whole process
+    ///
+    /// A tokio 0.2 runtime is only driven inside `Runtime::block_on`, which
+    /// needs exclusive access, so calls from different threads take turns.
+    /// Must not be called from an async context.
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        RUNTIME
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .block_on(future)
+    }
+
+    #[inline]
+    pub fn get_grade(user: &str, passwd: &str, semesters: &[&str]) -> Result<Grade, Error> {
+        get_grade_with(&GradeClient::default(), user, passwd, semesters)
+    }
+
+    /// Blocking version of [`GradeClient::get_grade`](../struct.GradeClient.html#method.get_grade)
+    pub fn get_grade_with(
+        client: &GradeClient,
+        user: &str,
+        passwd: &str,
+        semesters: &[&str],
+    ) -> Result<Grade, Error> {
+        block_on(client.get_grade(&Credentials::password(user, passwd), semesters))
+    }
+
+    /// A [`GradeClient`](../struct.GradeClient.html) whose methods block
+    /// until done, see [`block_on`](fn.block_on.html)
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct BlockingGradeClient {
+        client: GradeClient,
+    }
+
+    impl BlockingGradeClient {
+        pub fn new(client: GradeClient) -> Self {
+            BlockingGradeClient { client }
+        }
+
+        /// The wrapped client
+        pub fn inner(&self) -> &GradeClient {
+            &self.client
+        }
+
+        /// See [`GradeClient::get_grade`](../struct.GradeClient.html#method.get_grade)
+        pub fn get_grade(
+            &self,
+            credentials: &Credentials,
+            semesters: &[&str],
+        ) -> Result<Grade, Error> {
+            block_on(self.client.get_grade(credentials, semesters))
+        }
+
+        /// See [`GradeClient::get_grade_current`](../struct.GradeClient.html#method.get_grade_current)
+        pub fn get_grade_current(
+            &self,
+            credentials: &Credentials,
+        ) -> Result<(Vec<String>, Grade), Error> {
+            block_on(self.client.get_grade_current(credentials))
+        }
+
+        /// See [`GradeClient::get_grade_by_ids`](../struct.GradeClient.html#method.get_grade_by_ids)
+        pub fn get_grade_by_ids(
+            &self,
+            credentials: &Credentials,
+            semester_ids: &[usize],
+            semester_names: &HashMap<usize, String>,
+        ) -> Result<Grade, Error> {
+            block_on(
+                self.client
+                    .get_grade_by_ids(credentials, semester_ids, semester_names),
+            )
+        }
+
+        /// See [`GradeClient::check_login`](../struct.GradeClient.html#method.check_login)
+        pub fn check_login(&self, credentials: &Credentials) -> Result<(), Error> {
+            block_on(self.client.check_login(credentials))
+        }
+
+        /// See [`GradeClient::semesters`](../struct.GradeClient.html#method.semesters)
+        pub fn semesters(&self, credentials: &Credentials) -> Result<Vec<SemesterInfo>, Error> {
+            block_on(self.client.semesters(credentials))
+        }
+    }
+
+    impl From<GradeClient> for BlockingGradeClient {
+        fn from(client: GradeClient) -> Self {
+            BlockingGradeClient::new(client)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{build_client, GradeRequest};
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::thread;
+        use std::time::Duration;
+
+        #[test]
+        fn shared_runtime_drives_timers() {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    thread::spawn(move || {
+                        block_on(async move {
+                            tokio::time::delay_for(Duration::from_millis(10)).await;
+                            i
+                        })
+                    })
+                })
+                .collect();
+            let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            assert_eq!(results, [0, 1, 2, 3]);
+        }
+
+        /// Serves one request, echoing its User-Agent header as the body
+        fn echo_user_agent() -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let ua = BufReader::new(&stream)
+                    .lines()
+                    .map(Result::unwrap)
+                    .take_while(|line| !line.is_empty())
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        Some(value.trim().to_owned())
+                            .filter(|_| name.eq_ignore_ascii_case("user-agent"))
+                    })
+                    .unwrap_or_default();
+                write!(
+                    &stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    ua.len(),
+                    ua
+                )
+                .unwrap();
+            });
+            url
+        }
+
+        #[test]
+        fn custom_user_agent_is_sent() {
+            let grade_client = GradeClient::new("ustc-get-grade-test/1.0");
+            let client = build_client(grade_client.user_agent(), None).unwrap();
+            let url = echo_user_agent();
+            let ua = block_on(async { client.get(&url).send().await?.text().await }).unwrap();
+            assert_eq!(ua, "ustc-get-grade-test/1.0");
+        }
+
+        #[test]
+        fn given_http_client_is_used() {
+            let http = build_client("injected/1.0", None).unwrap();
+            let grade_client = GradeClient::new("ignored/1.0").with_http_client(http);
+            let client = grade_client.http_client().unwrap();
+            let url = echo_user_agent();
+            let ua = block_on(async { client.get(&url).send().await?.text().await }).unwrap();
+            assert_eq!(ua, "injected/1.0");
+
+            let blocking = BlockingGradeClient::from(grade_client.clone());
+            assert_eq!(blocking.inner(), &grade_client);
+            let other = GradeClient::new("ignored/1.0")
+                .with_http_client(build_client("injected/1.0", None).unwrap());
+            assert_ne!(grade_client, other);
+        }
+
+        #[test]
+        fn transient_errors_are_retried() {
+            use std::cell::Cell;
+
+            // Nothing listens on the port once the listener is dropped
+            let url = {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                format!("http://{}/", listener.local_addr().unwrap())
+            };
+            let grade_client = GradeClient::default()
+                .max_retries(2)
+                .retry_base_delay(Duration::from_millis(1));
+            let attempts = Cell::new(0);
+            let result = block_on(grade_client.retry(|| async {
+                attempts.set(attempts.get() + 1);
+                let client = grade_client.http_client()?;
+                Ok(client.get(&url).send().await?)
+            }));
+            assert!(result.unwrap_err().is_transient());
+            assert_eq!(attempts.get(), 3);
+
+            attempts.set(0);
+            let result: Result<(), _> = block_on(grade_client.retry(|| async {
+                attempts.set(attempts.get() + 1);
+                Err(Error::JWLoginFailed)
+            }));
+            assert!(!result.unwrap_err().is_transient());
+            assert_eq!(attempts.get(), 1);
+        }
+
+        /// Serves a request for each of `bodies` in turn, as HTML unless the
+        /// body is JSON
+        fn serve_bodies(bodies: Vec<&'static str>) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                for body in bodies {
+                    let (stream, _) = listener.accept().unwrap();
+                    BufReader::new(&stream)
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .for_each(drop);
+                    let content_type = if body.starts_with('{') {
+                        "application/json"
+                    } else {
+                        "text/html"
+                    };
+                    write!(
+                        &stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        content_type,
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            });
+            url
+        }
+
+        /// Writes each of `responses` in turn to a connection accepted on
+        /// `listener`, sending the request line and headers read on the
+        /// returned channel
+        fn serve_responses(
+            listener: TcpListener,
+            responses: Vec<String>,
+        ) -> std::sync::mpsc::Receiver<Vec<String>> {
+            let (tx, rx) = std::sync::mpsc::channel();
+            thread::spawn(move || {
+                for response in responses {
+                    let (stream, _) = listener.accept().unwrap();
+                    let request = BufReader::new(&stream)
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect();
+                    tx.send(request).unwrap();
+                    write!(&stream, "{}", response).unwrap();
+                }
+            });
+            rx
+        }
+
+        #[test]
+        fn tgc_login() {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let (passport, jw) = (format!("{}/passport", base), format!("{}/jw", base));
+            let response = |status: &str, location: &str, body: &str| {
+                format!(
+                    "HTTP/1.1 {}\r\nLocation: {}\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    status,
+                    location,
+                    body.len(),
+                    body
+                )
+            };
+            let credentials = Credentials::Tgc("TGT-1-abc".to_owned());
+
+            // Passport hands out a ticket, which Jiaowu turns into a session
+            let requests = serve_responses(
+                listener,
+                vec![
+                    response(
+                        "302 Found",
+                        &format!("{}/ucas-sso/login?ticket=ST-1", jw),
+                        "",
+                    ),
+                    response("302 Found", &format!("{}/home", jw), ""),
+                    response("200 OK", "", "home"),
+                ],
+            );
+            let client = build_client("ustc-get-grade-test/1.0", None).unwrap();
+            block_on(crate::login(&client, &passport, &jw, &credentials)).unwrap();
+            let first = requests.recv().unwrap();
+            assert!(
+                first[0].starts_with("GET /passport/login?service=http%3A%2F%2F127.0.0.1"),
+                "{:?}",
+                first
+            );
+            assert!(
+                first.iter().any(|h| h == "cookie: TGC=TGT-1-abc"),
+                "{:?}",
+                first
+            );
+            assert!(requests.recv().unwrap()[0].starts_with("GET /jw/ucas-sso/login?ticket=ST-1"));
+
+            // An expired TGC gets the login form
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let (passport, jw) = (format!("{}/passport", base), format!("{}/jw", base));
+            let _requests = serve_responses(
+                listener,
+                vec![response(
+                    "200 OK",
+                    "",
+                    "<html><title>统一身份认证</title></html>",
+                )],
+            );
+            let client = build_client("ustc-get-grade-test/1.0", None).unwrap();
+            let result = block_on(crate::login(&client, &passport, &jw, &credentials));
+            assert!(matches!(result, Err(Error::TgcExpired)), "{:?}", result);
+        }
+
+        /// Answers every connection accepted on `listener` with `route` of its
+        /// request line, sending the request lines on the returned channel
+        fn serve_routes(
+            listener: TcpListener,
+            route: impl Fn(&str) -> String + Send + 'static,
+        ) -> std::sync::mpsc::Receiver<String> {
+            let (tx, rx) = std::sync::mpsc::channel();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = stream.unwrap();
+                    let request: Vec<_> = BufReader::new(&stream)
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect();
+                    tx.send(request[0].clone()).unwrap();
+                    write!(&stream, "{}", route(&request[0])).unwrap();
+                }
+            });
+            rx
+        }
+
+        /// A client sending its requests to mock passport and Jiaowu servers,
+        /// which log in with any TGC and serve the grade of semester 121, and
+        /// the request lines they get in the order they are sent
+        fn mock_servers() -> (GradeClient, std::sync::mpsc::Receiver<String>) {
+            use crate::test_utils::mock_grade_response;
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let (passport, jw) = (format!("{}/passport", base), format!("{}/jw", base));
+            let response = |status: &str, location: &str, body: &str| {
+                format!(
+                    "HTTP/1.1 {}\r\nLocation: {}\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    status,
+                    location,
+                    body.len(),
+                    body
+                )
+            };
+            let jw_routes = jw.clone();
+            let requests = serve_routes(listener, move |request| {
+                let path = request.split(' ').nth(1).unwrap();
+                if path.starts_with("/passport/login?") {
+                    let ticket = format!("{}/ucas-sso/login?ticket=ST-1", jw_routes);
+                    response("302 Found", &ticket, "")
+                } else if path.starts_with("/jw/ucas-sso/login?") {
+                    response("302 Found", &format!("{}/home", jw_routes), "")
+                } else if path.ends_with("semesterIds=") {
+                    response("200 OK", "", &mock_grade_response(&[]))
+                } else if path.ends_with("semesterIds=121") {
+                    response("200 OK", "", &mock_grade_response(&["2019年秋季学期"]))
+                } else {
+                    response("200 OK", "", "home")
+                }
+            });
+            let http = build_client("ustc-get-grade-test/1.0", None).unwrap();
+            let client = GradeClient::default()
+                .with_http_client(http)
+                .servers(&passport, &jw);
+            (client, requests)
+        }
+
+        #[test]
+        fn get_grade_from_mock_servers() {
+            use crate::test_utils::{extract_grade, mock_grade_response};
+
+            let (grade_client, requests) = mock_servers();
+            let credentials = Credentials::Tgc("TGT-1-abc".to_owned());
+            let grade = block_on(grade_client.get_grade(&credentials, &["121"])).unwrap();
+            let expected = extract_grade(
+                mock_grade_response(&[]),
+                mock_grade_response(&["2019年秋季学期"]),
+                HashMap::new(),
+            )
+            .unwrap();
+            assert_eq!(grade, expected);
+
+            let mut paths: Vec<_> = requests.try_iter().collect();
+            paths.sort();
+            assert_eq!(
+                paths[..4],
+                [
+                    "GET /jw/for-std/grade/sheet/getGradeList?trainTypeId=1&semesterIds= HTTP/1.1",
+                    "GET /jw/for-std/grade/sheet/getGradeList?trainTypeId=1&semesterIds=121 HTTP/1.1",
+                    "GET /jw/home HTTP/1.1",
+                    "GET /jw/ucas-sso/login?ticket=ST-1 HTTP/1.1",
+                ]
+            );
+            assert!(paths[4].starts_with("GET /passport/login?service="));
+            assert_eq!(paths.len(), 5);
+        }
+
+        #[test]
+        fn request_defaults_send_the_same_requests() {
+            let credentials = Credentials::Tgc("TGT-1-abc".to_owned());
+            let sent = |send: &dyn Fn(GradeClient) -> Result<Grade, Error>| {
+                let (grade_client, requests) = mock_servers();
+                let grade = send(grade_client).unwrap();
+                // The service in the login request has the port of the server
+                let mut paths: Vec<_> = requests
+                    .try_iter()
+                    .map(|p| p.split("service=").next().unwrap().to_owned())
+                    .collect();
+                paths.sort();
+                (grade, paths)
+            };
+
+            let (grade, paths) = sent(&|client| block_on(client.get_grade(&credentials, &["121"])));
+            let request = |client| {
+                GradeRequest::with_credentials(credentials.clone())
+                    .client(client)
+                    .semesters(&["121"])
+            };
+            assert_eq!(
+                sent(&|client| block_on(request(client).send())),
+                (grade.clone(), paths.clone())
+            );
+
+            // Per-request options change the requests in the same session
+            let (_, train_type_2) = sent(&|client| block_on(request(client).train_type(2).send()));
+            assert_eq!(train_type_2.len(), paths.len());
+            assert!(
+                train_type_2[0].contains("trainTypeId=2&"),
+                "{:?}",
+                train_type_2
+            );
+        }
+
+        #[test]
+        fn expired_session_logs_in_again() {
+            use std::cell::Cell;
+
+            const LOGIN_PAGE: &str = "<html><title>统一身份认证</title></html>";
+            let client = build_client("ustc-get-grade-test/1.0", None).unwrap();
+            let logins = Cell::new(0);
+            let fetch = |url: String| {
+                let client = &client;
+                move || crate::jw_json(client.get(&url))
+            };
+
+            let url = serve_bodies(vec![LOGIN_PAGE, r#"{"overview": {}}"#]);
+            let result = block_on(crate::relogin_once(
+                || async {
+                    logins.set(logins.get() + 1);
+                    Ok(())
+                },
+                fetch(url),
+            ));
+            assert_eq!(result.unwrap(), r#"{"overview": {}}"#);
+            assert_eq!(logins.get(), 1);
+
+            let url = serve_bodies(vec![LOGIN_PAGE]);
+            let result = block_on(crate::relogin_once(
+                || async { Err(Error::JWLoginFailed) },
+                fetch(url),
+            ));
+            assert!(matches!(result, Err(Error::JWLoginFailed)));
+
+            logins.set(0);
+            let url = serve_bodies(vec![LOGIN_PAGE, LOGIN_PAGE]);
+            let result = block_on(crate::relogin_once(
+                || async {
+                    logins.set(logins.get() + 1);
+                    Ok(())
+                },
+                fetch(url),
+            ));
+            assert!(matches!(result, Err(Error::SessionExpired)));
+            assert_eq!(logins.get(), 1);
+
+            assert!(crate::session_expired(
+                "https://passport.ustc.edu.cn/login?service=https%3A%2F%2Fjw.ustc.edu.cn%2Fucas-sso%2Flogin",
+                "",
+            ));
+            assert!(!crate::session_expired(
+                "https://jw.ustc.edu.cn/for-std/grade/sheet/getGradeList",
+                " {\"overview\": {}}",
+            ));
+        }
+
+        #[test]
+        fn retry_delay_doubles() {
+            let base = Duration::from_secs(5);
+            assert_eq!(crate::retry_delay(base, 0), base);
+            assert_eq!(crate::retry_delay(base, 3), Duration::from_secs(40));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        assert_grade_eq, mock_grade, mock_grade_response, mock_sem_map, mock_semesters_response,
+        MOCK_GPA, MOCK_SEM_GPA,
+    };
+    use serde_json::json;
+
+    fn sem_map() -> HashMap<usize, String> {
+        mock_sem_map()
+    }
+
+    #[test]
+    fn course_names_normalized() {
+        assert_eq!(normalize_course_name("数学分析(B1)"), "数学分析(B1)");
+        assert_eq!(normalize_course_name("数学分析(B1) \n"), "数学分析(B1)");
+        assert_eq!(normalize_course_name("\u{3000}军事技能"), "军事技能");
+        assert_eq!(
+            normalize_course_name("Linear\u{a0}\u{3000} Algebra\t(B1)"),
+            "Linear Algebra (B1)"
+        );
+        assert_eq!(normalize_course_name(" \u{a0}"), "");
+
+        let scores = json!({
+            "overview": { "gpa": 2.0, "passedCredits": 6.0 },
+            "semesters": [{
+                "id": 121,
+                "scores": [
+                    { "courseNameCh": "数学分析(B2)\u{a0}", "scoreCh": "78", "credits": 6.0 },
+                ],
+            }],
+        })
+        .to_string();
+        let grade = extract_grade(scores.clone(), scores, sem_map()).unwrap();
+        assert_eq!(grade.scores[0].courses[0].name, "数学分析(B2)");
+    }
+
+    #[test]
+    fn failed_courses() {
+        let scores = json!({
+            "overview": { "gpa": 2.0, "passedCredits": 3.0 },
+            "semesters": [{
+                "id": 121,
+                "scores": [
+                    { "courseNameCh": "复变函数(B)", "scoreCh": "55", "credits": 3.0 },
+                    { "courseNameCh": "大学物理(B)", "scoreCh": "不及格", "credits": 4.0 },
+                    { "courseNameCh": "体育(1)", "scoreCh": "75", "credits": 1.0,
+                      "passed": false },
+                    { "courseNameCh": "数学分析(B1)", "scoreCh": "61", "credits": 6.0,
+                      "passed": true, "retake": true },
+                    { "courseNameCh": "线性代数(B1)", "scoreCh": "50", "credits": 4.0,
+                      "passed": true },
+                    { "courseNameCh": "军事技能", "scoreCh": "通过", "credits": 1.0 },
+                ],
+            }],
+        })
+        .to_string();
+        let grade = extract_grade(scores.clone(), scores, sem_map()).unwrap();
+        let statuses: Vec<_> = grade.all_courses().map(|c| c.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                CourseStatus::Failed,
+                CourseStatus::Failed,
+                CourseStatus::Failed,
+                CourseStatus::Retake,
+                CourseStatus::Normal,
+                CourseStatus::Normal,
+            ]
+        );
+        let failed: Vec<_> = grade.failed_courses().map(|c| c.name.as_str()).collect();
+        assert_eq!(failed, ["复变函数(B)", "大学物理(B)", "体育(1)"]);
+
+        assert_eq!(CourseStatus::from_score("不通过"), CourseStatus::Failed);
+        assert_eq!(CourseStatus::from_score("F"), CourseStatus::Failed);
+        assert_eq!(CourseStatus::from_score("未通过"), CourseStatus::Failed);
+        assert_eq!(CourseStatus::from_score("缓考"), CourseStatus::Normal);
+        assert_eq!(mock_grade(&[]).failed_courses().count(), 0);
+    }
+
+    #[test]
+    fn search_by_name() {
+        let grade = mock_grade(&[]);
+        let names = |query: &str| -> Vec<_> {
+            search_courses(&grade, query)
+                .into_iter()
+                .map(|(semester, c)| (semester, c.name.as_str()))
+                .collect()
+        };
+        assert_eq!(
+            names("数学"),
+            [
+                ("2019年春季学期", "数学分析(B1)"),
+                ("2019年秋季学期", "数学分析(B2)"),
+            ]
+        );
+        assert_eq!(
+            names("基础实验a"),
+            [("2019年秋季学期", "大学物理-基础实验A")]
+        );
+        assert_eq!(names("分析(B").len(), 2);
+        assert!(names("化学").is_empty());
+        assert_eq!(names("").len(), grade.total_courses());
+        #[cfg(feature = "regex-search")]
+        assert_eq!(
+            names("^(线性|军事)"),
+            [
+                ("2019年春季学期", "线性代数(B1)"),
+                ("2019年夏季学期", "军事技能")
+            ]
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut grade = mock_grade(&[]);
+        grade.rank = Some(Rank::new(12, 143, "专业"));
+        grade.scores[2].courses[0].graded_at = Some("2020-01-15 10:23:45".to_owned());
+        let json = serde_json::to_string(&grade).unwrap();
+        assert_eq!(Grade::from_json(&json).unwrap(), grade);
+
+        let json = json.replacen(r#""letter":"A""#, r#""letter":"Z""#, 1);
+        match Grade::from_json(&json) {
+            Err(Error::GradeMalformed(message)) => {
+                assert!(message.starts_with("unknown letter `Z`"), "{}", message)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(matches!(
+            Grade::from_json(r#"{"gpa": 3.5}"#),
+            Err(Error::GradeMalformed(_))
+        ));
+    }
+
+    #[test]
+    fn grading_modes() {
+        let cases = [
+            ("78", GradingMode::Numeric),
+            (" 100 ", GradingMode::Numeric),
+            ("优秀", GradingMode::Chinese),
+            ("不及格", GradingMode::Chinese),
+            ("A−", GradingMode::Letter),
+            ("F", GradingMode::Letter),
+            ("通过", GradingMode::PassFail),
+            ("未通过", GradingMode::PassFail),
+            ("P", GradingMode::PassFail),
+            ("缓考", GradingMode::Unknown),
+            ("NaN", GradingMode::Unknown),
+        ];
+        for &(score, mode) in cases.iter() {
+            assert_eq!(GradingMode::from_score(score), mode, "{}", score);
+        }
+
+        let course = CourseGrade::new("军事技能", "通过", 1.0);
+        assert!(course.is_pass_fail());
+        assert_eq!(course.numeric_score(), None);
+        let courses = vec![course, CourseGrade::new("数学分析(B2)", "78", 6.0)];
+        assert_eq!(semester_stats(&courses).unwrap().mean, 78.);
+    }
+
+    #[test]
+    fn grade_builder() {
+        let grade = Grade::builder()
+            .gpa(MOCK_GPA)
+            .sem_gpa(MOCK_GPA)
+            .credits(18.5)
+            .add_semester(
+                "2019年春季学期",
+                vec![
+                    CourseGrade::new("数学分析(B1)", "92", 6.0),
+                    CourseGrade::new("线性代数(B1)", "85", 4.0),
+                ],
+            )
+            .add_semester(
+                "2019年夏季学期",
+                vec![CourseGrade::new("军事技能", "通过", 1.0)],
+            )
+            .add_semester(
+                "2019年秋季学期",
+                vec![
+                    CourseGrade::new("数学分析(B2)", "78", 6.0),
+                    CourseGrade::new("大学物理-基础实验A", "优秀", 1.5),
+                ],
+            );
+        assert_eq!(grade.passed_credits(), 18.5);
+        assert_eq!(grade.build(), mock_grade(&[]));
+
+        let failed = Grade::builder().add_semester(
+            "2019年秋季学期",
+            vec![CourseGrade::new("复变函数(B)", "不及格", 3.0)],
+        );
+        assert_eq!(failed.passed_credits(), 0.);
+        let empty = GradeBuilder::default().build();
+        assert_eq!(
+            (empty.gpa, empty.credits, empty.scores.len()),
+            (None, 0., 0)
+        );
+    }
+
+    #[test]
+    fn extract_selected_semesters() {
+        let grade = mock_grade(&["2019年春季学期", "2019年秋季学期"]);
+        assert_eq!(grade.gpa, Some(MOCK_GPA));
+        assert_eq!(grade.sem_gpa, Some(MOCK_SEM_GPA));
+        assert_eq!(grade.credits, 18.5);
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2019年春季学期", "2019年秋季学期"]);
+        assert_eq!(
+            grade.scores[0].courses,
+            [
+                CourseGrade::new("数学分析(B1)", "92", 6.0),
+                CourseGrade::new("线性代数(B1)", "85", 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_all_semesters() {
+        let grade = mock_grade(&[]);
+        assert_eq!(grade.sem_gpa, grade.gpa);
+        assert_eq!(grade.scores.len(), 3);
+    }
+
+    #[test]
+    fn extract_non_numeric_scores() {
+        let grade = mock_grade(&["2019年夏季学期"]);
+        assert_eq!(
+            grade.scores[0].courses,
+            [CourseGrade::new("军事技能", "通过", 1.0)]
+        );
+    }
+
+    #[test]
+    fn extract_empty_semester_list() {
+        let sem = json!({ "overview": { "gpa": 0.0 }, "semesters": [] }).to_string();
+        let grade = extract_grade(mock_grade_response(&[]), sem, sem_map()).unwrap();
+        assert!(grade.scores.is_empty());
+        assert_eq!(grade.sem_gpa, Some(0.0));
+    }
+
+    #[test]
+    fn extract_unpublished_gpa() {
+        let all = json!({
+            "overview": { "gpa": null, "passedCredits": 0.0 },
+            "semesters": [],
+        })
+        .to_string();
+        let grade = extract_grade(all.clone(), all, sem_map()).unwrap();
+        assert_eq!((grade.gpa, grade.sem_gpa), (None, None));
+
+        let published = mock_grade(&[]);
+        assert_eq!(grade.gpa_change(&published), None);
+        assert_eq!(published.gpa_change(&published), Some(0.));
+        assert_eq!(grade.gpa_change(&grade), Some(0.));
+    }
+
+    #[test]
+    fn extract_published_rank() {
+        let json = json!({ "rank": { "position": 12, "total": 143, "scopeZh": "专业" } });
+        let rank = extract_rank(&json.to_string()).unwrap().unwrap();
+        assert_eq!(rank, Rank::new(12, 143, "专业"));
+        assert_eq!(rank.to_string(), "12/143");
+
+        assert_eq!(extract_rank(r#"{"rank": null}"#).unwrap(), None);
+        assert!(matches!(
+            extract_rank(r#"{"rank": {"position": 12}}"#),
+            Err(Error::GradeMalformed(_))
+        ));
+    }
+
+    #[test]
+    fn extract_is_deterministic() {
+        let sems = ["2019年秋季学期"];
+        assert_grade_eq(&mock_grade(&sems), &mock_grade(&sems));
+    }
+
+    #[test]
+    fn total_courses() {
+        assert_eq!(mock_grade(&[]).total_courses(), 5);
+        assert_eq!(mock_grade(&["2019年秋季学期"]).total_courses(), 2);
+    }
+
+    #[test]
+    fn semester_by_name() {
+        let grade = mock_grade(&[]);
+        let sem = grade.semester("2019年夏季学期").unwrap();
+        assert_eq!(sem.courses, [CourseGrade::new("军事技能", "通过", 1.0)]);
+        assert!(grade.semester("2020年春季学期").is_none());
+    }
+
+    #[test]
+    fn all_courses_in_order() {
+        let grade = mock_grade(&["2019年春季学期", "2019年秋季学期"]);
+        let names: Vec<_> = grade.all_courses().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "数学分析(B1)",
+                "线性代数(B1)",
+                "数学分析(B2)",
+                "大学物理-基础实验A"
+            ]
+        );
+    }
+
+    #[test]
+    fn fixture_loaded() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/sample_grade.json");
+        let grade = load_fixture(path).unwrap();
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2019年春季学期", "2019年秋季学期"]);
+        assert_eq!(grade.gpa, Some(3.61));
+        assert_eq!(grade.total_courses(), 5);
+
+        match load_fixture("no/such/fixture.json") {
+            Err(Error::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn course_by_name() {
+        let grade = mock_grade(&["2019年春季学期", "2019年秋季学期"]);
+        let course = grade.grade_for_course("数学分析(B2)").unwrap();
+        assert_eq!(course.score, "78");
+        assert!(grade.has_grade_for_course("线性代数(B1)"));
+        assert!(!grade.has_grade_for_course("军事技能"));
+        assert!(!grade.has_grade_for_course("数学分析"));
+    }
+
+    #[test]
+    fn gpa_changes() {
+        let old = mock_grade(&["2019年秋季学期"]);
+        let mut new = old.clone();
+        new.gpa = Some(3.85);
+        new.sem_gpa = Some(3.1);
+        assert_eq!(old.gpa_change(&new).map(round2), Some(0.33));
+        assert_eq!(old.sem_gpa_change(&new).map(round2), Some(-0.2));
+        assert_eq!(new.gpa_change(&old).map(round2), Some(-0.33));
+        assert_eq!(old.gpa_change(&old), Some(0.0));
+    }
+
+    #[test]
+    fn build_client_with_timeout() {
+        assert!(build_client(DEFAULT_UA, None).is_ok());
+        assert!(build_client("ustc-get-grade", Some(Duration::from_secs(10))).is_ok());
+    }
+
+    #[test]
+    fn grade_client_user_agent() {
+        assert_eq!(GradeClient::default().user_agent(), DEFAULT_UA);
+        assert_eq!(GradeClient::new("foo/1.0").user_agent(), "foo/1.0");
+    }
+
+    #[test]
+    fn grade_client_base_path() {
+        assert_eq!(GradeClient::default().jw_url(), "https://jw.ustc.edu.cn");
+        for path in &["grade-api", "/grade-api", "/grade-api/"] {
+            let client = GradeClient::default().base_path(path);
+            assert_eq!(client.jw_url(), "https://jw.ustc.edu.cn/grade-api");
+        }
+        let client = GradeClient::default().base_path("/");
+        assert_eq!(client.jw_url(), "https://jw.ustc.edu.cn");
+    }
+
+    /// Returns `Pending` once before completing, so that other futures get
+    /// polled in between
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<()> {
+            if self.0 {
+                std::task::Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn concurrency_is_bounded() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let accounts: Vec<String> = (0..10).map(|i| format!("PB1900000{}", i)).collect();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let fetched = futures::executor::block_on(map_concurrently(&accounts, 3, |user| {
+            let (running, max_running) = (&running, &max_running);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                YieldNow(false).await;
+                YieldNow(false).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                format!("grade of {}", user)
+            }
+        }));
+
+        let expected: Vec<_> = accounts.iter().map(|u| format!("grade of {}", u)).collect();
+        assert_eq!(fetched, expected);
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn concurrent_requests_in_order() {
+        let request = ConcurrentRequest::new("PB19000000", "password", &["2019年秋季学期"]);
+        assert!(!format!("{:?}", request).contains("password"));
+
+        // Fetches without semesters fail before sending any request
+        let requests = vec![
+            ConcurrentRequest::new("PB19000000", "password", &[]),
+            ConcurrentRequest::new("PB19000001", "password", &[]),
+        ];
+        let results = futures::executor::block_on(get_grade_concurrent(requests, 0));
+        assert_eq!(results.len(), 2);
+        for result in results {
+            match result {
+                Err(Error::NoSemesters) => {}
+                r => panic!("unexpected result: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn gpa_weighted_by_credits() {
+        let grade = mock_grade(&[]);
+        // 92 (A, 4.0) with 6 credits and 85 (A−, 3.7) with 4
+        let gpa = calculate_gpa(&grade.scores[0].courses).unwrap();
+        assert!((gpa - 3.88).abs() < 1e-9, "{}", gpa);
+        // "通过" has no letter
+        assert_eq!(calculate_gpa(&grade.scores[1].courses), None);
+        assert_eq!(calculate_gpa(&Vec::new()), None);
+    }
+
+    #[test]
+    fn simple_average_ignores_credits() {
+        let grade = mock_grade(&["2019年春季学期"]);
+        assert_eq!(simple_average(&grade.scores[0].courses), Some(88.5));
+        assert_eq!(
+            round2(simple_average(&courses(&["78", "优秀", "93"])).unwrap()),
+            85.5
+        );
+        assert_eq!(simple_average(&courses(&["通过", "优秀"])), None);
+        assert_eq!(simple_average(&Vec::new()), None);
+    }
+
+    #[test]
+    fn numeric_letters() {
+        let cases = [
+            ("100", "A+"),
+            ("95", "A+"),
+            ("94", "A"),
+            ("90", "A"),
+            ("89", "A−"),
+            ("85", "A−"),
+            ("84", "B+"),
+            ("80", "B+"),
+            ("79", "B"),
+            ("75", "B"),
+            ("74", "B−"),
+            ("70", "B−"),
+            ("69", "C+"),
+            ("67", "C+"),
+            ("66", "C"),
+            ("63", "C"),
+            ("62", "D"),
+            ("60", "D"),
+            ("59.5", "F"),
+            ("0", "F"),
+            (" 88 ", "A−"),
+        ];
+        for &(score, letter) in cases.iter() {
+            assert_eq!(score_to_letter(score), Some(letter), "score {}", score);
+        }
+    }
+
+    #[test]
+    fn chinese_letters() {
+        assert_eq!(score_to_letter("优秀"), Some("A"));
+        assert_eq!(score_to_letter("良好"), Some("B+"));
+        assert_eq!(score_to_letter("中等"), Some("C+"));
+        assert_eq!(score_to_letter("及格"), Some("D"));
+        assert_eq!(score_to_letter("不及格"), Some("F"));
+    }
+
+    #[test]
+    fn no_letter() {
+        for score in &["通过", "", "101", "-1", "NaN", "缓考"] {
+            assert_eq!(score_to_letter(score), None, "score {}", score);
+        }
+        assert_eq!(CourseGrade::new("军事技能", "通过", 1.0).letter, None);
+        assert_eq!(
+            CourseGrade::new("数学分析(B1)", "92", 6.0).letter,
+            Some("A")
+        );
+    }
+
+    #[test]
+    fn grade_clone() {
+        let grade = mock_grade(&[]);
+        assert_eq!(grade.clone(), grade);
+    }
+
+    #[test]
+    fn missing_overview() {
+        let all = json!({ "semesters": [] }).to_string();
+        assert!(extract_grade(all, mock_grade_response(&[]), sem_map()).is_err());
+    }
+
+    #[test]
+    fn missing_course_field() {
+        let sem = json!({
+            "overview": { "gpa": 4.3 },
+            "semesters": [{ "id": 101, "scores": [{ "courseNameCh": "数学分析(B1)", "scoreCh": "92" }] }],
+        })
+        .to_string();
+        assert!(extract_grade(mock_grade_response(&[]), sem, sem_map()).is_err());
+    }
+
+    #[test]
+    fn null_score() {
+        let sem = json!({
+            "overview": { "gpa": 4.3 },
+            "semesters": [{ "id": 101, "scores": [{ "courseNameCh": "数学分析(B1)", "scoreCh": null, "credits": 6.0 }] }],
+        })
+        .to_string();
+        assert!(extract_grade(mock_grade_response(&[]), sem, sem_map()).is_err());
+    }
+
+    #[test]
+    fn unknown_semester_id() {
+        let sem = json!({
+            "overview": { "gpa": 4.3 },
+            "semesters": [{ "id": 998, "nameZh": "2020年春季学期", "scores": [] }, { "id": 999, "scores": [] }],
+        })
+        .to_string();
+        let grade = extract_grade(mock_grade_response(&[]), sem.clone(), sem_map()).unwrap();
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2020年春季学期", "999"]);
+
+        let grade =
+            extract_grade_expecting(mock_grade_response(&[]), &[sem], sem_map(), &[101, 998, 7])
+                .unwrap();
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2020年春季学期", "999", "2019年春季学期", "7"]);
+        assert!(grade.semester("7").unwrap().courses.is_empty());
+    }
+
+    #[test]
+    fn gpa_variants() {
+        assert_eq!(
+            mock_grade(&[]).gpa_variants,
+            [("gpa".to_owned(), MOCK_GPA)].iter().cloned().collect()
+        );
+
+        let all = json!({
+            "overview": {
+                "gpa": 3.52,
+                "bestAttemptGpa": 3.61,
+                "gpaRank": null,
+                "passedCredits": 18.5,
+                "failedCredits": 0.0,
+                "passedCourseCount": 4,
+            },
+            "semesters": [],
+        })
+        .to_string();
+        let grade = extract_grade(all.clone(), all, sem_map()).unwrap();
+        assert_eq!(grade.gpa, Some(3.52));
+        assert_eq!(grade.gpa_variants.len(), 2);
+        assert_eq!(grade.gpa_variants["gpa"], 3.52);
+        assert_eq!(grade.gpa_variants["bestAttemptGpa"], 3.61);
+        assert_eq!(grade.other_gpas(), [("bestAttemptGpa", 3.61)]);
+    }
+
+    #[test]
+    fn merge_semester_responses() {
+        let semester = |id: usize, gpa: Option<f64>, scores: &[(&str, &str, f64)]| {
+            let scores: Vec<_> = scores
+                .iter()
+                .map(|&(name, score, credits)| {
+                    json!({ "courseNameCh": name, "scoreCh": score, "credits": credits })
+                })
+                .collect();
+            json!({
+                "overview": { "gpa": gpa, "passedCredits": 1.0, "failedCredits": 0.0, "passedCourseCount": 1 },
+                "semesters": [{ "id": id, "scores": scores }],
+            })
+            .to_string()
+        };
+        let sems = [
+            semester(
+                101,
+                Some(4.0),
+                &[("数学分析(B1)", "95", 6.0), ("体育(1)", "通过", 1.0)],
+            ),
+            semester(102, None, &[]),
+            semester(121, Some(3.0), &[("线性代数(B1)", "80", 2.0)]),
+        ];
+        let grade =
+            extract_grade_expecting(mock_grade_response(&[]), &sems, sem_map(), &[]).unwrap();
+
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["2019年春季学期", "2019年夏季学期", "2019年秋季学期"]
+        );
+        assert_eq!(grade.total_courses(), 3);
+        assert_eq!(grade.gpa, Some(MOCK_GPA));
+        assert_eq!(grade.sem_gpa, None);
+
+        let grade =
+            extract_grade_expecting(mock_grade_response(&[]), &sems[2..], sem_map(), &[]).unwrap();
+        assert_eq!(grade.sem_gpa, Some(3.0));
+    }
+
+    #[test]
+    fn invalid_json() {
+        let all = "<html>login</html>".to_owned();
+        assert!(extract_grade(all, mock_grade_response(&[]), sem_map()).is_err());
+    }
+
+    #[test]
+    fn overview() {
+        let overview = extract_overview(&mock_grade_response(&[])).unwrap();
+        assert_eq!(
+            overview,
+            OverviewData {
+                gpa: Some(MOCK_GPA),
+                passed_credits: 18.5,
+                failed_credits: 0.0,
+                passed_course_count: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn overview_missing_field() {
+        let json = json!({ "overview": { "gpa": 4.3 } }).to_string();
+        match extract_overview(&json) {
+            Err(Error::GradeMalformed(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn login_failures() {
+        let failure = |page| match login_failure(page) {
+            Error::WrongPassword(m) => format!("wrong password: {}", m),
+            Error::AccountLocked(m) => format!("locked: {}", m),
+            Error::CaptchaRequired(m) => format!("captcha: {}", m),
+            e => e.to_string(),
+        };
+        assert_eq!(
+            failure(include_str!("../tests/fixtures/cas_wrong_password.html")),
+            "wrong password: 用户名或密码错误"
+        );
+        assert_eq!(
+            failure(include_str!("../tests/fixtures/cas_locked.html")),
+            "locked: 登录失败次数过多，账号已被锁定，请 30 分钟后再试"
+        );
+        assert_eq!(
+            failure(include_str!("../tests/fixtures/cas_captcha.html")),
+            "captcha: 验证码错误"
+        );
+        assert_eq!(
+            failure("<html><title>统一身份认证</title></html>"),
+            "Jiaowu login failed"
+        );
+        assert!(Error::WrongPassword(String::new()).is_credentials_rejected());
+        assert!(!Error::CaptchaRequired(String::new()).is_credentials_rejected());
+    }
+
+    #[test]
+    fn semesters_response() {
+        let sems: Vec<SemesterInfo> = serde_json::from_str(&mock_semesters_response()).unwrap();
+        assert_eq!(sems.len(), 3);
+        assert_eq!(sems[2].name_zh, "2019年秋季学期");
+        assert!(sems[2].current);
+    }
+
+    #[test]
+    fn cached_semesters() {
+        *semester_cache() = Some(mock_semesters());
+        let client = Client::new();
+        let sems = futures::executor::block_on(get_available_semesters_cached(&client));
+        assert_eq!(sems.unwrap(), mock_semesters());
+
+        clear_semester_cache();
+        assert!(semester_cache().is_none());
+    }
+
+    #[test]
+    fn numeric_scores() {
+        assert_eq!(numeric_score("92"), Some(92.));
+        assert_eq!(numeric_score(" 60.5 "), Some(60.5));
+        assert_eq!(numeric_score("101"), None);
+        assert_eq!(numeric_score("优"), Some(95.));
+        assert_eq!(numeric_score("良好"), Some(85.));
+        assert_eq!(numeric_score("A-"), Some(87.));
+        assert_eq!(numeric_score("F"), Some(30.));
+        assert_eq!(numeric_score("通过"), None);
+        assert_eq!(numeric_score("P"), None);
+    }
+
+    #[test]
+    fn sort_by_score() {
+        let semester = Semester::new("2019年秋季学期", courses(&["通过", "78", "优秀", "A"]));
+        let sorted: Vec<_> = semester
+            .sorted_by_score()
+            .iter()
+            .map(|c| c.score.as_str())
+            .collect();
+        assert_eq!(sorted, ["优秀", "A", "78", "通过"]);
+    }
+
+    fn courses(scores: &[&str]) -> SemesterGrade {
+        scores
+            .iter()
+            .map(|s| CourseGrade::new("课程", *s, 1.0))
+            .collect()
+    }
+
+    fn round2(x: f64) -> f64 {
+        (x * 100.).round() / 100.
+    }
+
+    #[test]
+    fn stats_odd_count() {
+        let stats = semester_stats(&courses(&["78", "92", "85"])).unwrap();
+        assert_eq!(round2(stats.mean), 85.0);
+        assert_eq!(round2(stats.median), 85.0);
+        assert_eq!(round2(stats.std_dev), 5.72);
+        assert_eq!((stats.min, stats.max), (78.0, 92.0));
+    }
+
+    #[test]
+    fn stats_even_count() {
+        let stats = semester_stats(&courses(&["90", "60", "80", "70"])).unwrap();
+        assert_eq!(round2(stats.mean), 75.0);
+        assert_eq!(round2(stats.median), 75.0);
+        assert_eq!(round2(stats.std_dev), 11.18);
+        assert_eq!((stats.min, stats.max), (60.0, 90.0));
+    }
+
+    #[test]
+    fn stats_ignore_non_numeric() {
+        let stats = semester_stats(&courses(&["优秀", "88", "通过", "NaN"])).unwrap();
+        assert_eq!(stats.mean, 88.0);
+        assert_eq!(stats.std_dev, 0.0);
+        assert!(semester_stats(&courses(&["优秀", "通过"])).is_none());
+        assert!(semester_stats(&courses(&[])).is_none());
+    }
+
+    fn mock_semesters() -> Vec<SemesterInfo> {
+        serde_json::from_str(&mock_semesters_response()).unwrap()
+    }
+
+    #[test]
+    fn select_known_semesters() {
+        let ids = select_semesters(&mock_semesters(), &["2019年秋季学期", "2019年春季学期"]);
+        assert_eq!(ids.unwrap().unwrap(), [101, 121]);
+    }
+
+    #[test]
+    fn select_semesters_by_id() {
+        let ids = select_semesters(&mock_semesters(), &["121", "2019年春季学期"]);
+        assert_eq!(ids.unwrap().unwrap(), [101, 121]);
+        assert!(select_semesters(&mock_semesters(), &["999"]).is_err());
+
+        assert_eq!(semester_ids(&["121", "101"]), Some(vec![121, 101]));
+        assert_eq!(semester_ids(&["121", "2019年春季学期"]), None);
+        assert_eq!(semester_ids(&[ALL_SEMESTERS]), None);
+    }
+
+    #[test]
+    fn select_all_semesters() {
+        let ids = select_semesters(&mock_semesters(), &[ALL_SEMESTERS]);
+        assert_eq!(ids.unwrap(), None);
+        match select_semesters(&mock_semesters(), &[]) {
+            Err(Error::NoSemesters) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn select_current_semester() {
+        let sems = |current: [bool; 3]| -> Vec<SemesterInfo> {
+            let mut sems = mock_semesters();
+            for (s, c) in sems.iter_mut().zip(current.iter()) {
+                s.current = *c;
+            }
+            sems
+        };
+        let ids = |sems: &[SemesterInfo]| -> Vec<usize> {
+            current_semesters(sems).iter().map(|s| s.id).collect()
+        };
+
+        let one = sems([false, false, true]);
+        assert_eq!(ids(&one), [121]);
+        let ids_selected = select_semesters(&one, &[CURRENT_SEMESTER]);
+        assert_eq!(ids_selected.unwrap().unwrap(), [121]);
+
+        // The spring semester stays current during the summer term
+        let summer = sems([true, true, false]);
+        assert_eq!(ids(&summer), [101, 102]);
+        let ids_selected = select_semesters(&summer, &[CURRENT_SEMESTER]);
+        assert_eq!(ids_selected.unwrap().unwrap(), [101, 102]);
+
+        let mut none = sems([false, false, false]);
+        assert_eq!(ids(&none), [121]);
+        none.swap(0, 2);
+        assert_eq!(ids(&none), [121]);
+
+        let mut sems = none;
+        sems.truncate(0);
+        assert!(matches!(
+            select_semesters(&sems, &[CURRENT_SEMESTER]),
+            Err(Error::NoSemesters)
+        ));
+    }
+
+    #[test]
+    fn semester_names() {
+        let mut sems = mock_semesters();
+        assert_eq!(latest_semester_name(&sems), Some("2019年秋季学期"));
+        assert_eq!(current_semester_name(&sems), Some("2019年秋季学期"));
+        sems[2].current = false;
+        sems.swap(0, 2);
+        assert_eq!(latest_semester_name(&sems), Some("2019年秋季学期"));
+        assert_eq!(current_semester_name(&sems), None);
+        assert_eq!(latest_semester_name(&[]), None);
+    }
+
+    #[test]
+    fn meaningful_grade() {
+        assert!(is_grade_meaningful(&mock_grade(&[])));
+        let mut grade = mock_grade(&["2019年秋季学期"]);
+        for course in &mut grade.scores[0].courses {
+            course.score = " ".to_owned();
+        }
+        assert!(!is_grade_meaningful(&grade));
+        grade.scores.clear();
+        assert!(!is_grade_meaningful(&grade));
+    }
+
+    #[test]
+    fn graduate_semesters() {
+        assert!(is_graduate_semester("2020年研究生秋季学期"));
+        assert!(is_graduate_semester("研究生2021年春季学期"));
+        assert!(!is_graduate_semester("2019年秋季学期"));
+        assert!(!mock_semesters().iter().any(SemesterInfo::is_graduate));
+    }
+
+    #[test]
+    fn fetcher_defaults() {
+        let fetcher = GradeRequest::new("PB19000000", "password");
+        assert_eq!(fetcher.client, GradeClient::default());
+        assert_eq!(fetcher.client.train_type, 1);
+        assert_eq!(fetcher.client.proxy, None);
+        assert!(!fetcher.client.http2);
+        assert!(!format!("{:?}", fetcher).contains("password"));
+
+        let fetcher = fetcher
+            .timeout(Duration::from_secs(10))
+            .proxy("socks5://127.0.0.1:1080")
+            .train_type(2)
+            .http2(true);
+        assert_eq!(fetcher.client.timeout, Some(Duration::from_secs(10)));
+        assert_eq!(fetcher.client.train_type, 2);
+        assert!(fetcher.client.http2);
+        assert!(GradeClient::default().http2(true).http_client().is_ok());
+    }
+
+    #[test]
+    fn fetcher_validation() {
+        let fetcher = GradeRequest::new("PB19000000", "password");
+        match fetcher.validate() {
+            Err(Error::NoSemesters) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let fetcher = fetcher
+            .semesters(&["2019年秋季学期"])
+            .semester_ids(&[121], &HashMap::new());
+        match futures::executor::block_on(fetcher.send()) {
+            Err(Error::ConflictingOptions(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let fetcher = GradeRequest::new("PB19000000", "password").semesters(&[ALL_SEMESTERS]);
+        assert!(fetcher.validate().is_ok());
+        assert!(fetcher.proxy("http://[::1").validate().is_err());
+    }
+
+    #[test]
+    fn grade_by_no_ids() {
+        let grade = futures::executor::block_on(get_grade_semester_only("PB19000000", "", &[]));
+        match grade {
+            Err(Error::NoSemesters) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn all_semesters_grade() {
+        let all = mock_grade_response(&[]);
+        let grade = extract_grade(all.clone(), all, sem_map()).unwrap();
+        assert_eq!(grade.sem_gpa, grade.gpa);
+        assert_eq!(grade.scores.len(), 3);
+    }
+
+    #[test]
+    fn select_unknown_semesters() {
+        let err = select_semesters(&mock_semesters(), &["2019年秋季学期", "2019年冬季学期"]);
+        match err {
+            Err(Error::UnknownSemester {
+                requested,
+                available,
+            }) => {
+                assert_eq!(requested, ["2019年冬季学期"]);
+                assert_eq!(
+                    available,
+                    ["2019年春季学期", "2019年夏季学期", "2019年秋季学期"]
+                );
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn closest_semesters() {
+        let available: Vec<_> = mock_semesters().into_iter().map(|s| s.name_zh).collect();
+        assert_eq!(
+            closest_semester("2019年秋季学期 ", &available),
+            Some("2019年秋季学期")
+        );
+        assert_eq!(
+            closest_semester("2019 秋季学期", &available),
+            Some("2019年秋季学期")
+        );
+        assert_eq!(closest_semester("2023-2024学年第一学期", &available), None);
+
+        let err = check_semesters(&mock_semesters(), &["2019年秋季学期 ", "all"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown semesters: \"2019年秋季学期 \" (did you mean \"2019年秋季学期\"?), \"all\". \
+             Available semesters: 2019年春季学期, 2019年夏季学期, 2019年秋季学期"
+        );
+        assert!(check_semesters(&mock_semesters(), &[ALL_SEMESTERS]).is_ok());
     }
 }
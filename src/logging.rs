@@ -0,0 +1,241 @@
+//! Logging to stderr and optionally a size-rotated file
+//!
+//! Filtering is done by `env_logger`, so `RUST_LOG` works as before and wins
+//! over the `level` in the config. Known passwords are replaced with `******`
+//! in every line before it is written anywhere.
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use env_logger::filter::{self, Filter};
+use log::{Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+const REDACTED: &str = "******";
+
+#[derive(Debug, Deserialize)]
+pub struct LogConfig {
+    file: Option<PathBuf>,
+    level: Option<String>,
+    /// Size in bytes after which the log file is rotated
+    #[serde(default = "default_max_size")]
+    max_size: u64,
+    /// Number of rotated files to keep
+    #[serde(default = "default_keep")]
+    keep: usize,
+}
+
+fn default_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+static LOGGER: Lazy<Logger> = Lazy::new(|| Logger {
+    inner: Mutex::new(Inner {
+        filter: build_filter(None),
+        file: None,
+        secrets: Vec::new(),
+    }),
+});
+
+struct Logger {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    filter: Filter,
+    file: Option<RotatingFile>,
+    secrets: Vec<String>,
+}
+
+/// Installs the logger, writing to stderr only until
+/// [`configure`](fn.configure.html) is called
+pub fn init() {
+    let logger: &'static Logger = &LOGGER;
+    log::set_max_level(logger.lock().filter.filter());
+    log::set_logger(logger).expect("logger already set");
+}
+
+/// Applies the `[log]` section of the config
+pub fn configure(config: &LogConfig) -> Result<()> {
+    let file = match &config.file {
+        Some(path) => Some(
+            RotatingFile::open(path.clone(), config.max_size, config.keep)
+                .with_context(|| format!("Cannot open log file {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    let mut inner = LOGGER.lock();
+    inner.filter = build_filter(config.level.as_deref());
+    inner.file = file;
+    log::set_max_level(inner.filter.filter());
+    Ok(())
+}
+
+/// Hides `secrets` in all the log lines from now on
+pub fn redact(secrets: &[&str]) {
+    let mut inner = LOGGER.lock();
+    for secret in secrets.iter().filter(|s| !s.is_empty()) {
+        inner.secrets.push(secret.to_string());
+    }
+}
+
+fn build_filter(level: Option<&str>) -> Filter {
+    let mut builder = filter::Builder::new();
+    match std::env::var("RUST_LOG") {
+        Ok(filters) => builder.parse(&filters),
+        Err(_) => builder.parse(level.unwrap_or_default()),
+    };
+    builder.build()
+}
+
+fn format_line(record: &Record, secrets: &[String]) -> String {
+    let mut line = format!(
+        "[{} {:<5} {}] {}\n",
+        Local::now().format("%Y-%m-%dT%H:%M:%S%:z"),
+        record.level(),
+        record.target(),
+        record.args()
+    );
+    for secret in secrets {
+        line = line.replace(secret.as_str(), REDACTED);
+    }
+    line
+}
+
+impl Logger {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.lock().filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let mut inner = self.lock();
+        if !inner.filter.matches(record) {
+            return;
+        }
+        let line = format_line(record, &inner.secrets);
+        let _ = io::stderr().write_all(line.as_bytes());
+        if let Some(file) = &mut inner.file {
+            if let Err(e) = file.write_line(&line) {
+                let _ = writeln!(io::stderr(), "Write log file failed: {}", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &mut self.lock().file {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// A log file renamed to `<file>.1` once it grows beyond `max_size`, with
+/// older ones shifted to `<file>.2` and so on
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep > 0 {
+            for i in (1..self.keep).rev() {
+                let from = self.rotated(i);
+                if from.exists() {
+                    fs::rename(from, self.rotated(i + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_are_redacted() {
+        let secrets = vec!["hunter2".to_owned()];
+        let line = format_line(
+            &Record::builder()
+                .args(format_args!("form: username=PB1, password=hunter2"))
+                .level(log::Level::Debug)
+                .target("ustc_get_grade")
+                .build(),
+            &secrets,
+        );
+        assert!(line.ends_with("] form: username=PB1, password=******\n"));
+        assert!(!line.contains("hunter2"));
+    }
+
+    #[test]
+    fn rotation_keeps_newest_files() {
+        let dir = std::env::temp_dir().join(format!("ustc-get-grade-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("grade.log");
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        for line in &["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("grade.log"), "fourth\n");
+        assert_eq!(read("grade.log.1"), "third\n");
+        assert_eq!(read("grade.log.2"), "second\n");
+        assert!(!dir.join("grade.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
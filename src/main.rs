@@ -1,29 +1,107 @@
 #![cfg(feature = "cli")]
 
+mod api;
+mod config_doc;
+mod error_email;
+mod http;
+mod logging;
+mod metrics;
+mod output;
+mod schedule;
+mod sendto;
+mod shutdown;
+mod status;
+mod systemd;
+
 use anyhow::{Context, Result};
-use clap::{App, Arg};
+use chrono::{DateTime, Local};
+use clap::{App, Arg, ArgMatches};
+use error_email::ErrorEmails;
+use futures::channel::oneshot;
+use futures::future::{join_all, BoxFuture, FutureExt};
 use itertools::Itertools;
-use log::{error, info};
+use log::{error, info, warn};
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng};
+use schedule::Schedule;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
-use std::{thread, time::Duration};
-use ustc_get_grade::blocking::get_grade;
-use ustc_get_grade::Grade;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use ustc_get_grade::blocking::block_on;
+use ustc_get_grade::export::report::{format_grade_html, format_grade_text, Language};
+use ustc_get_grade::export::{self, charts, GradeFormatter};
+use ustc_get_grade::notify::{ApnsRelayNotifier, Notifier, TelegramNotifier, WebhookNotifier};
+use ustc_get_grade::throttle;
+use ustc_get_grade::{
+    check_semesters, CourseGrade, Credentials, Grade, GradeClient, GradeDiff, GradeFilter,
+    GradeRequest, DEFAULT_UA,
+};
 
 #[derive(Debug, Deserialize)]
 struct Config {
-    mail: Mail,
+    /// Without it, no email is sent
+    mail: Option<Mail>,
     ustc: Ustc,
+    apns_relay: Option<ApnsRelayConfig>,
+    webhook: Option<WebhookConfig>,
+    telegram: Option<TelegramConfig>,
+    log: Option<logging::LogConfig>,
+    metrics: Option<MetricsConfig>,
+    http: Option<HttpConfig>,
+    status: Option<status::StatusConfig>,
+    output: Option<output::OutputConfig>,
+    #[serde(default)]
+    filter: FilterConfig,
+    #[serde(skip_deserializing)]
+    show_letters: bool,
+    #[serde(skip_deserializing)]
+    grade_filter: GradeFilter,
+    /// The `[ustc]` login with the password or TGC resolved
+    #[serde(skip_deserializing, default = "no_credentials")]
+    credentials: Credentials,
 }
 
-#[derive(Debug, Deserialize)]
+fn no_credentials() -> Credentials {
+    Credentials::password("", "")
+}
+
+impl Config {
+    /// Display width after which course names are cut in text reports
+    fn max_name_width(&self) -> usize {
+        self.mail
+            .as_ref()
+            .map_or_else(default_max_name_width, |m| m.max_name_width)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 struct Mail {
     username: String,
     #[serde(flatten)]
     password: Password,
     server: String,
-    sendto: Vec<String>,
+    sendto: Vec<Recipient>,
+    /// Address in the From header, `username` if unset
+    from_address: Option<String>,
+    /// Display name in the From header
+    from_name: Option<String>,
+    #[serde(default)]
+    bilingual_email: bool,
+    /// Display width after which course names are cut in text emails
+    #[serde(default = "default_max_name_width")]
+    max_name_width: usize,
+    /// Size limit of each part of an email, beyond which courses are cut
+    email_max_size_kb: Option<usize>,
+    /// Seconds during which an identical error email is not sent again
+    #[serde(default = "default_error_email_cooldown")]
+    error_email_cooldown: f64,
+    /// Sends an email when fetching succeeds again after an error email
+    #[serde(default)]
+    recovery_email: bool,
     #[serde(skip_deserializing)]
     pass_cache: String,
 }
@@ -32,30 +110,268 @@ struct Mail {
 struct Ustc {
     username: String,
     #[serde(flatten)]
-    password: Password,
+    login: UstcLogin,
+    /// Names or IDs, which are kept as strings
+    #[serde(deserialize_with = "deserialize_semesters")]
     semesters: Vec<String>,
-    interval: f64,
+    /// Minutes between fetches, exclusive with `schedule`
+    interval: Option<f64>,
+    /// Percentage by which every wait of `interval` is randomly lengthened
+    /// or shortened, e.g. 10 for ±10%
+    #[serde(default = "default_interval_jitter_percent")]
+    interval_jitter_percent: f64,
+    /// Same as `interval_jitter_percent` but as a fraction, which it
+    /// overrides. Kept for existing configs.
+    interval_jitter: Option<f64>,
+    /// Cron expression of when to fetch
+    schedule: Option<String>,
+    /// Timezone of `schedule`, see [`Schedule::new`]
+    timezone: Option<String>,
     #[serde(default)]
     send_first: bool,
+    /// Logs the GPA after every fetch, to show the daemon is alive
+    #[serde(default)]
+    log_heartbeat: bool,
+    /// Notifies through all the channels at once instead of one by one
+    #[serde(default)]
+    concurrent_notify: bool,
+    /// Only notifies about courses whose names contain one of these
+    #[serde(default)]
+    watch_courses: Vec<String>,
+    #[serde(default = "default_min_login_interval")]
+    min_login_interval: f64,
+    #[serde(default = "default_captcha_cooldown")]
+    captcha_cooldown: f64,
+    /// Times a fetch is retried after a network error or a 5xx response
+    #[serde(default = "default_max_retries")]
+    max_retries: u32,
+    /// Seconds before the first retry, doubling on every retry
+    #[serde(default = "default_retry_base_delay")]
+    retry_base_delay_seconds: f64,
+    /// Fetches the rank in the major after the grade
+    #[serde(default)]
+    fetch_rank: bool,
+    /// Speaks HTTP/2 to Jiaowu without negotiating it
+    #[serde(default)]
+    http2: bool,
+    /// Fetches every semester with a request of its own
+    #[serde(default)]
+    per_semester_requests: bool,
+    user_agent: Option<String>,
+    #[serde(default)]
+    api_base_path: String,
     #[serde(skip_deserializing)]
-    pass_cache: String,
+    parsed_schedule: Option<Schedule>,
+}
+
+/// A semester in the config, by name or by ID
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SemesterEntry {
+    Name(String),
+    Id(usize),
+}
+
+/// Reads `semesters`, keeping IDs as strings so that they are passed to the
+/// library like names
+fn deserialize_semesters<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let entries = Vec::<SemesterEntry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|e| match e {
+            SemesterEntry::Name(name) => name,
+            SemesterEntry::Id(id) => id.to_string(),
+        })
+        .collect())
+}
+
+fn default_max_name_width() -> usize {
+    40
+}
+
+fn default_error_email_cooldown() -> f64 {
+    error_email::DEFAULT_COOLDOWN.as_secs_f64()
+}
+
+fn default_interval_jitter_percent() -> f64 {
+    10.
 }
 
+fn default_min_login_interval() -> f64 {
+    throttle::DEFAULT_MIN_LOGIN_INTERVAL.as_secs_f64()
+}
+
+fn default_captcha_cooldown() -> f64 {
+    throttle::DEFAULT_CAPTCHA_COOLDOWN.as_secs_f64()
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_base_delay() -> f64 {
+    ustc_get_grade::DEFAULT_RETRY_BASE_DELAY.as_secs_f64()
+}
+
+/// A relay server that forwards notifications to Apple Push Notification
+/// Service, so that no Apple Developer account is needed
+#[derive(Debug, Deserialize)]
+struct ApnsRelayConfig {
+    relay_url: String,
+    device_token: String,
+}
+
+/// A URL to POST the grade and its changes to as JSON
+#[derive(Debug, Deserialize)]
+struct WebhookConfig {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramConfig {
+    bot_token: String,
+    chat_id: String,
+}
+
+/// Courses that do not trigger notifications, see [`GradeFilter`]
+#[derive(Debug, Default, Deserialize)]
+struct FilterConfig {
+    #[serde(default)]
+    ignore_scores: Vec<String>,
+    #[serde(default)]
+    ignore_courses: Vec<String>,
+    #[serde(default)]
+    only_courses: Vec<String>,
+}
+
+impl FilterConfig {
+    fn build(&self) -> Result<GradeFilter, ustc_get_grade::Error> {
+        GradeFilter::new()
+            .ignore_scores(&self.ignore_scores)
+            .ignore_courses(&self.ignore_courses)?
+            .only_courses(&self.only_courses)
+    }
+}
+
+/// Where to serve Prometheus metrics, needs the `metrics` feature
+#[derive(Debug, Deserialize)]
+struct MetricsConfig {
+    listen: String,
+}
+
+/// Where to serve the latest grade, needs the `http` feature
 #[derive(Debug, Deserialize)]
+struct HttpConfig {
+    listen: String,
+}
+
+/// How to log in to USTC, with the TGC cookie of a browser login or with
+/// the password
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum UstcLogin {
+    Tgc { tgc: String },
+    TgcExec { tgc_exec: String },
+    Password(Password),
+}
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
 enum Password {
-    Plain { password: String },
-    Exec { pass_exec: String },
+    Plain {
+        password: String,
+    },
+    Exec {
+        pass_exec: String,
+    },
+    Keyring {
+        keyring_service: String,
+        keyring_user: String,
+    },
+    /// `password = { keyring = "service" }`, stored under the username of
+    /// the section
+    KeyringService {
+        password: KeyringService,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct KeyringService {
+    keyring: String,
+}
+
+/// A recipient, optionally with the preferred format of emails
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Recipient {
+    Address(String),
+    Detailed {
+        address: String,
+        #[serde(default)]
+        format: MailFormat,
+    },
+}
+
+impl Recipient {
+    fn address(&self) -> &str {
+        match self {
+            Recipient::Address(address) | Recipient::Detailed { address, .. } => address,
+        }
+    }
+
+    fn format(&self) -> MailFormat {
+        match self {
+            Recipient::Address(_) => MailFormat::default(),
+            Recipient::Detailed { format, .. } => *format,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum MailFormat {
+    Text,
+    Html,
+    #[default]
+    Alternative,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 enum EmailContent {
     Plain(String),
+    Html(String),
     Alternative(String, String),
 }
 
-fn get_config() -> Result<Config> {
-    let options = App::new(env!("CARGO_PKG_NAME"))
+impl EmailContent {
+    /// The content sent to recipients preferring `format`
+    fn for_format(&self, format: MailFormat) -> EmailContent {
+        match (self, format) {
+            (EmailContent::Alternative(t, _), MailFormat::Text) => EmailContent::Plain(t.clone()),
+            (EmailContent::Alternative(_, h), MailFormat::Html) => EmailContent::Html(h.clone()),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Groups the addresses in `sendto` by their preferred formats, keeping the
+/// order of first appearance
+fn recipients_by_format(sendto: &[Recipient]) -> Vec<(MailFormat, Vec<&str>)> {
+    let mut groups: Vec<(MailFormat, Vec<&str>)> = Vec::new();
+    for to in sendto {
+        match groups.iter_mut().find(|(f, _)| *f == to.format()) {
+            Some((_, addresses)) => addresses.push(to.address()),
+            None => groups.push((to.format(), vec![to.address()])),
+        }
+    }
+    groups
+}
+
+fn app() -> App<'static, 'static> {
+    let app = App::new(env!("CARGO_PKG_NAME"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .version(env!("CARGO_PKG_VERSION"))
         .arg(
@@ -63,213 +379,2407 @@ fn get_config() -> Result<Config> {
                 .short("c")
                 .long("config")
                 .value_name("FILE")
-                .help("Sets a custom config file")
+                .help("Sets a custom config file, or - to read it from stdin")
                 .takes_value(true),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("once")
+                .long("once")
+                .help("Prints the grade once instead of watching for changes"),
+        )
+        .arg(
+            Arg::with_name("semesters")
+                .long("semesters")
+                .value_name("LIST")
+                .validator(|list| match split_list(&list).is_empty() {
+                    true => Err("No semesters given".to_owned()),
+                    false => Ok(()),
+                })
+                .help("Tracks these comma separated semesters instead of those in the config"),
+        )
+        .arg(
+            Arg::with_name("current")
+                .long("current")
+                .conflicts_with("semesters")
+                .help("Tracks the ongoing semester, looked up on every fetch"),
+        )
+        .arg(
+            Arg::with_name("help-config")
+                .long("help-config")
+                .help("Prints the documentation of every field of the config file"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .conflicts_with("once")
+                .help("Checks the USTC and SMTP credentials and the semesters, then exits"),
+        )
+        .arg(
+            Arg::with_name("test-fixture")
+                .long("test-fixture")
+                .value_name("PATH")
+                .conflicts_with_all(&["once", "check"])
+                .help("Sends one notification of the grade in a recorded getGradeList response"),
+        )
+        .arg(
+            Arg::with_name("pie-chart")
+                .long("pie-chart")
+                .requires("once")
+                .help("Prints a chart of credits earned in each semester"),
+        )
+        .arg(
+            Arg::with_name("chart")
+                .long("chart")
+                .conflicts_with_all(&["once", "check"])
+                .help("Prints a chart of the GPA in the history kept in [output], then exits"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["text", "markdown"])
+                .requires("once")
+                .help("Sets the format of the grade printed by --once, text by default"),
+        )
+        .arg(
+            Arg::with_name("search")
+                .long("search")
+                .value_name("QUERY")
+                .requires("once")
+                .conflicts_with_all(&["format", "pie-chart"])
+                .help("Prints only the courses whose name contains QUERY, ignoring case"),
+        )
+        .arg(
+            Arg::with_name("show-letters")
+                .long("show-letters")
+                .help("Shows letter grades in plain text reports"),
+        )
+        .arg(
+            Arg::with_name("email-add")
+                .long("email-add")
+                .value_name("ADDRESS")
+                .conflicts_with_all(&["email-remove", "once", "check"])
+                .help("Adds ADDRESS to sendto in the config file, then exits"),
+        )
+        .arg(
+            Arg::with_name("email-remove")
+                .long("email-remove")
+                .value_name("ADDRESS")
+                .conflicts_with_all(&["once", "check"])
+                .help("Removes ADDRESS from sendto in the config file, then exits"),
+        );
+    #[cfg(feature = "xlsx")]
+    let app = app
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["xlsx"])
+                .requires_all(&["once", "xlsx-path"])
+                .conflicts_with_all(&["format", "search"])
+                .help("Saves the grade printed by --once in FORMAT instead"),
+        )
+        .arg(
+            Arg::with_name("xlsx-path")
+                .long("xlsx-path")
+                .value_name("FILE")
+                .requires("output")
+                .help("Path of the workbook saved by --output xlsx"),
+        );
+    #[cfg(feature = "keyring")]
+    let app = app
+        .arg(
+            Arg::with_name("store-password")
+                .long("store-password")
+                .value_names(&["SERVICE", "USER"])
+                .help("Prompts for a password and stores it in the OS keyring"),
+        )
+        .arg(
+            Arg::with_name("set-password").long("set-password").help(
+                "Prompts for the passwords the config keeps in the OS keyring and stores them",
+            ),
+        );
+    app
+}
 
-    let conf = options.value_of("config").unwrap_or("config.toml");
-    let mut config =
-        File::open(conf).with_context(|| format!("Cannot find configuration file `{}'", conf))?;
-    let mut buf = String::new();
-    config.read_to_string(&mut buf)?;
-    let mut config: Config = toml::from_str(&buf)?;
-    anyhow::ensure!(
-        config.ustc.interval >= 10.,
-        "Interval {} is too small, should >= 10.",
-        config.ustc.interval
-    );
+const DEFAULT_CONFIG: &str = "config.toml";
 
-    config.mail.pass_cache = match config.mail.password {
-        Password::Plain { ref password } => password.clone(),
-        Password::Exec { ref pass_exec } => get_output(&pass_exec),
-    };
+/// Where the config is looked for without `-c`: `config.toml` in the
+/// platform's config directory, e.g. `~/.config/ustc-get-grade`, then in the
+/// current directory
+fn config_search_paths() -> Vec<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(DEFAULT_CONFIG))
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(DEFAULT_CONFIG)))
+        .collect()
+}
 
-    config.ustc.pass_cache = match config.ustc.password {
-        Password::Plain { ref password } => password.clone(),
-        Password::Exec { ref pass_exec } => get_output(&pass_exec),
-    };
+/// The first of `paths` that exists
+fn find_config(paths: &[PathBuf]) -> Option<&Path> {
+    paths.iter().map(PathBuf::as_path).find(|p| p.exists())
+}
 
-    Ok(config)
+/// Reads the config from the file given by `-c`, stdin if it is `-`, or the
+/// first `config.toml` found by `config_search_paths`. Without `-c` or any
+/// of those, the config is built from environment variables.
+fn get_config(options: &ArgMatches) -> Result<Config, Vec<String>> {
+    let paths = config_search_paths();
+    match options.value_of("config") {
+        Some("-") => read_config(std::io::stdin()),
+        Some(conf) => read_config_file(Path::new(conf)),
+        None => match find_config(&paths) {
+            Some(conf) => {
+                info!("Using configuration file `{}'", conf.display());
+                read_config_file(conf)
+            }
+            None => {
+                let searched = paths
+                    .iter()
+                    .map(|p| format!("`{}'", p.display()))
+                    .join(", ");
+                info!(
+                    "No configuration file at {}, reading config from environment variables",
+                    searched
+                );
+                let config = env_config(&std::env::vars().collect()).map_err(|e| {
+                    vec![format!(
+                        "No configuration file at {} and no config in the environment: {:#}",
+                        searched, e
+                    )]
+                })?;
+                check_config(config)
+            }
+        },
+    }
 }
 
-fn get_output(c: &str) -> String {
-    use std::process::Command;
-    let output = if cfg!(target_os = "windows") {
-        Command::new("cmd")
-            .arg("/C")
-            .arg(c)
-            .output()
-            .expect("failed to execute process")
-    } else {
-        Command::new("sh")
-            .arg("-c")
-            .arg(c)
-            .output()
-            .expect("failed to execute process")
-    };
-    String::from_utf8(output.stdout)
-        .expect("Invalid UTF-8 in output")
-        .trim_end_matches('\n')
-        .to_string()
+fn read_config_file(conf: &Path) -> Result<Config, Vec<String>> {
+    let file = File::open(conf).map_err(|_| {
+        vec![format!(
+            "Cannot find configuration file `{}'",
+            conf.display()
+        )]
+    })?;
+    read_config(file)
 }
 
-fn run(config: &Config) -> Result<()> {
-    let semesters: Vec<_> = config.ustc.semesters.iter().map(|s| s.as_str()).collect();
+fn read_config(mut reader: impl Read) -> Result<Config, Vec<String>> {
+    let mut buf = String::new();
+    reader
+        .read_to_string(&mut buf)
+        .map_err(|e| vec![format!("Cannot read the config: {}", e)])?;
+    validate_config(&buf)
+}
 
-    info!("App started");
+/// Builds the config from `USTC_USERNAME`, `USTC_PASSWORD`, `USTC_SEMESTERS`,
+/// `USTC_INTERVAL`, `USTC_SEND_FIRST`, `MAIL_USERNAME`, `MAIL_PASSWORD`,
+/// `MAIL_SERVER` and `MAIL_SENDTO`. The semesters and recipients are comma
+/// separated.
+fn env_config(vars: &HashMap<String, String>) -> Result<Config> {
+    use toml::value::{Table, Value};
 
-    let mut old_grade = get_grade(&config.ustc.username, &config.ustc.pass_cache, &semesters)?;
+    let get = |name: &str| {
+        vars.get(name)
+            .with_context(|| format!("Missing environment variable {}", name))
+    };
+    let var = |name: &str| -> Result<Value> { Ok(get(name)?.as_str().into()) };
+    let list = |name: &str| -> Result<Value> { Ok(split_list(get(name)?).into()) };
 
-    let content =
-        EmailContent::Alternative(format_grade_text(&old_grade), format_grade_html(&old_grade));
-    if config.ustc.send_first {
-        send_email(&config.mail, "Grade Report", content)?;
+    let mut ustc = Table::new();
+    ustc.insert("username".into(), var("USTC_USERNAME")?);
+    ustc.insert("password".into(), var("USTC_PASSWORD")?);
+    ustc.insert("semesters".into(), list("USTC_SEMESTERS")?);
+    let interval = get("USTC_INTERVAL")?;
+    let interval: f64 = interval
+        .trim()
+        .parse()
+        .with_context(|| format!("USTC_INTERVAL `{}' is not a number", interval))?;
+    ustc.insert("interval".into(), interval.into());
+    if let Some(send_first) = vars.get("USTC_SEND_FIRST") {
+        let send_first: bool = send_first
+            .trim()
+            .parse()
+            .with_context(|| format!("USTC_SEND_FIRST `{}' is not true or false", send_first))?;
+        ustc.insert("send_first".into(), send_first.into());
     }
 
-    loop {
-        info!("Sleep for {:.1} minutes", config.ustc.interval);
-        thread::sleep(Duration::from_secs_f64(60. * config.ustc.interval));
+    let mut mail = Table::new();
+    mail.insert("username".into(), var("MAIL_USERNAME")?);
+    mail.insert("password".into(), var("MAIL_PASSWORD")?);
+    mail.insert("server".into(), var("MAIL_SERVER")?);
+    mail.insert("sendto".into(), list("MAIL_SENDTO")?);
 
-        let grade = match get_grade(&config.ustc.username, &config.ustc.pass_cache, &semesters) {
-            Ok(g) => g,
-            Err(e) => {
-                error!("Get grade failed: {}", e);
-                send_email(
-                    &config.mail,
-                    "Get Grade Error",
-                    EmailContent::Plain(format!("Get grade failed: {}", e)),
-                )?;
-                continue;
+    let mut config = Table::new();
+    config.insert("ustc".into(), ustc.into());
+    config.insert("mail".into(), mail.into());
+    Ok(Value::Table(config).try_into()?)
+}
+
+/// Splits a comma separated list, ignoring empty items
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Parses and checks the config, returning all the problems found
+fn validate_config(raw: &str) -> Result<Config, Vec<String>> {
+    let config = toml::from_str(raw).map_err(|e| vec![format!("Invalid config: {}", e)])?;
+    check_config(config)
+}
+
+/// Checks the values in `config` and resolves the passwords. Commands in
+/// `pass_exec` are only run if everything else is valid.
+fn check_config(mut config: Config) -> Result<Config, Vec<String>> {
+    let mut errors = Vec::new();
+
+    match (config.ustc.interval, &config.ustc.schedule) {
+        (Some(_), Some(_)) => errors.push("Only one of interval and schedule can be set".into()),
+        (None, None) => errors.push("Either interval or schedule must be set".into()),
+        (Some(interval), None) => {
+            if interval < 10. {
+                errors.push(format!("interval must be ≥ 10 minutes, found {}", interval));
             }
-        };
-        if old_grade != grade {
-            info!("New grade detected");
-            let content =
-                EmailContent::Alternative(format_grade_text(&grade), format_grade_html(&grade));
-            if let Err(e) = send_email(&config.mail, "Grade Report", content) {
-                error!("Send email failed: {}", e);
-                send_email(
-                    &config.mail,
-                    "Get Grade Error",
-                    EmailContent::Plain(format!("Send email failed: {}", e)),
-                )?;
-                continue;
+            if let Some(jitter) = config.ustc.interval_jitter {
+                if !(0. ..1.).contains(&jitter) {
+                    errors.push(format!(
+                        "interval_jitter must be in [0, 1), found {}",
+                        jitter
+                    ));
+                }
+            }
+            let percent = config.ustc.interval_jitter_percent;
+            if !(0. ..=50.).contains(&percent) {
+                errors.push(format!(
+                    "interval_jitter_percent must be in [0, 50], found {}",
+                    percent
+                ));
             }
-            old_grade = grade;
         }
+        (None, Some(schedule)) => match Schedule::new(schedule, config.ustc.timezone.as_deref()) {
+            Ok(parsed) => config.ustc.parsed_schedule = Some(parsed),
+            Err(e) => errors.push(format!("Invalid schedule `{}': {:#}", schedule, e)),
+        },
+    }
+    if config.ustc.max_retries > 10 {
+        errors.push(format!(
+            "max_retries must be ≤ 10, found {}",
+            config.ustc.max_retries
+        ));
+    }
+    let seconds = [
+        (
+            "retry_base_delay_seconds",
+            config.ustc.retry_base_delay_seconds,
+        ),
+        ("min_login_interval", config.ustc.min_login_interval),
+        ("captcha_cooldown", config.ustc.captcha_cooldown),
+    ];
+    for &(name, value) in &seconds {
+        if !(value.is_finite() && value >= 0.) {
+            errors.push(format!("{} must be ≥ 0, found {}", name, value));
+        }
+    }
+    if let Some(mail) = &config.mail {
+        let cooldown = mail.error_email_cooldown;
+        if !(cooldown.is_finite() && cooldown >= 0.) {
+            errors.push(format!(
+                "error_email_cooldown must be ≥ 0, found {}",
+                cooldown
+            ));
+        }
+    }
+    match config.filter.build() {
+        Ok(filter) => config.grade_filter = filter.watch_courses(&config.ustc.watch_courses),
+        Err(e) => errors.push(format!("Invalid [filter]: {}", e)),
+    }
+    if config.ustc.semesters.is_empty() {
+        errors.push("semesters must not be empty, use [\"all\"] to track all the semesters".into());
+    }
+    if let Some(mail) = &config.mail {
+        for recipient in mail.sendto.iter() {
+            if !recipient.address().contains('@') {
+                errors.push(format!(
+                    "`{}' in sendto is not an email address",
+                    recipient.address()
+                ));
+            }
+        }
+    }
+    let passwords = config.mail.as_ref().map(|m| ("mail", &m.password));
+    let ustc_password = match &config.ustc.login {
+        UstcLogin::Password(password) => Some(("ustc", password)),
+        _ => None,
+    };
+    for (section, password) in passwords.into_iter().chain(ustc_password) {
+        if let Password::Exec { pass_exec } = password {
+            if pass_exec.trim().is_empty() {
+                errors.push(format!("pass_exec in [{}] must not be empty", section));
+            }
+        }
+    }
+    if let UstcLogin::TgcExec { tgc_exec } = &config.ustc.login {
+        if tgc_exec.trim().is_empty() {
+            errors.push("tgc_exec in [ustc] must not be empty".into());
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
     }
-}
 
-fn format_grade_html(grade: &Grade) -> String {
-    let preface = format!(
-        "<p>Total GPA: {:.2}<br />
-        Semester GPA: {:.2}<br />
-        Credits earned: {:.1}<br /></p>",
-        grade.gpa, grade.sem_gpa, grade.credits,
-    );
+    let mail_password = config
+        .mail
+        .as_ref()
+        .map(|m| m.password.resolve(&m.username))
+        .transpose();
+    match (
+        mail_password,
+        config.ustc.login.resolve(&config.ustc.username),
+    ) {
+        (Ok(mail_password), Ok(credentials)) => {
+            if let (Some(mail), Some(password)) = (&mut config.mail, mail_password) {
+                mail.pass_cache = password;
+            }
+            config.credentials = credentials;
+            Ok(config)
+        }
+        (mail, ustc) => Err(mail
+            .err()
+            .into_iter()
+            .chain(ustc.err())
+            .map(|e| format!("{:#}", e))
+            .collect()),
+    }
+}
 
-    let mut grades = String::new();
-    for (name, courses) in grade.scores.iter() {
-        let content = courses
-            .iter()
-            .map(|(n, g, c)| {
-                format!(
-                    r#"<tr>
-                    <td align="center">{}</td>
-                    <td align="center">{}</td>
-                    <td align="center">{}</td>
-                    </tr>"#,
-                    n, g, c,
-                )
-            })
-            .join("");
-        grades += &format!(
-            "<h4>{}</h4>
-            <table>
-              <tr>
-                <th>&nbsp;课程&nbsp;</th>
-                <th>&nbsp;成绩&nbsp;</th>
-                <th>&nbsp;学分&nbsp;</th>
-              </tr>
-              {}
-            </table>",
-            name, content
-        );
+impl UstcLogin {
+    /// The credentials of `username`, running `tgc_exec` or `pass_exec` or
+    /// reading the keyring if needed
+    fn resolve(&self, username: &str) -> Result<Credentials> {
+        match self {
+            UstcLogin::Tgc { tgc } => Ok(Credentials::Tgc(tgc.clone())),
+            UstcLogin::TgcExec { tgc_exec } => Ok(Credentials::Tgc(
+                get_output(tgc_exec).context("Cannot get the USTC TGC")?,
+            )),
+            UstcLogin::Password(password) => {
+                let password = password
+                    .resolve(username)
+                    .context("Cannot get the USTC password")?;
+                Ok(Credentials::password(username, password))
+            }
+        }
     }
 
-    preface + &grades
+    /// See `Password::keyring_entry`
+    fn keyring_entry<'a>(&'a self, username: &'a str) -> Option<(&'a str, &'a str)> {
+        match self {
+            UstcLogin::Password(password) => password.keyring_entry(username),
+            _ => None,
+        }
+    }
 }
 
-fn format_grade_text(grade: &Grade) -> String {
-    use prettytable::{cell, row, table};
+impl Password {
+    /// The password, where `username` is the username of the section
+    fn resolve(&self, username: &str) -> Result<String> {
+        match self {
+            Password::Plain { password } => Ok(password.clone()),
+            Password::Exec { pass_exec } => get_output(pass_exec),
+            Password::Keyring { .. } | Password::KeyringService { .. } => {
+                let (service, user) = self.keyring_entry(username).unwrap();
+                keyring_password(service, user)
+            }
+        }
+    }
 
-    let mut grades = String::new();
-    for (name, courses) in grade.scores.iter() {
-        let mut table = table!(["课程", "成绩", "学分"]);
-        for (n, g, c) in courses {
-            table.add_row(row![n, g, c]);
+    /// The keyring service and user of the password, if it is in the keyring
+    fn keyring_entry<'a>(&'a self, username: &'a str) -> Option<(&'a str, &'a str)> {
+        match self {
+            Password::Keyring {
+                keyring_service,
+                keyring_user,
+            } => Some((keyring_service, keyring_user)),
+            Password::KeyringService { password } => Some((&password.keyring, username)),
+            _ => None,
         }
-        grades += &format!("{}\n{}", name, table);
     }
+}
 
-    format!(
-        "\
-Total GPA: {:.2}
-Semester GPA: {:.2}
-Credits earned: {:.1}
+/// Reads the password from the keyring, prompting for it and storing it if
+/// there is none yet
+#[cfg(feature = "keyring")]
+fn keyring_password(service: &str, user: &str) -> Result<String> {
+    match keyring_rs::Entry::new(service, user).get_password() {
+        Err(keyring_rs::Error::NoEntry) => {
+            info!("No password of `{}' in keyring service `{}'", user, service);
+            store_password(service, user)
+        }
+        password => password.with_context(|| {
+            format!(
+                "Cannot read the password of `{}' from keyring service `{}'",
+                user, service
+            )
+        }),
+    }
+}
 
-{}",
-        grade.gpa, grade.sem_gpa, grade.credits, grades,
+#[cfg(not(feature = "keyring"))]
+fn keyring_password(service: &str, user: &str) -> Result<String> {
+    anyhow::bail!(
+        "Cannot read the password of `{}' from keyring service `{}': \
+         built without the `keyring` feature",
+        user,
+        service
     )
 }
 
-fn send_email(config: &Mail, subject: impl Into<String>, content: EmailContent) -> Result<()> {
-    use lettre::smtp::authentication::Credentials;
-    use lettre::{SmtpClient, Transport};
-    use lettre_email::Email;
+/// Prompts for a password and stores it in the keyring, returning it
+#[cfg(feature = "keyring")]
+fn store_password(service: &str, user: &str) -> Result<String> {
+    let prompt = format!("Password of `{}' for `{}': ", user, service);
+    let password = rpassword::read_password_from_tty(Some(&prompt))?;
+    keyring_rs::Entry::new(service, user)
+        .set_password(&password)
+        .with_context(|| {
+            format!(
+                "Cannot store the password of `{}' to keyring service `{}'",
+                user, service
+            )
+        })?;
+    Ok(password)
+}
 
-    info!("Sending email");
+#[cfg(not(feature = "keyring"))]
+fn store_password(_service: &str, _user: &str) -> Result<String> {
+    anyhow::bail!("Built without the `keyring` feature")
+}
 
-    let mut email = Email::builder()
-        .from(config.username.as_str())
-        .subject(subject);
-    email = match content {
-        EmailContent::Plain(t) => email.text(t),
-        EmailContent::Alternative(t, h) => email.alternative(h, t),
+/// The config file given by `-c`, or else the first one found by
+/// `config_search_paths`, or else `config.toml`
+fn config_path(conf: Option<&str>) -> PathBuf {
+    conf.map(PathBuf::from).unwrap_or_else(|| {
+        find_config(&config_search_paths())
+            .unwrap_or_else(|| Path::new(DEFAULT_CONFIG))
+            .to_owned()
+    })
+}
+
+/// Prompts for every password the config keeps in the keyring and stores
+/// them, replacing the ones stored before. The config is not checked
+/// otherwise.
+fn set_passwords(options: &ArgMatches) -> Result<()> {
+    let raw = match options.value_of("config") {
+        Some("-") => {
+            let mut raw = String::new();
+            std::io::stdin().read_to_string(&mut raw)?;
+            raw
+        }
+        conf => {
+            let conf = config_path(conf);
+            std::fs::read_to_string(&conf)
+                .with_context(|| format!("Cannot read configuration file `{}'", conf.display()))?
+        }
     };
-    for to in config.sendto.iter() {
-        email = email.to(to.as_str());
+    let config: Config = toml::from_str(&raw).context("Invalid config")?;
+    let entries: Vec<_> = [
+        config
+            .mail
+            .as_ref()
+            .and_then(|m| m.password.keyring_entry(&m.username)),
+        config.ustc.login.keyring_entry(&config.ustc.username),
+    ]
+    .iter()
+    .flatten()
+    .copied()
+    .collect();
+    anyhow::ensure!(
+        !entries.is_empty(),
+        "No password in the config is kept in the keyring"
+    );
+    for (service, user) in entries {
+        store_password(service, user)?;
     }
-    let email = email.build()?;
+    Ok(())
+}
 
-    let cred = Credentials::new(config.username.clone(), config.pass_cache.clone());
-    let mut mailer = SmtpClient::new_simple(config.server.as_str())?
-        .credentials(cred)
-        .transport();
+/// Applies `edit` to sendto in the config file, see `config_path`, and
+/// returns the new recipients
+fn edit_sendto_file(conf: Option<&str>, edit: sendto::Edit) -> Result<Vec<String>> {
+    anyhow::ensure!(conf != Some("-"), "Cannot edit a config read from stdin");
+    let conf = config_path(conf);
+    let raw = std::fs::read_to_string(&conf)
+        .with_context(|| format!("Cannot read configuration file `{}'", conf.display()))?;
+    let (edited, sendto) = sendto::edit_sendto(&raw, edit)?;
+    std::fs::write(&conf, edited)
+        .with_context(|| format!("Cannot write configuration file `{}'", conf.display()))?;
+    Ok(sendto)
+}
 
-    mailer.send(email.into())?;
-    info!("Email sent");
+/// Runs `c` in the shell and returns its output without the trailing newline
+fn get_output(c: &str) -> Result<String> {
+    use std::process::Command;
+    let output = if cfg!(target_os = "windows") {
+        Command::new("cmd").arg("/C").arg(c).output()
+    } else {
+        Command::new("sh").arg("-c").arg(c).output()
+    }
+    .with_context(|| format!("Cannot run `{}'", c))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`{}' failed with {}: {}",
+        c,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("Invalid UTF-8 in the output of `{}'", c))?;
+    Ok(stdout.trim_end_matches('\n').to_string())
+}
+
+fn configure_throttle(config: &Ustc) {
+    let throttle = throttle::login_throttle();
+    throttle.set_min_interval(Duration::from_secs_f64(config.min_login_interval));
+    throttle.set_captcha_cooldown(Duration::from_secs_f64(config.captcha_cooldown));
+}
+
+fn run_once(
+    config: &Config,
+    markdown: bool,
+    pie_chart: bool,
+    search: Option<&str>,
+    xlsx_path: Option<&str>,
+) -> Result<()> {
+    let semesters: Vec<_> = config.ustc.semesters.iter().map(|s| s.as_str()).collect();
+    let client = grade_client(&config.ustc);
+    let grade = block_on(client.get_grade(&config.credentials, &semesters))?;
 
+    if let Some(path) = xlsx_path {
+        save_xlsx(&grade, path)?;
+    } else if let Some(query) = search {
+        let courses = ustc_get_grade::search_courses(&grade, query);
+        print!("{}", format_search_text(&courses, config.max_name_width()));
+    } else if markdown {
+        print!("{}", grade.to_markdown());
+    } else {
+        println!(
+            "{}",
+            format_grade_text(
+                &grade,
+                None,
+                &[Language::Chinese],
+                config.show_letters,
+                config.max_name_width()
+            )
+        );
+    }
+    if pie_chart {
+        println!("{}", credit_chart(&grade));
+    }
     Ok(())
 }
 
-fn main() {
-    env_logger::init();
+#[cfg(feature = "xlsx")]
+fn save_xlsx(grade: &Grade, path: &str) -> Result<()> {
+    ustc_get_grade::grade_to_xlsx(grade, Path::new(path))
+        .with_context(|| format!("Cannot save the grade to `{}'", path))?;
+    info!("Grade saved to {}", path);
+    Ok(())
+}
 
-    let config = get_config().unwrap_or_else(|e| {
-        error!("Config error: {}", e);
-        std::process::exit(1);
-    });
+#[cfg(not(feature = "xlsx"))]
+fn save_xlsx(_grade: &Grade, _path: &str) -> Result<()> {
+    anyhow::bail!("Built without the `xlsx` feature")
+}
 
-    if let Err(e) = run(&config) {
-        error!("{}", e);
-        send_email(
-            &config.mail,
-            "Get Grade Error",
-            EmailContent::Plain(format!("{}", e)),
-        )
-        .unwrap();
-        std::process::exit(1);
+/// The grade in the fixture at `path`, limited to the configured semesters
+fn fixture_grade(config: &Config, path: &str) -> Result<Grade> {
+    let mut grade = ustc_get_grade::load_fixture(path)
+        .with_context(|| format!("Cannot load the fixture `{}'", path))?;
+    let semesters = &config.ustc.semesters;
+    if semesters.iter().any(|s| s == ustc_get_grade::ALL_SEMESTERS) {
+        return Ok(grade);
+    }
+    if semesters
+        .iter()
+        .any(|s| s == ustc_get_grade::CURRENT_SEMESTER)
+    {
+        // Recorded responses do not say which semester is ongoing, so the
+        // latest one is taken
+        let older = grade.scores.len().saturating_sub(1);
+        grade.scores.drain(..older);
+        return Ok(grade);
+    }
+    let available: Vec<_> = grade.scores.iter().map(|s| s.name.clone()).collect();
+    let unknown: Vec<_> = semesters
+        .iter()
+        .filter(|&s| !available.contains(s))
+        .cloned()
+        .collect();
+    if !unknown.is_empty() {
+        return Err(ustc_get_grade::Error::UnknownSemester {
+            requested: unknown,
+            available,
+        }
+        .into());
+    }
+    grade.scores.retain(|s| semesters.contains(&s.name));
+    Ok(grade)
+}
+
+/// Notifies once of the grade in the fixture at `path` through all the
+/// configured channels, without logging in to Jiaowu
+fn run_fixture(config: &Config, path: &str) -> Result<()> {
+    let grade = fixture_grade(config, path)?;
+    let notifiers = build_notifiers(config, &config.mail)?;
+    let concurrent = config.ustc.concurrent_notify;
+    if !notify_all(&config.mail, &notifiers, &grade, None, concurrent)? {
+        anyhow::bail!("Some notifications failed");
+    }
+    info!("Notified of the grade in `{}'", path);
+    Ok(())
+}
+
+/// Logs in to Jiaowu and the SMTP server and checks the configured
+/// semesters exist, printing the result of each, and returns whether all
+/// succeeded
+fn run_checks(config: &Config) -> bool {
+    let client = grade_client(&config.ustc);
+    let semesters = block_on(client.semesters(&config.credentials));
+    let mut checks = Vec::new();
+    match semesters {
+        Ok(available) => {
+            checks.push(("USTC login", Ok(())));
+            let requested: Vec<_> = config.ustc.semesters.iter().map(|s| s.as_str()).collect();
+            let result = check_semesters(&available, &requested);
+            checks.push(("Semesters", result.map_err(anyhow::Error::from)));
+        }
+        Err(e) => checks.push(("USTC login", Err(e.into()))),
+    }
+    if let Some(mail) = &config.mail {
+        checks.push(("SMTP login", check_smtp(mail)));
+    }
+
+    let mut ok = true;
+    for (name, result) in checks {
+        println!("{}", check_line(name, &result));
+        ok &= result.is_ok();
+    }
+    ok
+}
+
+fn check_line(name: &str, result: &Result<()>) -> String {
+    match result {
+        Ok(()) => format!("{}: ok", name),
+        Err(e) => format!("{}: failed: {:#}", name, e),
+    }
+}
+
+/// Draws the credits of each semester as a pie chart, or a bar chart if the
+/// terminal is too narrow
+fn credit_chart(grade: &Grade) -> String {
+    const RADIUS: u8 = 8;
+
+    let slices: Vec<_> = grade
+        .scores
+        .iter()
+        .map(|s| (s.name.clone(), s.courses.iter().map(|c| c.credits).sum()))
+        .collect();
+    let columns = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(80);
+    if charts::pie_chart_width(RADIUS) <= columns {
+        charts::ascii_pie_chart(&slices, RADIUS)
+    } else {
+        charts::ascii_bar_chart(&slices, columns / 2)
+    }
+}
+
+fn grade_client(ustc: &Ustc) -> GradeClient {
+    GradeClient::new(user_agent(
+        ustc.user_agent.as_deref(),
+        std::env::var("USTC_USER_AGENT").ok(),
+    ))
+    .base_path(&ustc.api_base_path)
+    .max_retries(ustc.max_retries)
+    .retry_base_delay(Duration::from_secs_f64(ustc.retry_base_delay_seconds))
+    .fetch_rank(ustc.fetch_rank)
+    .http2(ustc.http2)
+    .per_semester_requests(ustc.per_semester_requests)
+}
+
+fn heartbeat(gpa: Option<f64>, wait: Duration) -> String {
+    format!(
+        "Heartbeat: GPA={}, polling again in {:.1} minutes",
+        export::format_gpa(gpa),
+        wait.as_secs_f64() / 60.
+    )
+}
+
+/// When to fetch again after `now`, with `interval` jittered by `rng`
+fn next_fetch(ustc: &Ustc, now: DateTime<Local>, rng: &mut impl Rng) -> Result<DateTime<Local>> {
+    match &ustc.parsed_schedule {
+        Some(schedule) => schedule.next_after(now).context("The schedule never fires"),
+        None => {
+            let interval = Duration::from_secs_f64(60. * ustc.interval.unwrap_or_default());
+            let jitter = ustc
+                .interval_jitter
+                .unwrap_or(ustc.interval_jitter_percent / 100.);
+            let interval = jittered(interval, jitter, rng);
+            Ok(now + chrono::Duration::from_std(interval)?)
+        }
+    }
+}
+
+/// `interval` scaled by a random factor in `[1 - jitter, 1 + jitter]`
+fn jittered(interval: Duration, jitter: f64, rng: &mut impl Rng) -> Duration {
+    if jitter <= 0. {
+        return interval;
+    }
+    interval.mul_f64(rng.gen_range(1. - jitter, 1. + jitter))
+}
+
+/// The User-Agent in the config, or else the one in `USTC_USER_AGENT`, or
+/// else the default
+fn user_agent(configured: Option<&str>, env: Option<String>) -> String {
+    configured
+        .map(str::to_owned)
+        .or(env)
+        .filter(|ua| !ua.is_empty())
+        .unwrap_or_else(|| DEFAULT_UA.to_owned())
+}
+
+/// The request the main loop sends on every fetch
+fn grade_request(config: &Config, client: GradeClient) -> GradeRequest {
+    let semesters: Vec<_> = config.ustc.semesters.iter().map(|s| s.as_str()).collect();
+    GradeRequest::with_credentials(config.credentials.clone())
+        .client(client)
+        .semesters(&semesters)
+}
+
+fn run(config: &Config, cache: &api::GradeCache) -> Result<()> {
+    info!("App started");
+
+    let request = grade_request(config, grade_client(&config.ustc));
+    let old_grade = match block_on(request.send()) {
+        Ok(g) => g,
+        Err(e) => {
+            metrics::fetch_failed();
+            status::fetch_failed(&e);
+            return Err(e.into());
+        }
+    };
+    metrics::fetch_succeeded(&old_grade);
+    status::fetch_succeeded(&old_grade);
+    write_output(config, &old_grade);
+    api::store(cache, &old_grade);
+    systemd::ready();
+    systemd::status(&fetch_ok_status(&old_grade));
+
+    let notifiers = build_notifiers(config, &config.mail)?;
+    if !ustc_get_grade::is_grade_meaningful(&old_grade) {
+        warn!("No grade is published in the selected semesters yet");
+    } else if config.ustc.send_first {
+        notify_all(
+            &config.mail,
+            &notifiers,
+            &old_grade,
+            None,
+            config.ustc.concurrent_notify,
+        )?;
+    }
+
+    let mut rng = StdRng::from_entropy();
+    let mut poller = Poller::new(config, &config.mail, notifiers, cache, old_grade);
+    loop {
+        systemd::watchdog();
+        let now = Local::now();
+        let next = next_fetch(&config.ustc, now, &mut rng)?;
+        let wait = (next - now).to_std().unwrap_or_default();
+        if let Some(gpa) = poller.heartbeat_gpa.take() {
+            info!("{}", heartbeat(gpa, wait));
+        }
+        info!("Sleep for {:.1} minutes", wait.as_secs_f64() / 60.);
+        status::next_fetch(next);
+        if !shutdown::sleep(wait) {
+            info!("Shutting down gracefully");
+            systemd::stopping();
+            return Ok(());
+        }
+
+        poller.poll(&request)?;
+    }
+}
+
+/// What the main loop keeps between fetches
+struct Poller<'a> {
+    config: &'a Config,
+    mailer: &'a dyn Mailer,
+    notifiers: Vec<Box<dyn Notifier + 'a>>,
+    cache: &'a api::GradeCache,
+    /// The grade last notified of
+    old_grade: Grade,
+    error_emails: ErrorEmails,
+    /// GPA of the last fetch, logged with the wait before the next one
+    heartbeat_gpa: Option<Option<f64>>,
+}
+
+impl<'a> Poller<'a> {
+    fn new(
+        config: &'a Config,
+        mailer: &'a dyn Mailer,
+        notifiers: Vec<Box<dyn Notifier + 'a>>,
+        cache: &'a api::GradeCache,
+        old_grade: Grade,
+    ) -> Self {
+        Poller {
+            config,
+            mailer,
+            notifiers,
+            cache,
+            old_grade,
+            error_emails: ErrorEmails::new(Duration::from_secs_f64(
+                config
+                    .mail
+                    .as_ref()
+                    .map_or_else(default_error_email_cooldown, |m| m.error_email_cooldown),
+            )),
+            heartbeat_gpa: None,
+        }
+    }
+
+    /// Fetches the grade with `request` and handles the result
+    fn poll(&mut self, request: &GradeRequest) -> Result<()> {
+        self.handle(block_on(request.send()))
+    }
+
+    /// Sends an error email if `fetched` failed, or else notifies of the
+    /// changes from the grade last notified of. Fails if passport rejected
+    /// the credentials, as fetching again would only prolong a lockout.
+    fn handle(&mut self, fetched: Result<Grade, ustc_get_grade::Error>) -> Result<()> {
+        let config = self.config;
+        let mut grade = match fetched {
+            Ok(g) => g,
+            Err(e) => {
+                error!("Get grade failed: {}", e);
+                metrics::fetch_failed();
+                status::fetch_failed(&e);
+                systemd::status(&format!(
+                    "last fetch failed at {}: {}",
+                    Local::now().format("%H:%M"),
+                    e
+                ));
+                let content = format!("Get grade failed: {}", e);
+                if e.is_credentials_rejected() {
+                    let content = format!(
+                        "{}\n\nStopped fetching the grade, fix the credentials and restart",
+                        content
+                    );
+                    self.mailer
+                        .send("Get Grade Error", EmailContent::Plain(content))?;
+                    return Err(anyhow::Error::from(e).context("Stopped fetching the grade"));
+                }
+                if self.error_emails.should_send(&content, Instant::now()) {
+                    self.mailer
+                        .send("Get Grade Error", EmailContent::Plain(content))?;
+                } else {
+                    info!("Same error as the last error email, not sending it again");
+                }
+                return Ok(());
+            }
+        };
+        let recovery_email = matches!(&config.mail, Some(m) if m.recovery_email);
+        if self.error_emails.recovered() && recovery_email {
+            self.mailer.send(
+                "Get Grade Recovered",
+                EmailContent::Plain("Getting the grade succeeded again".to_owned()),
+            )?;
+        }
+        // Keeps the last rank if none was fetched, so that failing to fetch
+        // the rank is not a change
+        if grade.rank.is_none() {
+            grade.rank = self.old_grade.rank.clone();
+        }
+        metrics::fetch_succeeded(&grade);
+        status::fetch_succeeded(&grade);
+        systemd::status(&fetch_ok_status(&grade));
+        write_output(config, &grade);
+        if config.ustc.log_heartbeat {
+            self.heartbeat_gpa = Some(grade.gpa);
+        }
+        // Nothing to compare, and a change would only be the GPA dropping to 0
+        if !ustc_get_grade::is_grade_meaningful(&grade) {
+            warn!("No grade is published in the selected semesters yet, not comparing");
+            return Ok(());
+        }
+        if self.old_grade == grade {
+            return Ok(());
+        }
+
+        let diff = GradeDiff::between(&self.old_grade, &grade);
+        if config.grade_filter.ignores(&diff) {
+            info!("Only filtered courses changed");
+        } else {
+            info!("New grade detected");
+            metrics::grade_changed();
+            let diff = config.grade_filter.apply_diff(&diff);
+            write_changes(config, &grade, &diff);
+            let concurrent = config.ustc.concurrent_notify;
+            if !notify_all(
+                self.mailer,
+                &self.notifiers,
+                &grade,
+                Some(&diff),
+                concurrent,
+            )? {
+                return Ok(());
+            }
+        }
+        api::store(self.cache, &grade);
+        self.old_grade = grade;
+        Ok(())
+    }
+}
+
+fn fetch_ok_status(grade: &Grade) -> String {
+    format!(
+        "last fetch ok at {}, GPA {}",
+        Local::now().format("%H:%M"),
+        export::format_gpa(grade.gpa)
+    )
+}
+
+/// Replaces the reports in `[output]`, if set, with those of `grade`
+fn write_output(config: &Config, grade: &Grade) {
+    if let Some(out) = &config.output {
+        if let Err(e) = output::write_latest(out, grade, &file_formatter(config)) {
+            error!("Writing the reports failed: {:#}", e);
+        }
+        if let Err(e) = output::append_history(out, grade, Local::now()) {
+            error!("Writing the history failed: {:#}", e);
+        }
+    }
+}
+
+/// Writes the report of the changes in `diff` to `[output]`, if set
+fn write_changes(config: &Config, grade: &Grade, diff: &GradeDiff) {
+    if let Some(out) = &config.output {
+        let formatter = file_formatter(config);
+        match output::write_changed(out, grade, diff, &formatter, Local::now()) {
+            Ok(path) => info!("Changes written to {}", path.display()),
+            Err(e) => error!("Writing the changes failed: {:#}", e),
+        }
+    }
+}
+
+/// The email reports without their size limit
+fn file_formatter(config: &Config) -> ReportFormatter {
+    ReportFormatter {
+        max_size: None,
+        ..ReportFormatter::new(config)
+    }
+}
+
+/// The reports sent by email, in the languages and within the size limit
+/// set in `[mail]`
+struct ReportFormatter {
+    languages: Vec<Language>,
+    show_letters: bool,
+    max_name_width: usize,
+    max_size: Option<usize>,
+}
+
+impl ReportFormatter {
+    fn new(config: &Config) -> Self {
+        let mail = config.mail.as_ref();
+        let languages = if matches!(mail, Some(m) if m.bilingual_email) {
+            vec![Language::Chinese, Language::English]
+        } else {
+            vec![Language::Chinese]
+        };
+        ReportFormatter {
+            languages,
+            show_letters: config.show_letters,
+            max_name_width: config.max_name_width(),
+            max_size: mail.and_then(|m| m.email_max_size_kb).map(|kb| kb * 1024),
+        }
+    }
+
+    fn fit(&self, grade: &Grade, render: impl Fn(&Grade, usize) -> String) -> String {
+        match self.max_size {
+            Some(limit) => fit_report(grade, limit, render),
+            None => render(grade, 0),
+        }
+    }
+}
+
+impl GradeFormatter for ReportFormatter {
+    fn format_text(&self, grade: &Grade, diff: Option<&GradeDiff>) -> String {
+        self.fit(grade, |grade, omitted| {
+            let text = format_grade_text(
+                grade,
+                diff,
+                &self.languages,
+                self.show_letters,
+                self.max_name_width,
+            );
+            match omitted {
+                0 => text,
+                n => format!("{}\n{}\n", text, truncation_note(n)),
+            }
+        })
+    }
+
+    fn format_html(&self, grade: &Grade, diff: Option<&GradeDiff>) -> String {
+        self.fit(grade, |grade, omitted| {
+            let html = format_grade_html(grade, diff, &self.languages);
+            match omitted {
+                0 => html,
+                n => html.replacen(
+                    "</body>",
+                    &format!("<p><i>{}</i></p>\n</body>", truncation_note(n)),
+                    1,
+                ),
+            }
+        })
+    }
+}
+
+/// Formats `grade` for an email, showing the changes in `diff` if given
+fn grade_report(
+    formatter: &dyn GradeFormatter,
+    grade: &Grade,
+    diff: Option<&GradeDiff>,
+) -> EmailContent {
+    EmailContent::Alternative(
+        formatter.format_text(grade, diff),
+        formatter.format_html(grade, diff),
+    )
+}
+
+fn truncation_note(omitted: usize) -> String {
+    format!("(truncated — {} courses omitted)", omitted)
+}
+
+/// Renders `grade` with `render`, dropping courses from the bottom until
+/// the report fits in `limit` bytes. `render` is also given the number of
+/// courses dropped. The summary is kept even if the report never fits.
+fn fit_report(grade: &Grade, limit: usize, render: impl Fn(&Grade, usize) -> String) -> String {
+    let mut report = render(grade, 0);
+    let mut truncated = grade.clone();
+    let mut omitted = 0;
+    while report.len() > limit {
+        match truncated.scores.last_mut() {
+            Some(semester) => {
+                semester.courses.pop();
+                if semester.courses.is_empty() {
+                    truncated.scores.pop();
+                }
+            }
+            None => break,
+        }
+        omitted += 1;
+        report = render(&truncated, omitted);
+    }
+    report
+}
+
+/// Courses found by `--search`, in a table for each semester
+fn format_search_text(courses: &[(&str, &CourseGrade)], max_name_width: usize) -> String {
+    use ustc_get_grade::export::table::TextTable;
+
+    if courses.is_empty() {
+        return "No courses found\n".to_owned();
+    }
+    let headers = Language::Chinese.headers();
+    let mut text = String::new();
+    for (semester, courses) in &courses.iter().group_by(|(semester, _)| *semester) {
+        let mut table = TextTable::new().max_width(0, max_name_width);
+        table.add_row(vec![
+            headers[0].to_owned(),
+            headers[1].to_owned(),
+            headers[3].to_owned(),
+        ]);
+        for (_, course) in courses {
+            table.add_row(vec![
+                course.name.clone(),
+                course.score.clone(),
+                course.credits.to_string(),
+            ]);
+        }
+        text += &format!("{}\n{}", semester, table);
+    }
+    text
+}
+
+/// Connects to the SMTP server in `config` and logs in, without sending any
+/// mail. Like `send_email`, it uses TLS on port 465.
+fn check_smtp(config: &Mail) -> Result<()> {
+    use lettre::smtp::authentication::{Credentials, Mechanism};
+    use lettre::smtp::client::net::NetworkStream;
+    use lettre::smtp::client::InnerClient;
+    use lettre::smtp::commands::EhloCommand;
+    use lettre::smtp::extension::{ClientId, ServerInfo};
+    use lettre::smtp::SUBMISSIONS_PORT;
+    use lettre::ClientTlsParameters;
+
+    let tls = ClientTlsParameters::new(config.server.clone(), native_tls::TlsConnector::new()?);
+    let mut client = InnerClient::<NetworkStream>::new();
+    client
+        .connect(&(config.server.as_str(), SUBMISSIONS_PORT), Some(&tls))
+        .with_context(|| format!("Cannot connect to `{}'", config.server))?;
+    let ehlo = client.command(EhloCommand::new(ClientId::hostname()))?;
+    let info = ServerInfo::from_response(&ehlo)?;
+    let mechanism = [Mechanism::Plain, Mechanism::Login]
+        .iter()
+        .copied()
+        .find(|&m| info.supports_auth_mechanism(m))
+        .context("The server supports neither PLAIN nor LOGIN authentication")?;
+    let cred = Credentials::new(config.username.clone(), config.pass_cache.clone());
+    let result = client.auth(mechanism, &cred);
+    client.close();
+    result?;
+    Ok(())
+}
+
+fn send_email(config: &Mail, subject: impl Into<String>, content: EmailContent) -> Result<()> {
+    use lettre::smtp::authentication::Credentials;
+    use lettre::{SmtpClient, Transport};
+
+    info!("Sending email");
+
+    let cred = Credentials::new(config.username.clone(), config.pass_cache.clone());
+    let mut mailer = SmtpClient::new_simple(config.server.as_str())?
+        .credentials(cred)
+        .transport();
+
+    let subject = subject.into();
+    for (format, addresses) in recipients_by_format(&config.sendto) {
+        let email = build_email(config, &subject, content.for_format(format), &addresses)?;
+        retry_transient(SMTP_ATTEMPTS, SMTP_RETRY_DELAY, || {
+            mailer.send(email.clone().into())
+        })?;
+        metrics::email_sent();
+    }
+    info!("Email sent");
+
+    Ok(())
+}
+
+/// An email from the sender in `config` to `addresses`
+fn build_email(
+    config: &Mail,
+    subject: &str,
+    content: EmailContent,
+    addresses: &[&str],
+) -> Result<lettre_email::Email> {
+    use lettre_email::{Email, Mailbox};
+
+    let address = config.from_address.as_ref().unwrap_or(&config.username);
+    let from = match &config.from_name {
+        Some(name) => Mailbox::new_with_name(name.clone(), address.clone()),
+        None => Mailbox::new(address.clone()),
+    };
+    let mut email = Email::builder().from(from).subject(subject);
+    email = match content {
+        EmailContent::Plain(t) => email.text(t),
+        EmailContent::Html(h) => email.html(h),
+        EmailContent::Alternative(t, h) => email.alternative(h, t),
+    };
+    for to in addresses {
+        email = email.to(*to);
+    }
+    Ok(email.build()?)
+}
+
+/// Times an email is tried when the SMTP server replies with a 4xx code
+const SMTP_ATTEMPTS: u32 = 3;
+const SMTP_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Runs `send` until it succeeds, fails with anything but a transient SMTP
+/// error, or has been tried `attempts` times, waiting `delay` in between
+fn retry_transient<T>(
+    attempts: u32,
+    delay: Duration,
+    mut send: impl FnMut() -> Result<T, lettre::smtp::error::Error>,
+) -> Result<T, lettre::smtp::error::Error> {
+    use lettre::smtp::error::Error::Transient;
+
+    let mut attempt = 1;
+    loop {
+        match send() {
+            Err(e @ Transient(_)) if attempt < attempts => {
+                warn!(
+                    "Sending email failed: {}, retrying in {} seconds",
+                    e,
+                    delay.as_secs()
+                );
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Ok(sent) => {
+                if attempt > 1 {
+                    info!("Email sent after {} retries", attempt - 1);
+                }
+                return Ok(sent);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Sends emails, to the SMTP server in `[mail]` unless in tests
+trait Mailer: Sync {
+    fn send(&self, subject: &str, content: EmailContent) -> Result<()>;
+
+    /// A copy to send from another thread, so that the blocking SMTP
+    /// session does not hold up the other notifiers
+    fn detach(&self) -> Box<dyn Mailer + Send>;
+}
+
+impl Mailer for Mail {
+    fn send(&self, subject: &str, content: EmailContent) -> Result<()> {
+        send_email(self, subject, content)
+    }
+
+    fn detach(&self) -> Box<dyn Mailer + Send> {
+        Box::new(self.clone())
+    }
+}
+
+/// Without `[mail]`, emails are only logged
+impl Mailer for Option<Mail> {
+    fn send(&self, subject: &str, content: EmailContent) -> Result<()> {
+        match self {
+            Some(mail) => mail.send(subject, content),
+            None => {
+                info!("No [mail] in the config, not sending \"{}\"", subject);
+                Ok(())
+            }
+        }
+    }
+
+    fn detach(&self) -> Box<dyn Mailer + Send> {
+        Box::new(self.clone())
+    }
+}
+
+/// Sends grade reports to the recipients in `[mail]`
+struct EmailNotifier<'a> {
+    mailer: &'a dyn Mailer,
+    formatter: Box<dyn GradeFormatter>,
+}
+
+impl Notifier for EmailNotifier<'_> {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        grade: &'a Grade,
+        diff: Option<&'a GradeDiff>,
+    ) -> BoxFuture<'a, Result<(), ustc_get_grade::Error>> {
+        // Sent on its own thread, which starts right away, so that the
+        // other notifiers run meanwhile
+        let content = grade_report(&*self.formatter, grade, diff);
+        let mailer = self.mailer.detach();
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            let sent = mailer.send("Grade Report", content);
+            let _ = tx.send(sent.map_err(|e| format!("{:#}", e)));
+        });
+        async move {
+            rx.await
+                .unwrap_or_else(|_| Err("the email thread panicked".to_owned()))
+                .map_err(ustc_get_grade::Error::NotifyFailed)
+        }
+        .boxed()
+    }
+}
+
+/// The email notifier sending through `mailer` and the other channels set
+/// in the config
+fn build_notifiers<'a>(
+    config: &Config,
+    mailer: &'a dyn Mailer,
+) -> Result<Vec<Box<dyn Notifier + 'a>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if config.mail.is_some() {
+        notifiers.push(Box::new(EmailNotifier {
+            mailer,
+            formatter: Box::new(ReportFormatter::new(config)),
+        }));
+    }
+    if let Some(apns) = &config.apns_relay {
+        notifiers.push(Box::new(ApnsRelayNotifier::new(
+            &apns.relay_url,
+            &apns.device_token,
+        )?));
+    }
+    if let Some(webhook) = &config.webhook {
+        notifiers.push(Box::new(WebhookNotifier::new(&webhook.url)?));
+    }
+    if let Some(telegram) = &config.telegram {
+        notifiers.push(Box::new(TelegramNotifier::new(
+            &telegram.bot_token,
+            &telegram.chat_id,
+        )?));
+    }
+    Ok(notifiers)
+}
+
+/// Notifies through all the `notifiers`, one by one or all at once if
+/// `concurrent`. Failures are logged and reported in an error email, and
+/// `false` is returned if any notifier failed.
+fn notify_all(
+    mailer: &dyn Mailer,
+    notifiers: &[Box<dyn Notifier + '_>],
+    grade: &Grade,
+    diff: Option<&GradeDiff>,
+    concurrent: bool,
+) -> Result<bool> {
+    let results = if concurrent {
+        block_on(join_all(notifiers.iter().map(|n| n.notify(grade, diff))))
+    } else {
+        notifiers
+            .iter()
+            .map(|n| block_on(n.notify(grade, diff)))
+            .collect()
+    };
+    let mut failures = Vec::new();
+    for (notifier, result) in notifiers.iter().zip(results) {
+        if let Err(e) = result {
+            error!("Notify through {} failed: {}", notifier.name(), e);
+            failures.push(format!("{}: {}", notifier.name(), e));
+        }
+    }
+    if failures.is_empty() {
+        return Ok(true);
+    }
+    mailer.send(
+        "Get Grade Error",
+        EmailContent::Plain(format!(
+            "Send notification failed:\n{}",
+            failures.join("\n")
+        )),
+    )?;
+    Ok(false)
+}
+
+fn main() {
+    logging::init();
+
+    let options = app().get_matches();
+    if options.is_present("help-config") {
+        print!("{}", config_doc::render());
+        return;
+    }
+    if let Some(mut args) = options.values_of("store-password") {
+        let (service, user) = (args.next().unwrap(), args.next().unwrap());
+        if let Err(e) = store_password(service, user) {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        info!("Password stored");
+        return;
+    }
+    let edit = options
+        .value_of("email-add")
+        .map(sendto::Edit::Add)
+        .or_else(|| options.value_of("email-remove").map(sendto::Edit::Remove));
+    if let Some(edit) = edit {
+        match edit_sendto_file(options.value_of("config"), edit) {
+            Ok(sendto) => println!("sendto = [{}]", sendto.join(", ")),
+            Err(e) => {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if options.is_present("set-password") {
+        if let Err(e) = set_passwords(&options) {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        info!("Passwords stored");
+        return;
+    }
+
+    let mut config = get_config(&options).unwrap_or_else(|errors| {
+        for e in errors {
+            error!("Config error: {}", e);
+        }
+        std::process::exit(1);
+    });
+    if let Some(semesters) = options.value_of("semesters") {
+        config.ustc.semesters = split_list(semesters);
+    } else if options.is_present("current") {
+        config.ustc.semesters = vec![ustc_get_grade::CURRENT_SEMESTER.to_owned()];
+    }
+    logging::redact(&[config.credentials.secret()]);
+    if let Some(mail) = &config.mail {
+        logging::redact(&[&mail.pass_cache]);
+    }
+    if let Some(telegram) = &config.telegram {
+        logging::redact(&[&telegram.bot_token]);
+    }
+    if let Some(log) = &config.log {
+        if let Err(e) = logging::configure(log) {
+            error!("Config error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+    config.show_letters = options.is_present("show-letters");
+    configure_throttle(&config.ustc);
+
+    if options.is_present("check") {
+        let ok = run_checks(&config);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if options.is_present("chart") {
+        match output::history_chart(config.output.as_ref()) {
+            Ok(chart) => println!("{}", chart.trim_end()),
+            Err(e) => {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(path) = options.value_of("test-fixture") {
+        if let Err(e) = run_fixture(&config, path) {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if options.is_present("once") {
+        let markdown = options.value_of("format") == Some("markdown");
+        let pie_chart = options.is_present("pie-chart");
+        let search = options.value_of("search");
+        let xlsx_path = options.value_of("xlsx-path");
+        if let Err(e) = run_once(&config, markdown, pie_chart, search, xlsx_path) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(m) = &config.metrics {
+        if let Err(e) = metrics::serve(&m.listen) {
+            error!("Config error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(s) = &config.status {
+        if let Err(e) = status::serve(s) {
+            error!("Config error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let cache = api::GradeCache::default();
+    if let Some(h) = &config.http {
+        if let Err(e) = api::serve(&h.listen, cache.clone()) {
+            error!("Config error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+
+    shutdown::install();
+    if let Err(e) = run(&config, &cache) {
+        error!("{}", e);
+        config
+            .mail
+            .send("Get Grade Error", EmailContent::Plain(format!("{}", e)))
+            .unwrap();
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rand::SeedableRng;
+    use ustc_get_grade::test_utils::mock_grade;
+
+    #[test]
+    fn truncated_report() {
+        let grade = mock_grade(&["2019年春季学期", "2019年秋季学期"]);
+        let render = |grade: &Grade, omitted| {
+            let names = grade
+                .scores
+                .iter()
+                .flat_map(|s| s.courses.iter().map(|c| c.name.as_str()))
+                .join(",");
+            format!("GPA {:.2}: {} {}", grade.gpa.unwrap(), names, omitted)
+        };
+        let full = fit_report(&grade, 1024, render);
+        assert_eq!(
+            full,
+            "GPA 3.52: 数学分析(B1),线性代数(B1),数学分析(B2),大学物理-基础实验A 0"
+        );
+        assert_eq!(
+            fit_report(&grade, full.len() - 1, render),
+            "GPA 3.52: 数学分析(B1),线性代数(B1),数学分析(B2) 1"
+        );
+        assert_eq!(fit_report(&grade, 40, render), "GPA 3.52: 数学分析(B1) 3");
+        assert_eq!(fit_report(&grade, 1, render), "GPA 3.52:  4");
+    }
+
+    #[test]
+    fn fixture_semesters_selected() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/sample_grade.json");
+        let mut config = check_config(env_config(&env_vars()).unwrap()).unwrap();
+        config.ustc.semesters = vec!["2019年秋季学期".to_owned()];
+        let grade = fixture_grade(&config, path).unwrap();
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2019年秋季学期"]);
+
+        config.ustc.semesters = vec!["all".to_owned()];
+        assert_eq!(fixture_grade(&config, path).unwrap().scores.len(), 2);
+        config.ustc.semesters = vec!["current".to_owned()];
+        let grade = fixture_grade(&config, path).unwrap();
+        let names: Vec<_> = grade.scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2019年秋季学期"]);
+
+        config.ustc.semesters = vec!["2019年秋学期".to_owned()];
+        let err = fixture_grade(&config, path).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(r#""2019年秋学期" (did you mean "2019年秋季学期"?)"#));
+    }
+
+    #[test]
+    fn check_lines() {
+        assert_eq!(check_line("USTC login", &Ok(())), "USTC login: ok");
+        let error = anyhow::Error::from(ustc_get_grade::Error::JWLoginFailed);
+        assert_eq!(
+            check_line("USTC login", &Err(error)),
+            format!(
+                "USTC login: failed: {}",
+                ustc_get_grade::Error::JWLoginFailed
+            )
+        );
+        let error = anyhow::anyhow!("535 Authentication failed").context("SMTP");
+        assert_eq!(
+            check_line("SMTP login", &Err(error)),
+            "SMTP login: failed: SMTP: 535 Authentication failed"
+        );
+    }
+
+    #[test]
+    fn search_results_by_semester() {
+        let grade = mock_grade(&[]);
+        let text = format_search_text(&ustc_get_grade::search_courses(&grade, "数学"), 40);
+        assert_eq!(
+            text,
+            "2019年春季学期
++--------------+------+------+
+| 课程         | 成绩 | 学分 |
++--------------+------+------+
+| 数学分析(B1) | 92   | 6    |
++--------------+------+------+
+2019年秋季学期
++--------------+------+------+
+| 课程         | 成绩 | 学分 |
++--------------+------+------+
+| 数学分析(B2) | 78   | 6    |
++--------------+------+------+
+"
+        );
+
+        assert_eq!(format_search_text(&[], 40), "No courses found\n");
+    }
+
+    fn env_vars() -> HashMap<String, String> {
+        [
+            ("USTC_USERNAME", "PB19000000"),
+            ("USTC_PASSWORD", "ustc-pass"),
+            ("USTC_SEMESTERS", "2019年秋季学期, 2020年春季学期,"),
+            ("USTC_INTERVAL", "30"),
+            ("MAIL_USERNAME", "me@example.com"),
+            ("MAIL_PASSWORD", "mail-pass"),
+            ("MAIL_SERVER", "smtp.example.com"),
+            ("MAIL_SENDTO", "me@example.com,friend@example.com"),
+        ]
+        .iter()
+        .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+    }
+
+    #[test]
+    fn config_from_reader() {
+        let toml = r#"
+            [mail]
+            username = "me@example.com"
+            password = "mail-pass"
+            server = "smtp.example.com"
+            sendto = ["me@example.com"]
+
+            [ustc]
+            username = "PB19000000"
+            password = "ustc-pass"
+            semesters = ["2019年秋季学期"]
+            interval = 30
+        "#;
+        let config = read_config(toml.as_bytes()).unwrap();
+        assert_eq!(
+            config.credentials,
+            Credentials::password("PB19000000", "ustc-pass")
+        );
+        assert_eq!(config.mail.as_ref().unwrap().pass_cache, "mail-pass");
+        assert_eq!(config.ustc.semesters, ["2019年秋季学期"]);
+
+        let ids = toml.replace(r#"["2019年秋季学期"]"#, r#"[121, "2019年春季学期"]"#);
+        let config = read_config(ids.as_bytes()).unwrap();
+        assert_eq!(config.ustc.semesters, ["121", "2019年春季学期"]);
+
+        let mail_start = toml.find("[mail]").unwrap();
+        let without_mail = toml[..mail_start].to_owned()
+            + &toml[toml.find("[ustc]").unwrap()..]
+            + "[output]\ndir = \"/tmp/grade\"\n";
+        let config = read_config(without_mail.as_bytes()).unwrap();
+        assert!(config.mail.is_none());
+        assert!(config.output.is_some());
+        assert!(build_notifiers(&config, &config.mail).unwrap().is_empty());
+        let content = EmailContent::Plain("Getting the grade failed".to_owned());
+        assert!(config.mail.send("Get Grade Error", content).is_ok());
+
+        let too_frequent = toml.replace("interval = 30", "interval = 5");
+        assert!(read_config(too_frequent.as_bytes()).is_err());
+
+        let scheduled = toml.replace("interval = 30", r#"schedule = "*/10 8-23 * * *""#);
+        let config = read_config(scheduled.as_bytes()).unwrap();
+        assert!(config.ustc.parsed_schedule.is_some());
+        let both = toml.replace("interval = 30", "interval = 30\nschedule = \"0 * * * *\"");
+        assert!(read_config(both.as_bytes()).is_err());
+        let invalid = toml.replace("interval = 30", r#"schedule = "*/10 8-24 * * *""#);
+        let errors = read_config(invalid.as_bytes()).unwrap_err();
+        assert_eq!(
+            errors,
+            ["Invalid schedule `*/10 8-24 * * *': Invalid hour: `8-24' is not within 0-23"]
+        );
+    }
+
+    #[test]
+    fn next_fetch_time() {
+        let now = Local.with_ymd_and_hms(2020, 1, 15, 8, 3, 0).unwrap();
+        let mut config = read_config(
+            r#"
+            [mail]
+            username = "me@example.com"
+            password = "mail-pass"
+            server = "smtp.example.com"
+            sendto = ["me@example.com"]
+
+            [ustc]
+            username = "PB19000000"
+            password = "ustc-pass"
+            semesters = ["2019年秋季学期"]
+            interval = 30
+            interval_jitter_percent = 0
+        "#
+            .as_bytes(),
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            next_fetch(&config.ustc, now, &mut rng).unwrap(),
+            Local.with_ymd_and_hms(2020, 1, 15, 8, 33, 0).unwrap()
+        );
+
+        assert!(!config.ustc.log_heartbeat);
+        let client = grade_client(&config.ustc);
+        assert_eq!(
+            client,
+            GradeClient::new(client.user_agent())
+                .max_retries(3)
+                .retry_base_delay(Duration::from_secs(5))
+        );
+        config.ustc.fetch_rank = true;
+        config.ustc.http2 = true;
+        assert_eq!(
+            grade_client(&config.ustc),
+            client.clone().fetch_rank(true).http2(true)
+        );
+        assert_eq!(
+            heartbeat(mock_grade(&[]).gpa, Duration::from_secs(30 * 60)),
+            "Heartbeat: GPA=3.52, polling again in 30.0 minutes"
+        );
+
+        // The schedule is not jittered
+        config.ustc.interval_jitter_percent = 50.;
+        config.ustc.parsed_schedule = Some(Schedule::new("*/10 8-23 * * *", None).unwrap());
+        assert_eq!(
+            next_fetch(&config.ustc, now, &mut rng).unwrap(),
+            Local.with_ymd_and_hms(2020, 1, 15, 8, 10, 0).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pass_exec_output() {
+        assert_eq!(get_output("echo secret").unwrap(), "secret");
+        assert_eq!(get_output("printf 'a\\nb\\n\\n'").unwrap(), "a\nb");
+
+        let err = get_output("echo oops >&2; exit 3").unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("exit status: 3"), "{}", message);
+        assert!(message.ends_with(": oops"), "{}", message);
+
+        let err = get_output("printf '\\377'").unwrap_err();
+        assert!(
+            format!("{:#}", err).starts_with("Invalid UTF-8"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn jittered_interval_within_band() {
+        let mut rng = StdRng::seed_from_u64(303);
+        let interval = Duration::from_secs(30 * 60);
+        let waits: Vec<_> = (0..1000)
+            .map(|_| jittered(interval, 0.1, &mut rng))
+            .collect();
+        assert!(waits
+            .iter()
+            .all(|&w| interval.mul_f64(0.9) <= w && w <= interval.mul_f64(1.1)));
+        // Actually randomized, not stuck at one end of the band
+        assert!(waits.iter().any(|&w| w < interval.mul_f64(0.95)));
+        assert!(waits.iter().any(|&w| w > interval.mul_f64(1.05)));
+
+        assert_eq!(jittered(interval, 0., &mut rng), interval);
+    }
+
+    #[test]
+    fn config_errors_all_reported() {
+        let toml = r#"
+            [mail]
+            username = "me@example.com"
+            pass_exec = " "
+            server = "smtp.example.com"
+            sendto = ["me@example.com", "friend.example.com"]
+
+            [ustc]
+            username = "PB19000000"
+            password = "ustc-pass"
+            semesters = []
+            interval = 5
+            interval_jitter = 1.5
+            interval_jitter_percent = 60
+            max_retries = 11
+            retry_base_delay_seconds = -1
+        "#
+        .replace("[ustc]", "error_email_cooldown = -60\n[ustc]");
+        let errors = read_config(toml.as_bytes()).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "interval must be ≥ 10 minutes, found 5",
+                "interval_jitter must be in [0, 1), found 1.5",
+                "interval_jitter_percent must be in [0, 50], found 60",
+                "max_retries must be ≤ 10, found 11",
+                "retry_base_delay_seconds must be ≥ 0, found -1",
+                "error_email_cooldown must be ≥ 0, found -60",
+                "semesters must not be empty, use [\"all\"] to track all the semesters",
+                "`friend.example.com' in sendto is not an email address",
+                "pass_exec in [mail] must not be empty",
+            ]
+        );
+
+        let wrong_type = toml.replace("interval = 5", r#"interval = "5""#);
+        let errors = read_config(wrong_type.as_bytes()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Invalid config: "));
+    }
+
+    #[test]
+    fn bad_throttle_seconds() {
+        let toml = r#"
+            [ustc]
+            username = "PB19000000"
+            password = "ustc-pass"
+            semesters = ["all"]
+            interval = 30
+            min_login_interval = nan
+            captcha_cooldown = -inf
+        "#;
+        assert_eq!(
+            read_config(toml.as_bytes()).unwrap_err(),
+            [
+                "min_login_interval must be ≥ 0, found NaN",
+                "captcha_cooldown must be ≥ 0, found -inf",
+            ]
+        );
+        let negative = toml.replace("nan", "-1").replace("-inf", "inf");
+        assert_eq!(
+            read_config(negative.as_bytes()).unwrap_err(),
+            [
+                "min_login_interval must be ≥ 0, found -1",
+                "captcha_cooldown must be ≥ 0, found inf",
+            ]
+        );
+    }
+
+    #[test]
+    fn credentials_from_pass_exec() {
+        let login = UstcLogin::Password(Password::Exec {
+            pass_exec: "echo from-exec".to_owned(),
+        });
+        let credentials = login.resolve("PB19000000").unwrap();
+        assert_eq!(
+            credentials,
+            Credentials::password("PB19000000", "from-exec")
+        );
+        assert!(!format!("{:?}", credentials).contains("from-exec"));
+
+        let login = UstcLogin::Password(Password::Exec {
+            pass_exec: "exit 3".to_owned(),
+        });
+        let err = login.resolve("PB19000000").unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Cannot get the USTC password: `exit 3' failed"));
+    }
+
+    #[test]
+    fn tgc_login() {
+        let toml = r#"
+            username = "PB19000000"
+            tgc = "TGT-1-abc"
+            semesters = ["all"]
+            interval = 30
+        "#;
+        let ustc: Ustc = toml::from_str(toml).unwrap();
+        let credentials = ustc.login.resolve(&ustc.username).unwrap();
+        assert_eq!(credentials, Credentials::Tgc("TGT-1-abc".to_owned()));
+        assert!(!format!("{:?}", credentials).contains("TGT-1-abc"));
+        assert_eq!(ustc.login.keyring_entry(&ustc.username), None);
+
+        let ustc: Ustc =
+            toml::from_str(&toml.replace(r#"tgc = "TGT-1-abc""#, r#"tgc_exec = "exit 3""#))
+                .unwrap();
+        let err = ustc.login.resolve(&ustc.username).unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Cannot get the USTC TGC: `exit 3' failed"));
+    }
+
+    #[test]
+    fn keyring_service_password() {
+        let toml = r#"
+            username = "PB19000000"
+            password = { keyring = "ustc" }
+            semesters = ["all"]
+            interval = 30
+        "#;
+        let ustc: Ustc = toml::from_str(toml).unwrap();
+        assert_eq!(
+            ustc.login.keyring_entry(&ustc.username),
+            Some(("ustc", "PB19000000"))
+        );
+
+        let ustc: Ustc = toml::from_str(&toml.replace(
+            r#"password = { keyring = "ustc" }"#,
+            "keyring_service = \"ustc\"\nkeyring_user = \"me\"",
+        ))
+        .unwrap();
+        assert_eq!(ustc.login.keyring_entry("PB19000000"), Some(("ustc", "me")));
+
+        let plain = Password::Plain {
+            password: "ustc-pass".to_owned(),
+        };
+        assert_eq!(plain.keyring_entry("PB19000000"), None);
+    }
+
+    #[test]
+    fn config_search_order() {
+        let dir = std::env::temp_dir().join(format!("ustc-get-grade-{}", std::process::id()));
+        let (user, cwd) = (dir.join("user"), dir.join("cwd"));
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::create_dir_all(&cwd).unwrap();
+        let paths = [user.join(DEFAULT_CONFIG), cwd.join(DEFAULT_CONFIG)];
+
+        assert_eq!(find_config(&paths), None);
+        File::create(&paths[1]).unwrap();
+        assert_eq!(find_config(&paths), Some(paths[1].as_path()));
+        File::create(&paths[0]).unwrap();
+        assert_eq!(find_config(&paths), Some(paths[0].as_path()));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let defaults = config_search_paths();
+        assert_eq!(defaults.last().unwrap(), Path::new(DEFAULT_CONFIG));
+        if defaults.len() == 2 {
+            assert!(defaults[0].ends_with("ustc-get-grade/config.toml"));
+        }
+    }
+
+    #[test]
+    fn config_from_env() {
+        let config = check_config(env_config(&env_vars()).unwrap()).unwrap();
+        assert_eq!(config.ustc.username, "PB19000000");
+        assert_eq!(config.credentials.secret(), "ustc-pass");
+        assert_eq!(config.ustc.semesters, ["2019年秋季学期", "2020年春季学期"]);
+        assert_eq!(config.ustc.interval, Some(30.));
+        assert!(!config.ustc.send_first);
+        assert_eq!(config.mail.as_ref().unwrap().server, "smtp.example.com");
+        assert_eq!(config.mail.as_ref().unwrap().pass_cache, "mail-pass");
+        let sendto: Vec<_> = config
+            .mail
+            .as_ref()
+            .unwrap()
+            .sendto
+            .iter()
+            .map(|r| r.address())
+            .collect();
+        assert_eq!(sendto, ["me@example.com", "friend@example.com"]);
+    }
+
+    #[test]
+    fn config_from_env_errors() {
+        let mut vars = env_vars();
+        vars.remove("MAIL_SERVER");
+        let err = env_config(&vars).unwrap_err();
+        assert_eq!(err.to_string(), "Missing environment variable MAIL_SERVER");
+
+        let mut vars = env_vars();
+        vars.insert("USTC_INTERVAL".to_owned(), "often".to_owned());
+        assert!(env_config(&vars).is_err());
+
+        let mut vars = env_vars();
+        vars.insert("USTC_INTERVAL".to_owned(), "5".to_owned());
+        assert!(check_config(env_config(&vars).unwrap()).is_err());
+    }
+
+    #[test]
+    fn comma_separated_list() {
+        assert_eq!(split_list("a, b ,,c,"), ["a", "b", "c"]);
+        assert!(split_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn user_agent_precedence() {
+        let env = || Some("env/1.0".to_owned());
+        assert_eq!(user_agent(Some("config/1.0"), env()), "config/1.0");
+        assert_eq!(user_agent(None, env()), "env/1.0");
+        assert_eq!(user_agent(None, None), DEFAULT_UA);
+        assert_eq!(user_agent(None, Some(String::new())), DEFAULT_UA);
+    }
+
+    #[test]
+    fn smtp_transient_errors_retried() {
+        use lettre::smtp::error::Error as SmtpError;
+        use lettre::smtp::response::{Category, Code, Detail, Response, Severity};
+
+        let reply = |severity| {
+            Response::new(
+                Code::new(severity, Category::MailSystem, Detail::One),
+                vec!["Try again later".to_owned()],
+            )
+        };
+        let transient = || SmtpError::Transient(reply(Severity::TransientNegativeCompletion));
+        let no_wait = Duration::from_secs(0);
+
+        let mut attempts = 0;
+        let result = retry_transient(3, no_wait, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(transient())
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+
+        attempts = 0;
+        let result: Result<(), _> = retry_transient(3, no_wait, || {
+            attempts += 1;
+            Err(transient())
+        });
+        assert!(matches!(result, Err(SmtpError::Transient(_))));
+        assert_eq!(attempts, 3);
+
+        attempts = 0;
+        let result: Result<(), _> = retry_transient(3, no_wait, || {
+            attempts += 1;
+            Err(SmtpError::Permanent(reply(
+                Severity::PermanentNegativeCompletion,
+            )))
+        });
+        assert!(matches!(result, Err(SmtpError::Permanent(_))));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn from_header() {
+        let toml = r#"
+            username = "a1b2@mail.ustc.edu.cn"
+            password = "passw0rd"
+            server = "smtp.example.com"
+            sendto = ["me@example.com"]
+        "#;
+        let from = |toml: &str| {
+            let mail: Mail = toml::from_str(toml).unwrap();
+            let email = build_email(
+                &mail,
+                "Grade Update",
+                EmailContent::Plain("text".to_owned()),
+                &["me@example.com"],
+            )
+            .unwrap();
+            let email: lettre::SendableEmail = email.into();
+            let message = email.message_to_string().unwrap();
+            message
+                .lines()
+                .find(|l| l.starts_with("From: "))
+                .unwrap()
+                .to_owned()
+        };
+        assert_eq!(from(toml), "From: <a1b2@mail.ustc.edu.cn>");
+        assert_eq!(
+            from(&format!(
+                "{}from_address = \"bot@example.com\"\nfrom_name = \"Grade Bot\"",
+                toml
+            )),
+            "From: \"Grade Bot\" <bot@example.com>"
+        );
+    }
+
+    #[test]
+    fn per_recipient_format() {
+        let mail: Mail = toml::from_str(
+            r#"
+            username = "abc@example.com"
+            password = "passw0rd"
+            server = "smtp.example.com"
+            sendto = [
+                "a@example.com",
+                { address = "b@example.com", format = "text" },
+                { address = "c@example.com", format = "html" },
+                { address = "d@example.com" },
+                { address = "e@example.com", format = "text" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let groups = recipients_by_format(&mail.sendto);
+        assert_eq!(
+            groups,
+            [
+                (
+                    MailFormat::Alternative,
+                    vec!["a@example.com", "d@example.com"]
+                ),
+                (MailFormat::Text, vec!["b@example.com", "e@example.com"]),
+                (MailFormat::Html, vec!["c@example.com"]),
+            ]
+        );
+
+        let content = EmailContent::Alternative("text".to_owned(), "html".to_owned());
+        let bodies: Vec<_> = groups.iter().map(|(f, _)| content.for_format(*f)).collect();
+        assert_eq!(
+            bodies,
+            [
+                content.clone(),
+                EmailContent::Plain("text".to_owned()),
+                EmailContent::Html("html".to_owned()),
+            ]
+        );
+        let plain = EmailContent::Plain("error".to_owned());
+        assert_eq!(plain.for_format(MailFormat::Html), plain);
+    }
+
+    /// Records the emails instead of sending them, taking `delay` to send
+    /// each. Copies record to the same list.
+    #[derive(Default, Clone)]
+    struct RecordingMailer(
+        std::sync::Arc<std::sync::Mutex<Vec<(String, EmailContent)>>>,
+        Duration,
+    );
+
+    impl Mailer for RecordingMailer {
+        fn send(&self, subject: &str, content: EmailContent) -> Result<()> {
+            thread::sleep(self.1);
+            self.0.lock().unwrap().push((subject.to_owned(), content));
+            Ok(())
+        }
+
+        fn detach(&self) -> Box<dyn Mailer + Send> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl RecordingMailer {
+        fn take(&self) -> Vec<(String, EmailContent)> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    /// Takes 300 ms to notify, failing if `fail`
+    struct SlowNotifier {
+        name: &'static str,
+        fail: bool,
+    }
+
+    impl Notifier for SlowNotifier {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn notify<'a>(
+            &'a self,
+            _grade: &'a Grade,
+            _diff: Option<&'a GradeDiff>,
+        ) -> BoxFuture<'a, Result<(), ustc_get_grade::Error>> {
+            async move {
+                tokio::time::delay_for(Duration::from_millis(300)).await;
+                if self.fail {
+                    Err(ustc_get_grade::Error::NotifyFailed("HTTP 502".to_owned()))
+                } else {
+                    Ok(())
+                }
+            }
+            .boxed()
+        }
+    }
+
+    #[test]
+    fn concurrent_notify() {
+        let slow = |name, fail| -> Box<dyn Notifier> { Box::new(SlowNotifier { name, fail }) };
+        let notifiers = [slow("webhook", true), slow("telegram", false)];
+        let mailer = RecordingMailer::default();
+        let grade = mock_grade(&[]);
+
+        let start = Instant::now();
+        assert!(!notify_all(&mailer, &notifiers, &grade, None, true).unwrap());
+        assert!(start.elapsed() < Duration::from_millis(550));
+        let emails = mailer.take();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(
+            emails[0].1,
+            EmailContent::Plain(
+                "Send notification failed:\nwebhook: Notification failed: HTTP 502".to_owned()
+            )
+        );
+
+        assert!(!notify_all(&mailer, &notifiers, &grade, None, false).unwrap());
+        assert_eq!(mailer.take().len(), 1);
+    }
+
+    #[test]
+    fn slow_email_does_not_delay_others() {
+        let mailer = RecordingMailer(Default::default(), Duration::from_millis(300));
+        let notifiers: [Box<dyn Notifier>; 3] = [
+            Box::new(EmailNotifier {
+                mailer: &mailer,
+                formatter: Box::new(export::DefaultFormatter),
+            }),
+            Box::new(SlowNotifier {
+                name: "webhook",
+                fail: false,
+            }),
+            Box::new(SlowNotifier {
+                name: "telegram",
+                fail: false,
+            }),
+        ];
+        let grade = mock_grade(&[]);
+
+        let start = Instant::now();
+        assert!(notify_all(&mailer, &notifiers, &grade, None, true).unwrap());
+        assert!(start.elapsed() < Duration::from_millis(550));
+        let emails = mailer.take();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].0, "Grade Report");
+    }
+
+    #[test]
+    fn one_email_per_change() {
+        let config = read_config(
+            r#"
+            [mail]
+            username = "me@example.com"
+            password = "mail-pass"
+            server = "smtp.example.com"
+            sendto = [{ address = "me@example.com", format = "text" }]
+
+            [ustc]
+            username = "PB19000000"
+            password = "ustc-pass"
+            semesters = ["all"]
+            interval = 30
+        "#
+            .as_bytes(),
+        )
+        .unwrap();
+        let mailer = RecordingMailer::default();
+        let cache = api::GradeCache::default();
+        let notifiers = build_notifiers(&config, &mailer).unwrap();
+        let mut poller = Poller::new(&config, &mailer, notifiers, &cache, mock_grade(&[]));
+
+        poller.handle(Ok(mock_grade(&[]))).unwrap();
+        assert!(mailer.take().is_empty());
+
+        let mut changed = mock_grade(&[]);
+        changed.scores[2].courses[0].score = "81".to_owned();
+        poller.handle(Ok(changed.clone())).unwrap();
+        let emails = mailer.take();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].0, "Grade Report");
+        match &emails[0].1 {
+            EmailContent::Alternative(text, _) => assert!(text.contains("81"), "{}", text),
+            content => panic!("unexpected content: {:?}", content),
+        }
+        assert_eq!(cache.lock().unwrap().as_ref(), Some(&changed));
+
+        poller.handle(Ok(changed.clone())).unwrap();
+        assert!(mailer.take().is_empty());
+
+        for _ in 0..3 {
+            poller
+                .handle(Err(ustc_get_grade::Error::JWLoginFailed))
+                .unwrap();
+        }
+        let emails = mailer.take();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].0, "Get Grade Error");
+        poller.handle(Ok(changed)).unwrap();
+        assert!(mailer.take().is_empty());
+
+        let mut unpublished = mock_grade(&[]);
+        unpublished.scores.clear();
+        unpublished.sem_gpa = Some(0.);
+        poller.handle(Ok(unpublished)).unwrap();
+        assert!(mailer.take().is_empty());
+        assert!(ustc_get_grade::is_grade_meaningful(&poller.old_grade));
+
+        let wrong = ustc_get_grade::Error::WrongPassword("用户名或密码错误".to_owned());
+        let err = poller.handle(Err(wrong)).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Stopped fetching the grade: Wrong username or password: 用户名或密码错误"
+        );
+        let emails = mailer.take();
+        assert_eq!(emails.len(), 1);
+        match &emails[0].1 {
+            EmailContent::Plain(text) => assert!(text.contains("restart"), "{}", text),
+            content => panic!("unexpected content: {:?}", content),
+        }
+    }
+
+    /// Polls mock passport and Jiaowu servers, which log in with any TGC and
+    /// serve the grade of 2019 Fall, raising a score once `changed` is set
+    #[test]
+    fn poll_mock_servers() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use ustc_get_grade::test_utils::{mock_grade_response, mock_semesters_response};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (passport, jw) = (format!("{}/passport", base), format!("{}/jw", base));
+        let changed = Arc::new(AtomicBool::new(false));
+        let (jw_routes, changed_routes) = (jw.clone(), changed.clone());
+        let route = move |path: &str| -> (&str, String, String) {
+            if path.starts_with("/passport/login?") {
+                let ticket = format!("{}/ucas-sso/login?ticket=ST-1", jw_routes);
+                ("302 Found", ticket, String::new())
+            } else if path.starts_with("/jw/ucas-sso/login?") {
+                ("302 Found", format!("{}/home", jw_routes), String::new())
+            } else if path.ends_with("/getSemesters") {
+                ("200 OK", String::new(), mock_semesters_response())
+            } else if path.contains("/getGradeList?") {
+                let semesters: &[&str] = if path.ends_with("semesterIds=") {
+                    &[]
+                } else {
+                    &["2019年秋季学期"]
+                };
+                let list = mock_grade_response(semesters);
+                if changed_routes.load(Ordering::SeqCst) {
+                    ("200 OK", String::new(), list.replace("\"78\"", "\"81\""))
+                } else {
+                    ("200 OK", String::new(), list)
+                }
+            } else {
+                ("200 OK", String::new(), "home".to_owned())
+            }
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let request_line = BufReader::new(&stream).lines().next().unwrap().unwrap();
+                let (status, location, body) = route(request_line.split(' ').nth(1).unwrap());
+                write!(
+                    &stream,
+                    "HTTP/1.1 {}\r\nLocation: {}\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                    status,
+                    location,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let mut config = read_config(
+            r#"
+        [mail]
+        username = "me@example.com"
+        password = "mail-pass"
+        server = "smtp.example.com"
+        sendto = [{ address = "me@example.com", format = "text" }]
+
+        [ustc]
+        username = "PB19000000"
+        password = "ustc-pass"
+        semesters = ["2019年秋季学期"]
+        interval = 30
+    "#
+            .as_bytes(),
+        )
+        .unwrap();
+        config.credentials = Credentials::Tgc("TGT-1-abc".to_owned());
+        let http = ustc_get_grade::build_client("ustc-get-grade-test/1.0", None).unwrap();
+        let client = grade_client(&config.ustc)
+            .with_http_client(http)
+            .servers(&passport, &jw);
+        let request = grade_request(&config, client);
+
+        let mailer = RecordingMailer::default();
+        let cache = api::GradeCache::default();
+        let notifiers = build_notifiers(&config, &mailer).unwrap();
+        let old_grade = block_on(request.send()).unwrap();
+        let mut poller = Poller::new(&config, &mailer, notifiers, &cache, old_grade);
+
+        poller.poll(&request).unwrap();
+        assert!(mailer.take().is_empty());
+
+        changed.store(true, Ordering::SeqCst);
+        poller.poll(&request).unwrap();
+        let emails = mailer.take();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].0, "Grade Report");
+        match &emails[0].1 {
+            EmailContent::Alternative(text, _) => {
+                assert!(text.contains("数学分析(B2)"), "{}", text);
+                assert!(text.contains("81"), "{}", text);
+                assert!(!text.contains("78"), "{}", text);
+            }
+            content => panic!("unexpected content: {:?}", content),
+        }
+
+        poller.poll(&request).unwrap();
+        assert!(mailer.take().is_empty());
     }
 }
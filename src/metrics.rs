@@ -0,0 +1,197 @@
+//! Prometheus metrics of the watcher
+//!
+//! The values are always recorded, but only served on `/metrics` when built
+//! with the `metrics` feature. The server runs on its own thread, so it keeps
+//! answering while the main loop sleeps or retries.
+
+#![cfg_attr(not(feature = "metrics"), allow(dead_code))]
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ustc_get_grade::Grade;
+
+static METRICS: Metrics = Metrics::new();
+
+#[derive(Debug)]
+struct Metrics {
+    fetch_attempts: AtomicU64,
+    fetch_successes: AtomicU64,
+    fetch_failures: AtomicU64,
+    emails_sent: AtomicU64,
+    grade_changes: AtomicU64,
+    // Gauges are stored as the bits of `f64`s
+    last_success: AtomicU64,
+    gpa: AtomicU64,
+    credits: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            fetch_attempts: AtomicU64::new(0),
+            fetch_successes: AtomicU64::new(0),
+            fetch_failures: AtomicU64::new(0),
+            emails_sent: AtomicU64::new(0),
+            grade_changes: AtomicU64::new(0),
+            last_success: AtomicU64::new(0),
+            gpa: AtomicU64::new(0),
+            credits: AtomicU64::new(0),
+        }
+    }
+
+    fn fetched(&self, grade: Option<&Grade>) {
+        self.fetch_attempts.fetch_add(1, Ordering::Relaxed);
+        match grade {
+            Some(grade) => {
+                self.fetch_successes.fetch_add(1, Ordering::Relaxed);
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0., |d| d.as_secs_f64());
+                set(&self.last_success, now);
+                // NaN until the GPA is published
+                set(&self.gpa, grade.gpa.unwrap_or(f64::NAN));
+                set(&self.credits, grade.credits);
+            }
+            None => {
+                self.fetch_failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Renders in the Prometheus text exposition format
+    fn render(&self) -> String {
+        let counters = [
+            (
+                "fetch_attempts_total",
+                "Grade fetches attempted",
+                &self.fetch_attempts,
+            ),
+            (
+                "fetch_successes_total",
+                "Grade fetches succeeded",
+                &self.fetch_successes,
+            ),
+            (
+                "fetch_failures_total",
+                "Grade fetches failed",
+                &self.fetch_failures,
+            ),
+            ("emails_sent_total", "Emails sent", &self.emails_sent),
+            (
+                "grade_changes_total",
+                "Grade changes detected",
+                &self.grade_changes,
+            ),
+        ];
+        let gauges = [
+            (
+                "last_success_timestamp_seconds",
+                "Unix time of the last successful fetch",
+                &self.last_success,
+            ),
+            ("gpa", "Overall GPA in the last successful fetch", &self.gpa),
+            (
+                "credits",
+                "Credits earned in the last successful fetch",
+                &self.credits,
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in counters.iter() {
+            let value = value.load(Ordering::Relaxed);
+            write_metric(&mut out, name, help, "counter", value);
+        }
+        for (name, help, value) in gauges.iter() {
+            write_metric(&mut out, name, help, "gauge", get(value));
+        }
+        out
+    }
+}
+
+fn set(gauge: &AtomicU64, value: f64) {
+    gauge.store(value.to_bits(), Ordering::Relaxed);
+}
+
+fn get(gauge: &AtomicU64) -> f64 {
+    f64::from_bits(gauge.load(Ordering::Relaxed))
+}
+
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    kind: &str,
+    value: impl std::fmt::Display,
+) {
+    let _ = write!(
+        out,
+        "# HELP ustc_get_grade_{name} {}\n# TYPE ustc_get_grade_{name} {}\nustc_get_grade_{name} {}\n",
+        help,
+        kind,
+        value,
+        name = name,
+    );
+}
+
+/// Records a successful fetch of `grade`
+pub fn fetch_succeeded(grade: &Grade) {
+    METRICS.fetched(Some(grade));
+}
+
+pub fn fetch_failed() {
+    METRICS.fetched(None);
+}
+
+pub fn email_sent() {
+    METRICS.emails_sent.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn grade_changed() {
+    METRICS.grade_changes.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Serves `/metrics` on `listen` in a background thread
+#[cfg(feature = "metrics")]
+pub fn serve(listen: &str) -> anyhow::Result<()> {
+    crate::http::serve(listen, |path| match path {
+        "/metrics" => {
+            crate::http::Response::new("200 OK", "text/plain; version=0.0.4", METRICS.render())
+        }
+        _ => crate::http::Response::not_found(),
+    })?;
+    log::info!("Serving metrics on {}", listen);
+    Ok(())
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn serve(_listen: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Built without the `metrics` feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ustc_get_grade::test_utils::mock_grade;
+
+    #[test]
+    fn render_counters_and_gauges() {
+        let metrics = Metrics::new();
+        metrics.fetched(Some(&mock_grade(&[])));
+        metrics.fetched(None);
+        metrics.grade_changes.fetch_add(1, Ordering::Relaxed);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE ustc_get_grade_fetch_attempts_total counter\n"));
+        assert!(text.contains("\nustc_get_grade_fetch_attempts_total 2\n"));
+        assert!(text.contains("\nustc_get_grade_fetch_successes_total 1\n"));
+        assert!(text.contains("\nustc_get_grade_fetch_failures_total 1\n"));
+        assert!(text.contains("\nustc_get_grade_emails_sent_total 0\n"));
+        assert!(text.contains("\nustc_get_grade_grade_changes_total 1\n"));
+        assert!(text.contains("# TYPE ustc_get_grade_gpa gauge\n"));
+        assert!(text.contains("\nustc_get_grade_gpa 3.52\n"));
+        assert!(text.contains("\nustc_get_grade_credits 18.5\n"));
+        assert!(get(&metrics.last_success) > 0.);
+    }
+}
@@ -0,0 +1,52 @@
+use super::{http_client, summary, Notifier};
+use crate::{Error, Grade, GradeDiff};
+use futures::future::{BoxFuture, FutureExt};
+use serde_json::json;
+
+/// Sends iOS push notifications through a relay server that forwards them to
+/// Apple Push Notification Service, so that no Apple Developer account is
+/// needed
+#[derive(Debug)]
+pub struct ApnsRelayNotifier {
+    client: reqwest::Client,
+    relay_url: String,
+    device_token: String,
+}
+
+impl ApnsRelayNotifier {
+    pub fn new(relay_url: &str, device_token: &str) -> Result<Self, Error> {
+        Ok(ApnsRelayNotifier {
+            client: http_client()?,
+            relay_url: relay_url.to_owned(),
+            device_token: device_token.to_owned(),
+        })
+    }
+}
+
+impl Notifier for ApnsRelayNotifier {
+    fn name(&self) -> &str {
+        "APNs relay"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        grade: &'a Grade,
+        diff: Option<&'a GradeDiff>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        let payload = json!({
+            "device_token": self.device_token,
+            "title": "Grade Report",
+            "body": summary(grade, diff),
+        });
+        async move {
+            self.client
+                .post(&self.relay_url)
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
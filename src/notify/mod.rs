@@ -0,0 +1,96 @@
+//! Channels that notify users of grade changes
+//!
+//! Every channel implements [`Notifier`](trait.Notifier.html), so that other
+//! crates can add their own channels next to the built-in ones.
+
+use crate::export::format_gpa;
+use crate::{format_score_change, Error, Grade, GradeDiff};
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+mod apns;
+mod telegram;
+mod webhook;
+
+pub use apns::ApnsRelayNotifier;
+pub use telegram::TelegramNotifier;
+pub use webhook::WebhookNotifier;
+
+/// Timeout of the requests sent by the built-in notifiers
+pub const NOTIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A channel that notifies users of a grade
+///
+/// `notify` is called with the newly fetched grade, and its differences from
+/// the previous one if there was a previous one. It may be called again with
+/// the same grade and diff when this or another notifier failed, so repeated
+/// notifications must be harmless. Implementations should give up within a
+/// bounded time, e.g. [`NOTIFY_TIMEOUT`](constant.NOTIFY_TIMEOUT.html), since
+/// the caller waits for all the notifiers before fetching again.
+pub trait Notifier: Send + Sync {
+    /// Name of the channel in logs
+    fn name(&self) -> &str;
+
+    fn notify<'a>(
+        &'a self,
+        grade: &'a Grade,
+        diff: Option<&'a GradeDiff>,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+/// A short plain text summary of `grade`, listing the changed courses in
+/// `diff` if given
+pub fn summary(grade: &Grade, diff: Option<&GradeDiff>) -> String {
+    let mut summary = format!(
+        "Total GPA: {}, Semester GPA: {}",
+        format_gpa(grade.gpa),
+        format_gpa(grade.sem_gpa)
+    );
+    if let Some(rank) = &grade.rank {
+        summary += &format!(", Rank: {}", rank);
+    }
+    for change in diff.iter().flat_map(|d| d.courses.iter()) {
+        summary += &match &change.old {
+            Some(old) => format!(
+                "\n{}: {}",
+                change.new.name,
+                format_score_change(&old.score, &change.new.score)
+            ),
+            None => format!("\n{}: {}", change.new.name, change.new.score),
+        };
+    }
+    summary
+}
+
+fn http_client() -> Result<reqwest::Client, Error> {
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("ustc-get-grade/", env!("CARGO_PKG_VERSION")))
+        .timeout(NOTIFY_TIMEOUT)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+    use crate::Rank;
+
+    #[test]
+    fn summary_lists_changes() {
+        let old = mock_grade(&["2019年秋季学期"]);
+        let mut new = mock_grade(&["2019年夏季学期", "2019年秋季学期"]);
+        new.scores[1].courses[0].score = "81".to_owned();
+
+        assert_eq!(summary(&new, None), "Total GPA: 3.52, Semester GPA: 3.30");
+        assert_eq!(
+            summary(&new, Some(&GradeDiff::between(&old, &new))),
+            "Total GPA: 3.52, Semester GPA: 3.30\n军事技能: 通过\n数学分析(B2): ↑ 78 → 81"
+        );
+
+        new.rank = Some(Rank::new(12, 143, "专业"));
+        assert_eq!(
+            summary(&new, None),
+            "Total GPA: 3.52, Semester GPA: 3.30, Rank: 12/143"
+        );
+    }
+}
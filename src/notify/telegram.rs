@@ -0,0 +1,50 @@
+use super::{http_client, summary, Notifier};
+use crate::{Error, Grade, GradeDiff};
+use futures::future::{BoxFuture, FutureExt};
+use serde_json::json;
+
+/// Sends messages through a Telegram bot
+#[derive(Debug)]
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot_token: &str, chat_id: &str) -> Result<Self, Error> {
+        Ok(TelegramNotifier {
+            client: http_client()?,
+            bot_token: bot_token.to_owned(),
+            chat_id: chat_id.to_owned(),
+        })
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "Telegram"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        grade: &'a Grade,
+        diff: Option<&'a GradeDiff>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        let payload = json!({
+            "chat_id": self.chat_id,
+            "text": summary(grade, diff),
+        });
+        async move {
+            self.client
+                .post(&url)
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
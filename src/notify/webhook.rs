@@ -0,0 +1,99 @@
+use super::{http_client, Notifier};
+use crate::{Error, Grade, GradeDiff};
+use futures::future::{BoxFuture, FutureExt};
+use serde_json::{json, Value};
+
+/// POSTs the grade and the changes as JSON to a URL
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        Ok(WebhookNotifier {
+            client: http_client()?,
+            url: url.to_owned(),
+        })
+    }
+}
+
+/// The JSON body of a webhook request
+fn payload(grade: &Grade, diff: Option<&GradeDiff>) -> Value {
+    let changes: Vec<_> = diff
+        .iter()
+        .flat_map(|d| d.courses.iter())
+        .map(|c| {
+            json!({
+                "semester": c.semester,
+                "course": c.new.name,
+                "old_score": c.old.as_ref().map(|o| &o.score),
+                "score": c.new.score,
+                "credits": c.new.credits,
+            })
+        })
+        .collect();
+    json!({
+        "gpa": grade.gpa,
+        "sem_gpa": grade.sem_gpa,
+        "credits": grade.credits,
+        "rank": grade.rank,
+        "gpa_change": diff.and_then(|d| d.gpa_change),
+        "sem_gpa_change": diff.and_then(|d| d.sem_gpa_change),
+        "changes": changes,
+        "markdown": grade.to_markdown(),
+    })
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        grade: &'a Grade,
+        diff: Option<&'a GradeDiff>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        let payload = payload(grade, diff);
+        async move {
+            self.client
+                .post(&self.url)
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_grade;
+
+    #[test]
+    fn webhook_payload() {
+        let old = mock_grade(&["2019年春季学期"]);
+        let new = mock_grade(&["2019年春季学期", "2019年夏季学期"]);
+        let diff = GradeDiff::between(&old, &new);
+
+        let body = payload(&new, Some(&diff));
+        assert_eq!(body["gpa"], json!(3.52));
+        assert_eq!(
+            body["changes"],
+            json!([{
+                "semester": "2019年夏季学期",
+                "course": "军事技能",
+                "old_score": null,
+                "score": "通过",
+                "credits": 1.0,
+            }])
+        );
+        assert_eq!(payload(&new, None)["gpa_change"], Value::Null);
+        assert_eq!(body["markdown"], json!(new.to_markdown()));
+    }
+}
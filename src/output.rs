@@ -0,0 +1,278 @@
+//! Reports written to files, e.g. for a dashboard built from them
+//!
+//! After every successful fetch, `latest.html`, `latest.txt` and
+//! `latest.json` are replaced in `dir` for each of `formats`, and when the
+//! grade changes `changed-<timestamp>.html` is written too. Files are
+//! written to a temporary file first and renamed, so that readers never see
+//! half of one.
+//!
+//! With `history`, the GPA of every fetch is also appended to
+//! `history.jsonl`, which `--chart` draws.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use ustc_get_grade::export::charts::ascii_line_chart;
+use ustc_get_grade::export::GradeFormatter;
+use ustc_get_grade::{Grade, GradeDiff};
+
+#[derive(Debug, Deserialize)]
+pub struct OutputConfig {
+    /// Created if missing
+    dir: PathBuf,
+    #[serde(default = "default_formats")]
+    formats: Vec<OutputFormat>,
+    /// Appends the GPA of every fetch to `history.jsonl`
+    #[serde(default)]
+    history: bool,
+}
+
+/// The GPA of a fetch, a line of `history.jsonl`
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    /// RFC 3339
+    time: String,
+    gpa: Option<f64>,
+}
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Html,
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Html => "html",
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+fn default_formats() -> Vec<OutputFormat> {
+    vec![OutputFormat::Html, OutputFormat::Text, OutputFormat::Json]
+}
+
+/// Replaces the latest reports with those of `grade`
+pub fn write_latest(
+    config: &OutputConfig,
+    grade: &Grade,
+    formatter: &dyn GradeFormatter,
+) -> Result<()> {
+    for &format in &config.formats {
+        let content = match format {
+            OutputFormat::Html => formatter.format_html(grade, None),
+            OutputFormat::Text => formatter.format_text(grade, None),
+            OutputFormat::Json => serde_json::to_string_pretty(grade)?,
+        };
+        let name = format!("latest.{}", format.extension());
+        write_atomic(&config.dir, &name, &content)?;
+    }
+    Ok(())
+}
+
+/// Writes the report of `grade` showing the changes in `diff`, named after
+/// `now`, and returns its path
+pub fn write_changed(
+    config: &OutputConfig,
+    grade: &Grade,
+    diff: &GradeDiff,
+    formatter: &dyn GradeFormatter,
+    now: DateTime<Local>,
+) -> Result<PathBuf> {
+    let name = format!("changed-{}.html", now.format("%Y%m%d-%H%M%S"));
+    write_atomic(
+        &config.dir,
+        &name,
+        &formatter.format_html(grade, Some(diff)),
+    )
+}
+
+/// Appends the GPA of `grade` fetched at `now` to the history, if enabled
+pub fn append_history(config: &OutputConfig, grade: &Grade, now: DateTime<Local>) -> Result<()> {
+    if !config.history {
+        return Ok(());
+    }
+    let dir = &config.dir;
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create `{}'", dir.display()))?;
+    let path = dir.join(HISTORY_FILE);
+    let line = serde_json::to_string(&Snapshot {
+        time: now.to_rfc3339(),
+        gpa: grade.gpa,
+    })?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .with_context(|| format!("Cannot write `{}'", path.display()))
+}
+
+/// An 80×20 chart of the GPA in the history, or how to record one if there
+/// is none
+pub fn history_chart(config: Option<&OutputConfig>) -> Result<String> {
+    const NO_HISTORY: &str = "No grade history yet. Set `dir` and `history = true` in [output] \
+                              to record the GPA after every fetch";
+    let path = match config {
+        Some(config) => config.dir.join(HISTORY_FILE),
+        None => return Ok(NO_HISTORY.to_owned()),
+    };
+    let history = match fs::read_to_string(&path) {
+        Ok(history) => history,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(NO_HISTORY.to_owned()),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read `{}'", path.display())),
+    };
+    let mut points = Vec::new();
+    for (i, line) in history
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        let invalid = || format!("Invalid line {} in `{}'", i + 1, path.display());
+        let snapshot: Snapshot = serde_json::from_str(line).with_context(invalid)?;
+        let time = DateTime::parse_from_rfc3339(&snapshot.time).with_context(invalid)?;
+        if let Some(gpa) = snapshot.gpa {
+            points.push((time.timestamp() as f64, gpa));
+        }
+    }
+    if points.len() < 2 {
+        return Ok(format!(
+            "`{}' has {} GPA, at least 2 are needed for a chart",
+            path.display(),
+            points.len()
+        ));
+    }
+    Ok(ascii_line_chart(&points, 80, 20, |x| {
+        Local
+            .timestamp_opt(x as i64, 0)
+            .single()
+            .map_or_else(String::new, |t| t.format("%Y-%m-%d").to_string())
+    }))
+}
+
+/// Writes `content` to `name` in `dir`, creating `dir` if missing, through a
+/// temporary file renamed over it
+fn write_atomic(dir: &Path, name: &str, content: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create `{}'", dir.display()))?;
+    let path = dir.join(name);
+    let tmp = dir.join(format!(".{}.tmp", name));
+    fs::write(&tmp, content).with_context(|| format!("Cannot write `{}'", tmp.display()))?;
+    set_readable(&tmp)?;
+    fs::rename(&tmp, &path).with_context(|| format!("Cannot replace `{}'", path.display()))?;
+    Ok(path)
+}
+
+/// Makes `path` readable by everyone, e.g. by a web server, with mode 0644
+#[cfg(unix)]
+fn set_readable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o644))
+        .with_context(|| format!("Cannot set the permissions of `{}'", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_readable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use ustc_get_grade::export::DefaultFormatter;
+    use ustc_get_grade::test_utils::mock_grade;
+
+    #[test]
+    fn reports_replaced_atomically() {
+        let dir = std::env::temp_dir()
+            .join(format!("ustc-get-grade-output-{}", std::process::id()))
+            .join("reports");
+        let config = OutputConfig {
+            dir: dir.clone(),
+            formats: vec![OutputFormat::Text, OutputFormat::Json],
+            history: false,
+        };
+        let old = mock_grade(&[]);
+        write_latest(&config, &old, &DefaultFormatter).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("latest.txt"), DefaultFormatter.format_text(&old, None));
+        assert_eq!(
+            serde_json::from_str::<Grade>(&read("latest.json")).unwrap(),
+            old
+        );
+        assert!(!dir.join("latest.html").exists());
+
+        let mut new = old.clone();
+        new.scores[2].courses[0].score = "81".to_owned();
+        write_latest(&config, &new, &DefaultFormatter).unwrap();
+        assert_eq!(read("latest.txt"), DefaultFormatter.format_text(&new, None));
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        // No temporary file is left behind
+        assert_eq!(names, ["latest.json", "latest.txt"]);
+
+        let now = Local.with_ymd_and_hms(2020, 1, 15, 8, 30, 5).unwrap();
+        let diff = GradeDiff::between(&old, &new);
+        let path = write_changed(&config, &new, &diff, &DefaultFormatter, now).unwrap();
+        assert_eq!(path, dir.join("changed-20200115-083005.html"));
+        assert_eq!(
+            read("changed-20200115-083005.html"),
+            DefaultFormatter.format_html(&new, Some(&diff))
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o644);
+        }
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn chart_of_history() {
+        let dir =
+            std::env::temp_dir().join(format!("ustc-get-grade-history-{}", std::process::id()));
+        let mut config = OutputConfig {
+            dir: dir.clone(),
+            formats: Vec::new(),
+            history: false,
+        };
+        let mut grade = mock_grade(&[]);
+        let start = Local.with_ymd_and_hms(2020, 1, 15, 8, 0, 0).unwrap();
+        append_history(&config, &grade, start).unwrap();
+        assert!(!dir.exists());
+        assert!(history_chart(Some(&config))
+            .unwrap()
+            .starts_with("No grade history yet"));
+        assert!(history_chart(None)
+            .unwrap()
+            .starts_with("No grade history yet"));
+
+        config.history = true;
+        for (day, gpa) in [3.32, 3.35, 3.41, 3.40, 3.52].iter().enumerate() {
+            grade.gpa = Some(*gpa);
+            let now = start + chrono::Duration::days(30 * day as i64);
+            append_history(&config, &grade, now).unwrap();
+        }
+        let chart = history_chart(Some(&config)).unwrap();
+        let lines: Vec<_> = chart.lines().collect();
+        assert_eq!(lines.len(), 22, "{}", chart);
+        assert!(lines[0].starts_with("  3.6┤"));
+        assert!(lines[19].starts_with("  3.3┤"));
+        assert!(lines[21].trim_start().starts_with("2020-01-15"));
+        assert!(lines[21].ends_with("2020-05-14"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
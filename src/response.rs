@@ -0,0 +1,250 @@
+//! Typed `getGradeList` responses
+//!
+//! Fields not listed here are ignored, so that additions upstream do not
+//! break parsing.
+
+use crate::{
+    normalize_course_name, CourseGrade, CourseStatus, Error, OverviewData, Rank, Semester,
+    PRIMARY_GPA,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct GradeList {
+    pub overview: Overview,
+    pub semesters: Vec<SemesterScores>,
+}
+
+/// A response of which only the overview is needed
+#[derive(Deserialize, Debug)]
+pub(crate) struct OverviewOnly {
+    pub overview: Overview,
+}
+
+/// Only `gpa` is sent for every selection of semesters, and it is `null`
+/// until any grade in them is published
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Overview {
+    pub gpa: Option<f64>,
+    pub passed_credits: Option<f64>,
+    pub failed_credits: Option<f64>,
+    pub passed_course_count: Option<u64>,
+    /// Fields not listed above, among which other GPAs may be
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SemesterScores {
+    pub id: usize,
+    pub name_zh: Option<String>,
+    pub scores: Vec<Score>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Score {
+    pub course_name_ch: String,
+    pub score_ch: String,
+    pub credits: f64,
+    pub passed: Option<bool>,
+    pub retake: Option<bool>,
+    #[serde(default)]
+    pub course_code: String,
+    pub course_type: Option<String>,
+    pub exam_type: Option<String>,
+    pub grade_time: Option<String>,
+}
+
+/// A `getStdRank` response, of which `rank` is `null` until the department
+/// publishes it
+#[derive(Deserialize, Debug)]
+pub(crate) struct RankResponse {
+    pub rank: Option<RankData>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RankData {
+    pub position: u32,
+    pub total: u32,
+    #[serde(default)]
+    pub scope_zh: String,
+}
+
+/// Parses `json`, naming the missing or mistyped field on failure
+pub(crate) fn parse<'a, T: Deserialize<'a>>(json: &'a str) -> Result<T, Error> {
+    serde_json::from_str(json).map_err(|e| Error::GradeMalformed(e.to_string()))
+}
+
+/// The value of the optional overview field `name`, failing if it is missing
+pub(crate) fn required<T>(field: Option<T>, name: &str) -> Result<T, Error> {
+    field.ok_or_else(|| Error::GradeMalformed(format!("missing field `overview.{}`", name)))
+}
+
+impl GradeList {
+    /// Merges the lists of separately fetched semesters in order. The GPA is
+    /// left out, as Jiaowu only sends those of the single semesters, and an
+    /// estimate from them would not match the one Jiaowu reports.
+    pub fn merge(mut lists: Vec<GradeList>) -> GradeList {
+        if lists.len() == 1 {
+            return lists.remove(0);
+        }
+        GradeList {
+            overview: Overview {
+                gpa: None,
+                passed_credits: lists.iter().map(|l| l.overview.passed_credits).sum(),
+                failed_credits: lists.iter().map(|l| l.overview.failed_credits).sum(),
+                passed_course_count: lists.iter().map(|l| l.overview.passed_course_count).sum(),
+                other: HashMap::new(),
+            },
+            semesters: lists.into_iter().flat_map(|l| l.semesters).collect(),
+        }
+    }
+}
+
+impl Overview {
+    /// `gpa` and every other number in a field whose name contains "gpa",
+    /// ignoring case
+    pub fn gpa_variants(&self) -> HashMap<String, f64> {
+        let others = self
+            .other
+            .iter()
+            .filter(|(name, _)| name.to_lowercase().contains("gpa"))
+            .filter_map(|(name, value)| Some((name.clone(), value.as_f64()?)));
+        let gpa = self.gpa.map(|gpa| (PRIMARY_GPA.to_owned(), gpa));
+        gpa.into_iter().chain(others).collect()
+    }
+
+    pub fn into_data(self) -> Result<OverviewData, Error> {
+        Ok(OverviewData {
+            gpa: self.gpa,
+            passed_credits: required(self.passed_credits, "passedCredits")?,
+            failed_credits: required(self.failed_credits, "failedCredits")?,
+            passed_course_count: required(self.passed_course_count, "passedCourseCount")?,
+        })
+    }
+}
+
+impl Score {
+    /// The status given by the `passed` and `retake` flags, if any
+    fn status(&self) -> Option<CourseStatus> {
+        match (self.passed, self.retake) {
+            (Some(false), _) => Some(CourseStatus::Failed),
+            (_, Some(true)) => Some(CourseStatus::Retake),
+            (Some(true), _) => Some(CourseStatus::Normal),
+            (None, _) => None,
+        }
+    }
+
+    pub fn into_course(self) -> CourseGrade {
+        let status = self.status();
+        let name = normalize_course_name(&self.course_name_ch);
+        let course = CourseGrade::new(name, self.score_ch, self.credits);
+        let status = status.unwrap_or(course.status);
+        let mut course = course.with_status(status).with_code(self.course_code);
+        course.course_type = self.course_type;
+        course.exam_type = self.exam_type;
+        course.graded_at = self.grade_time;
+        course
+    }
+}
+
+impl RankData {
+    pub fn into_rank(self) -> Rank {
+        Rank::new(self.position, self.total, self.scope_zh)
+    }
+}
+
+impl SemesterScores {
+    pub fn into_semester(self, name: String) -> Semester {
+        let courses = self.scores.into_iter().map(Score::into_course).collect();
+        Semester::new(name, courses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{extract_grade, mock_sem_map};
+
+    const FIXTURES: &[&str] = &[
+        include_str!("../tests/fixtures/grade_list.json"),
+        include_str!("../tests/fixtures/grade_list_pass_fail.json"),
+        include_str!("../tests/fixtures/grade_list_empty.json"),
+    ];
+
+    #[test]
+    fn fixtures_parse() {
+        for &fixture in FIXTURES {
+            parse::<GradeList>(fixture).unwrap();
+            parse::<OverviewOnly>(fixture)
+                .unwrap()
+                .overview
+                .into_data()
+                .unwrap();
+        }
+
+        let grade = extract_grade(FIXTURES[0].into(), FIXTURES[0].into(), mock_sem_map()).unwrap();
+        assert_eq!(grade.credits, 21.5);
+        assert_eq!(grade.total_courses(), 5);
+        let retaken = &grade.scores[1].courses[2];
+        assert_eq!(retaken.status, CourseStatus::Retake);
+        assert_eq!(retaken.code, "MATH1004");
+        assert_eq!(retaken.course_type.as_deref(), Some("必修"));
+        assert_eq!(retaken.exam_type.as_deref(), Some("重修"));
+
+        let grade = extract_grade(FIXTURES[1].into(), FIXTURES[1].into(), mock_sem_map()).unwrap();
+        let statuses: Vec<_> = grade.all_courses().map(|c| c.status).collect();
+        assert_eq!(statuses, [CourseStatus::Normal, CourseStatus::Failed]);
+        let military = &grade.scores[0].courses[0];
+        assert_eq!(military.code, "MIL1001");
+        assert_eq!(military.course_type, None);
+        assert_eq!(military.exam_type, None);
+
+        let grade = extract_grade(FIXTURES[2].into(), FIXTURES[2].into(), mock_sem_map()).unwrap();
+        assert!(grade.scores.is_empty());
+    }
+
+    #[test]
+    fn grade_time_optional() {
+        let score = |json: &str| serde_json::from_str::<Score>(json).unwrap().into_course();
+        let course = score(
+            r#"{"courseNameCh": "数学分析(B2)", "scoreCh": "78", "credits": 6.0,
+                "gradeTime": "2020-01-15 10:23:45"}"#,
+        );
+        assert_eq!(course.graded_at.as_deref(), Some("2020-01-15 10:23:45"));
+
+        let course = score(r#"{"courseNameCh": "数学分析(B2)", "scoreCh": "78", "credits": 6.0}"#);
+        assert_eq!(course.graded_at, None);
+        let course = score(
+            r#"{"courseNameCh": "数学分析(B2)", "scoreCh": "78", "credits": 6.0,
+                "gradeTime": null}"#,
+        );
+        assert_eq!(course.graded_at, None);
+    }
+
+    #[test]
+    fn errors_name_the_field() {
+        let message = |json: &str| match parse::<GradeList>(json) {
+            Err(Error::GradeMalformed(message)) => message,
+            r => panic!("unexpected result: {:?}", r),
+        };
+        let json = r#"{"overview": {"gpa": 3.0}, "semesters": [{"id": 101, "scores": [
+            {"courseNameCh": "数学分析(B1)", "scoreCh": "92"}]}]}"#;
+        assert!(message(json).starts_with("missing field `credits`"));
+        assert!(message(r#"{"overview": {"gpa": null}}"#).starts_with("missing field `semesters`"));
+
+        let overview = parse::<OverviewOnly>(r#"{"overview": {"gpa": 3.0}}"#).unwrap();
+        match overview.overview.into_data() {
+            Err(Error::GradeMalformed(message)) => {
+                assert_eq!(message, "missing field `overview.passedCredits`")
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Cron schedules for fetching more often at some times than others
+//!
+//! An expression has the five standard fields: minute, hour, day of month,
+//! month and day of week (0 or 7 is Sunday). Each field is a comma separated
+//! list of `*`, numbers and ranges like `8-23`, optionally with a step like
+//! `*/10`. Names like `MON` are not supported. As in cron, when both days of
+//! month and days of week are restricted, a day matching either fires.
+
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, TimeZone};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+    zone: Zone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Schedule {
+    /// Parses `expr` in `timezone`, which is `local` (the default), `UTC` or
+    /// an offset like `+08:00`
+    pub fn new(expr: &str, timezone: Option<&str>) -> Result<Self> {
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        ensure!(
+            fields.len() == 5,
+            "Expected 5 fields in the schedule, found {}",
+            fields.len()
+        );
+
+        let mut weekdays = parse_field(fields[4], 0, 7).context("Invalid day of week")?;
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Ok(Schedule {
+            minutes: parse_field(fields[0], 0, 59).context("Invalid minute")?,
+            hours: parse_field(fields[1], 0, 23).context("Invalid hour")?,
+            days: parse_field(fields[2], 1, 31).context("Invalid day of month")?,
+            months: parse_field(fields[3], 1, 12).context("Invalid month")?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+            zone: parse_zone(timezone.unwrap_or("local"))?,
+        })
+    }
+
+    /// The first time after `now` that matches the schedule, `None` if there
+    /// is none, e.g. for February 30
+    pub fn next_after(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self.zone {
+            Zone::Local => self.next_in(&now),
+            Zone::Fixed(offset) => self
+                .next_in(&now.with_timezone(&offset))
+                .map(|t| t.with_timezone(&Local)),
+        }
+    }
+
+    fn next_in<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let tz = now.timezone();
+        // Days of month and week line up again within 28 years, but a day
+        // that matches at all is found much sooner
+        let days = now.naive_local().date().iter_days().take(366 * 8);
+        for date in days.filter(|&d| self.matches(d)) {
+            for hour in bits(self.hours) {
+                for minute in bits(self.minutes) {
+                    let local = date.and_hms_opt(hour, minute, 0)?;
+                    // Times skipped by a DST change never fire
+                    match tz.from_local_datetime(&local).earliest() {
+                        Some(t) if t > *now => return Some(t),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        let day_matches = if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        };
+        day_matches && has(self.months, date.month())
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = number(step)?;
+                ensure!(step > 0, "Step must be positive");
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+        let (low, high) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((low, high)) => (number(low)?, number(high)?),
+            // `5/15` means from 5 to the end in steps of 15
+            None if step.is_some() => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if low < min || high > max || low > high {
+            bail!("`{}' is not within {}-{}", range, min, max);
+        }
+        for value in (low..=high).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+fn number(s: &str) -> Result<u32> {
+    s.parse()
+        .with_context(|| format!("`{}' is not a number", s))
+}
+
+fn parse_zone(timezone: &str) -> Result<Zone> {
+    if timezone.eq_ignore_ascii_case("local") {
+        return Ok(Zone::Local);
+    }
+    if timezone.eq_ignore_ascii_case("utc") {
+        return Ok(Zone::Fixed(FixedOffset::east_opt(0).unwrap()));
+    }
+
+    let (sign, offset) = match timezone.split_at(timezone.len().min(1)) {
+        ("+", offset) => (1, offset),
+        ("-", offset) => (-1, offset),
+        _ => bail!("Unknown timezone `{}'", timezone),
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let seconds = number(hours)? * 3600 + number(minutes)? * 60;
+    FixedOffset::east_opt(sign * seconds as i32)
+        .map(Zone::Fixed)
+        .with_context(|| format!("Timezone offset `{}' is out of range", timezone))
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & 1 << value != 0
+}
+
+fn bits(set: u64) -> impl Iterator<Item = u32> {
+    (0..64).filter(move |&v| has(set, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(expr: &str, now: &str) -> String {
+        let schedule = Schedule::new(expr, Some("+08:00")).unwrap();
+        let now = DateTime::parse_from_rfc3339(now).unwrap();
+        let next = schedule.next_after(now.with_timezone(&Local)).unwrap();
+        next.with_timezone(now.offset()).to_rfc3339()
+    }
+
+    #[test]
+    fn every_ten_minutes_during_the_day() {
+        let expr = "*/10 8-23 * * *";
+        assert_eq!(
+            next(expr, "2020-01-15T08:03:00+08:00"),
+            "2020-01-15T08:10:00+08:00"
+        );
+        assert_eq!(
+            next(expr, "2020-01-15T08:10:00+08:00"),
+            "2020-01-15T08:20:00+08:00"
+        );
+        assert_eq!(
+            next(expr, "2020-01-15T23:55:00+08:00"),
+            "2020-01-16T08:00:00+08:00"
+        );
+    }
+
+    #[test]
+    fn days_of_month_and_week() {
+        // 2020-01-15 is a Wednesday
+        assert_eq!(
+            next("0 9 * * 1,7", "2020-01-15T12:00:00+08:00"),
+            "2020-01-19T09:00:00+08:00"
+        );
+        assert_eq!(
+            next("0 9 20 * 0", "2020-01-15T12:00:00+08:00"),
+            "2020-01-19T09:00:00+08:00"
+        );
+        assert_eq!(
+            next("30 0 29 2 *", "2020-03-01T00:00:00+08:00"),
+            "2024-02-29T00:30:00+08:00"
+        );
+    }
+
+    #[test]
+    fn other_timezones() {
+        let schedule = Schedule::new("0 12 * * *", Some("UTC")).unwrap();
+        let now = DateTime::parse_from_rfc3339("2020-01-15T13:00:00+08:00").unwrap();
+        let next = schedule.next_after(now.with_timezone(&Local)).unwrap();
+        assert_eq!(
+            next.with_timezone(now.offset()).to_rfc3339(),
+            "2020-01-15T20:00:00+08:00"
+        );
+        assert!(Schedule::new("0 12 * * *", Some("-05:30")).is_ok());
+        assert!(Schedule::new("0 12 * * *", Some("Asia/Shanghai")).is_err());
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let error = |expr| format!("{:#}", Schedule::new(expr, None).unwrap_err());
+        assert_eq!(
+            error("* * * *"),
+            "Expected 5 fields in the schedule, found 4"
+        );
+        assert_eq!(
+            error("60 * * * *"),
+            "Invalid minute: `60' is not within 0-59"
+        );
+        assert_eq!(
+            error("* 9-5 * * *"),
+            "Invalid hour: `9-5' is not within 0-23"
+        );
+        assert_eq!(
+            error("*/0 * * * *"),
+            "Invalid minute: Step must be positive"
+        );
+        assert_eq!(
+            error("* * * * MON"),
+            "Invalid day of week: `MON' is not a number: invalid digit found in string"
+        );
+        assert!(Schedule::new("0 0 30 2 *", None)
+            .unwrap()
+            .next_after(Local::now())
+            .is_none());
+    }
+}
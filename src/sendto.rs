@@ -0,0 +1,293 @@
+//! Adding and removing recipients in `[mail] sendto` in place
+//!
+//! Only the text of the `sendto` array is replaced, so that the comments and
+//! the layout of the rest of the config are kept. Comments inside the array
+//! are lost.
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use std::ops::Range;
+use toml::Value;
+
+/// A change to the recipients
+#[derive(Debug, Clone, Copy)]
+pub enum Edit<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+}
+
+/// Applies `edit` to the `sendto` array in the config `raw`, and returns the
+/// new config with the addresses in the new array
+pub fn edit_sendto(raw: &str, edit: Edit) -> Result<(String, Vec<String>)> {
+    let config: Value = toml::from_str(raw).context("Invalid config")?;
+    let mut sendto = config
+        .get("mail")
+        .and_then(|m| m.get("sendto"))
+        .and_then(Value::as_array)
+        .cloned()
+        .context("No sendto array in [mail]")?;
+    let has = |sendto: &[Value], address: &str| {
+        sendto
+            .iter()
+            .any(|r| matches!(address_of(r), Some(a) if a.eq_ignore_ascii_case(address)))
+    };
+    match edit {
+        Edit::Add(address) => {
+            anyhow::ensure!(
+                address.contains('@'),
+                "`{}' is not an email address",
+                address
+            );
+            anyhow::ensure!(!has(&sendto, address), "`{}' is already in sendto", address);
+            sendto.push(Value::String(address.to_owned()));
+        }
+        Edit::Remove(address) => {
+            anyhow::ensure!(has(&sendto, address), "`{}' is not in sendto", address);
+            sendto.retain(|r| !matches!(address_of(r), Some(a) if a.eq_ignore_ascii_case(address)));
+            anyhow::ensure!(!sendto.is_empty(), "sendto must not be empty");
+        }
+    }
+
+    let span = sendto_span(raw).context("Cannot find sendto in [mail]")?;
+    let edited = format!(
+        "{}{}{}",
+        &raw[..span.start],
+        render_array(&sendto, &raw[span.clone()]),
+        &raw[span.end..]
+    );
+    let _: crate::Config = toml::from_str(&edited).context("The edited config is invalid")?;
+    let addresses = sendto
+        .iter()
+        .filter_map(address_of)
+        .map(str::to_owned)
+        .collect();
+    Ok((edited, addresses))
+}
+
+/// The address of a recipient, which is either a string or a table with an
+/// `address`
+fn address_of(recipient: &Value) -> Option<&str> {
+    match recipient {
+        Value::Table(t) => t.get("address").and_then(Value::as_str),
+        r => r.as_str(),
+    }
+}
+
+/// Renders `values` as an inline array, with a value on each line if
+/// `original` spans several lines
+fn render_array(values: &[Value], original: &str) -> String {
+    let items: Vec<_> = values.iter().map(render_value).collect();
+    if !original.contains('\n') {
+        return format!("[{}]", items.join(", "));
+    }
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let item_indent = original
+        .lines()
+        .skip(1)
+        .find(|l| !l.trim().is_empty() && !l.trim().starts_with(']'))
+        .map_or(4, indent);
+    let close_indent = original.lines().last().map_or(0, indent);
+    format!(
+        "[\n{}]",
+        items
+            .iter()
+            .map(|item| format!("{:width$}{},\n", "", item, width = item_indent))
+            .join("")
+            + &" ".repeat(close_indent)
+    )
+}
+
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::Table(t) => format!(
+            "{{ {} }}",
+            t.iter()
+                .map(|(k, v)| format!("{} = {}", k, render_value(v)))
+                .join(", ")
+        ),
+        Value::Array(a) => format!("[{}]", a.iter().map(render_value).join(", ")),
+        v => v.to_string(),
+    }
+}
+
+/// The byte range of the array of `sendto` in `[mail]`, from its `[` to its
+/// `]`
+fn sendto_span(raw: &str) -> Option<Range<usize>> {
+    let mut section = "";
+    let mut pos = 0;
+    while pos < raw.len() {
+        let line_end = raw[pos..].find('\n').map_or(raw.len(), |i| pos + i + 1);
+        let line = raw[pos..line_end].trim();
+        if line.starts_with('[') {
+            section = line[1..line.find(']')?].trim();
+            pos = line_end;
+            continue;
+        }
+        let eq = match line.find('=') {
+            Some(_) if !line.starts_with('#') => pos + raw[pos..].find('=')?,
+            _ => {
+                pos = line_end;
+                continue;
+            }
+        };
+        let key = raw[pos..eq].trim().trim_matches('"');
+        let start = eq + 1 + (raw[eq + 1..].len() - raw[eq + 1..].trim_start().len());
+        let end = value_end(raw, start);
+        if section == "mail" && key == "sendto" {
+            return Some(start..end).filter(|_| raw[start..].starts_with('['));
+        }
+        pos = raw[end..].find('\n').map_or(raw.len(), |i| end + i + 1);
+    }
+    None
+}
+
+/// The end of the value starting at `start`, skipping over strings,
+/// comments and nested arrays and tables
+fn value_end(raw: &str, start: usize) -> usize {
+    let bytes = raw.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                let quote = ["\"\"\"", "'''", "\"", "'"]
+                    .iter()
+                    .find(|q| raw[i..].starts_with(*q))
+                    .unwrap();
+                // Compares bytes, as `j` may be inside a multi-byte character
+                let mut j = i + quote.len();
+                while j < bytes.len() && !bytes[j..].starts_with(quote.as_bytes()) {
+                    j += if bytes[j] == b'\\' && quote.starts_with('"') {
+                        2
+                    } else {
+                        1
+                    };
+                }
+                i = (j + quote.len()).min(bytes.len());
+                if depth == 0 {
+                    return i;
+                }
+                continue;
+            }
+            b'#' | b'\n' if depth == 0 => return raw[..i].trim_end().len().max(start),
+            b'#' => i += raw[i..].find('\n').unwrap_or(bytes.len() - i),
+            b'[' | b'{' => depth += 1,
+            b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"# My config
+[mail]
+username = "abc@example.com"   # the bot
+password = "pass]word#"
+server = "smtp.example.com"
+sendto = ["a@example.com", { address = "b@example.com", format = "text" }]  # people
+bilingual_email = true
+
+[ustc]
+username = "PB19000000"
+password = "p@ssword"
+semesters = ["all"]
+interval = 30
+"#;
+
+    #[test]
+    fn add_and_remove() {
+        let (added, addresses) = edit_sendto(CONFIG, Edit::Add("c@example.com")).unwrap();
+        assert_eq!(
+            added,
+            CONFIG.replace(r#""text" }]  #"#, r#""text" }, "c@example.com"]  #"#)
+        );
+        assert_eq!(
+            addresses,
+            ["a@example.com", "b@example.com", "c@example.com"]
+        );
+
+        let (removed, addresses) = edit_sendto(&added, Edit::Remove("B@example.com")).unwrap();
+        assert_eq!(
+            removed,
+            CONFIG.replace(
+                r#"{ address = "b@example.com", format = "text" }"#,
+                r#""c@example.com""#
+            )
+        );
+        assert_eq!(addresses, ["a@example.com", "c@example.com"]);
+    }
+
+    #[test]
+    fn multi_line_array() {
+        let raw = CONFIG.replace(
+            r#"sendto = ["a@example.com", { address = "b@example.com", format = "text" }]"#,
+            "sendto = [\n  \"a@example.com\",  # me\n  \"b@example.com\"\n]",
+        );
+        let (edited, _) = edit_sendto(&raw, Edit::Add("c@example.com")).unwrap();
+        assert!(edited.contains(
+            "sendto = [\n  \"a@example.com\",\n  \"b@example.com\",\n  \"c@example.com\",\n]  # people\n"
+        ), "{}", edited);
+    }
+
+    #[test]
+    fn non_ascii_strings() {
+        // Non-ASCII strings before `sendto`, with [ustc] first
+        let (mail, ustc) = CONFIG.split_at(CONFIG.find("[ustc]").unwrap());
+        let raw = format!("{}\n{}", ustc, mail)
+            .replace(
+                r#"semesters = ["all"]"#,
+                r#"semesters = ["2019年秋季学期"]"#,
+            )
+            .replace("[mail]\n", "[mail]\nfrom_name = \"成绩\\\"通知\"\n");
+        let (edited, addresses) = edit_sendto(&raw, Edit::Add("c@example.com")).unwrap();
+        assert_eq!(
+            edited,
+            raw.replace(r#""text" }]  #"#, r#""text" }, "c@example.com"]  #"#)
+        );
+        assert_eq!(addresses.len(), 3);
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let message = |edit| format!("{:#}", edit_sendto(CONFIG, edit).unwrap_err());
+        assert_eq!(
+            message(Edit::Add("a@example.com")),
+            "`a@example.com' is already in sendto"
+        );
+        assert_eq!(
+            message(Edit::Add("nobody")),
+            "`nobody' is not an email address"
+        );
+        assert_eq!(
+            message(Edit::Remove("c@example.com")),
+            "`c@example.com' is not in sendto"
+        );
+
+        let (one, _) = edit_sendto(CONFIG, Edit::Remove("a@example.com")).unwrap();
+        assert_eq!(
+            format!(
+                "{:#}",
+                edit_sendto(&one, Edit::Remove("b@example.com")).unwrap_err()
+            ),
+            "sendto must not be empty"
+        );
+        let no_mail = &CONFIG[CONFIG.find("[ustc]").unwrap()..];
+        assert_eq!(
+            format!(
+                "{:#}",
+                edit_sendto(no_mail, Edit::Add("c@example.com")).unwrap_err()
+            ),
+            "No sendto array in [mail]"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Graceful shutdown on SIGINT and SIGTERM
+//!
+//! The first signal only sets a flag, which interrupts
+//! [`sleep`](fn.sleep.html) so that the main loop can stop after finishing
+//! what it is doing. A second signal exits immediately.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle(_: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(130) }
+    }
+}
+
+/// Installs the handlers of SIGINT and SIGTERM
+pub fn install() {
+    let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Whether a shutdown has been requested
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `dur`. Returns `false` as soon as a shutdown is requested.
+pub fn sleep(dur: Duration) -> bool {
+    const TICK: Duration = Duration::from_millis(200);
+
+    let deadline = Instant::now() + dur;
+    while !requested() {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep(TICK.min(deadline - now));
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_interrupts_sleep() {
+        install();
+        assert!(sleep(Duration::from_millis(10)));
+
+        unsafe { libc::raise(libc::SIGTERM) };
+        let start = Instant::now();
+        assert!(!sleep(Duration::from_secs(60)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
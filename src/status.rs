@@ -0,0 +1,192 @@
+//! Liveness endpoint for Docker `HEALTHCHECK`, Uptime Kuma and the like
+//!
+//! `GET /healthz` returns a small JSON summary of recent fetches, and 503
+//! once too many fetches in a row have failed. Courses are only included
+//! with `verbose = true`.
+
+use crate::http::{self, Response};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+use ustc_get_grade::Grade;
+
+#[derive(Debug, Deserialize)]
+pub struct StatusConfig {
+    listen: String,
+    /// Includes the courses in the response
+    #[serde(default)]
+    verbose: bool,
+    /// Number of failed fetches in a row after which the service is unhealthy
+    #[serde(default = "default_max_failures")]
+    max_failures: u32,
+}
+
+fn default_max_failures() -> u32 {
+    3
+}
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| Mutex::new(State::new()));
+
+#[derive(Debug)]
+struct State {
+    started: Instant,
+    last_fetch: Option<DateTime<Local>>,
+    last_error: Option<String>,
+    failures: u32,
+    next_fetch: Option<DateTime<Local>>,
+    grade: Option<Grade>,
+}
+
+impl State {
+    fn new() -> Self {
+        State {
+            started: Instant::now(),
+            last_fetch: None,
+            last_error: None,
+            failures: 0,
+            next_fetch: None,
+            grade: None,
+        }
+    }
+
+    fn healthz(&self, verbose: bool, max_failures: u32) -> Response {
+        let last_result = match (&self.last_fetch, &self.last_error) {
+            (None, _) => Value::Null,
+            (Some(_), None) => json!({ "ok": true }),
+            (Some(_), Some(e)) => json!({ "ok": false, "error": e }),
+        };
+        let mut body = json!({
+            "uptime_seconds": self.started.elapsed().as_secs(),
+            "last_fetch": self.last_fetch.map(|t| t.to_rfc3339()),
+            "last_result": last_result,
+            "consecutive_failures": self.failures,
+            "next_fetch": self.next_fetch.map(|t| t.to_rfc3339()),
+            "gpa": self.grade.as_ref().and_then(|g| g.gpa),
+        });
+        if verbose {
+            body["semesters"] = self.grade.as_ref().map_or(Value::Null, semesters_json);
+        }
+
+        let status = if self.failures >= max_failures {
+            "503 Service Unavailable"
+        } else {
+            "200 OK"
+        };
+        Response::new(status, "application/json", body.to_string())
+    }
+}
+
+fn semesters_json(grade: &Grade) -> Value {
+    grade
+        .scores
+        .iter()
+        .map(|s| {
+            let courses: Vec<_> = s
+                .courses
+                .iter()
+                .map(|c| json!({ "name": c.name, "score": c.score, "credits": c.credits }))
+                .collect();
+            json!({ "name": s.name, "courses": courses })
+        })
+        .collect()
+}
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn fetch_succeeded(grade: &Grade) {
+    let mut state = lock();
+    state.last_fetch = Some(Local::now());
+    state.last_error = None;
+    state.failures = 0;
+    state.grade = Some(grade.clone());
+}
+
+pub fn fetch_failed(error: &dyn Display) {
+    let mut state = lock();
+    state.last_fetch = Some(Local::now());
+    state.last_error = Some(error.to_string());
+    state.failures += 1;
+}
+
+pub fn next_fetch(at: DateTime<Local>) {
+    lock().next_fetch = Some(at);
+}
+
+/// Serves `/healthz` in a background thread
+pub fn serve(config: &StatusConfig) -> Result<()> {
+    let (verbose, max_failures) = (config.verbose, config.max_failures);
+    http::serve(&config.listen, move |path| match path {
+        "/healthz" => lock().healthz(verbose, max_failures),
+        _ => Response::not_found(),
+    })?;
+    log::info!("Serving status on {}", config.listen);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ustc_get_grade::test_utils::mock_grade;
+
+    fn body(response: &Response) -> Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn starting() {
+        let response = State::new().healthz(false, 3);
+        assert_eq!(response.status, "200 OK");
+        let body = body(&response);
+        assert_eq!(body["last_fetch"], Value::Null);
+        assert_eq!(body["last_result"], Value::Null);
+        assert_eq!(body["gpa"], Value::Null);
+    }
+
+    #[test]
+    fn last_fetch_ok() {
+        let mut state = State::new();
+        state.last_fetch = Some(Local::now());
+        state.grade = Some(mock_grade(&[]));
+
+        let body = body(&state.healthz(false, 3));
+        assert_eq!(body["last_result"], json!({ "ok": true }));
+        assert_eq!(body["gpa"], json!(3.52));
+        assert!(body.get("semesters").is_none());
+    }
+
+    #[test]
+    fn unhealthy_after_failures() {
+        let mut state = State::new();
+        state.last_fetch = Some(Local::now());
+        state.last_error = Some("Jiaowu login failed".to_owned());
+        state.failures = 2;
+
+        let response = state.healthz(false, 3);
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(
+            body(&response)["last_result"],
+            json!({ "ok": false, "error": "Jiaowu login failed" })
+        );
+        state.failures = 3;
+        assert_eq!(state.healthz(false, 3).status, "503 Service Unavailable");
+    }
+
+    #[test]
+    fn courses_only_when_verbose() {
+        let mut state = State::new();
+        state.last_fetch = Some(Local::now());
+        state.grade = Some(mock_grade(&["2019年夏季学期"]));
+
+        assert!(!state.healthz(false, 3).body.contains("军事技能"));
+        let body = body(&state.healthz(true, 3));
+        assert_eq!(body["semesters"][0]["name"], "2019年夏季学期");
+        assert_eq!(body["semesters"][0]["courses"][0]["name"], "军事技能");
+    }
+}
@@ -0,0 +1,35 @@
+//! Readiness, watchdog and status notifications to systemd
+//!
+//! Everything here is a no-op when built without the `systemd` feature, or
+//! when not launched by systemd (`NOTIFY_SOCKET` is unset).
+
+#[cfg(feature = "systemd")]
+fn notify(state: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        log::warn!("Notify systemd failed: {}", e);
+    }
+}
+
+/// Tells systemd that startup is finished
+pub fn ready() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Ready]);
+}
+
+/// Tells systemd that the service is shutting down
+pub fn stopping() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Stopping]);
+}
+
+/// Resets the watchdog timer set by `WatchdogSec=`
+pub fn watchdog() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Watchdog]);
+}
+
+/// Sets the status shown by `systemctl status`
+pub fn status(_status: &str) {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Status(_status)]);
+}
@@ -0,0 +1,186 @@
+//! Canned Jiaowu responses for testing without network access
+//!
+//! Enabled in this crate's own tests, and for library users with the
+//! `test-utils` feature.
+
+use crate::Grade;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// (id, nameZh, nameEn, schoolYear, current)
+const SEMESTERS: &[(usize, &str, &str, &str, bool)] = &[
+    (101, "2019年春季学期", "2019 Spring", "2018-2019", false),
+    (102, "2019年夏季学期", "2019 Summer", "2018-2019", false),
+    (121, "2019年秋季学期", "2019 Fall", "2019-2020", true),
+];
+
+/// (semester id, course name, score, credits)
+const SCORES: &[(usize, &str, &str, f64)] = &[
+    (101, "数学分析(B1)", "92", 6.0),
+    (101, "线性代数(B1)", "85", 4.0),
+    (102, "军事技能", "通过", 1.0),
+    (121, "数学分析(B2)", "78", 6.0),
+    (121, "大学物理-基础实验A", "优秀", 1.5),
+];
+
+/// Overall GPA in [`mock_grade_response`](fn.mock_grade_response.html)
+pub const MOCK_GPA: f64 = 3.52;
+
+/// GPA of any proper subset of semesters in
+/// [`mock_grade_response`](fn.mock_grade_response.html)
+pub const MOCK_SEM_GPA: f64 = 3.3;
+
+/// A `getSemesters` response
+pub fn mock_semesters_response() -> String {
+    let sems: Vec<_> = SEMESTERS
+        .iter()
+        .map(|&(id, zh, en, year, current)| {
+            json!({
+                "id": id,
+                "nameZh": zh,
+                "nameEn": en,
+                "schoolYear": year,
+                "current": current,
+            })
+        })
+        .collect();
+    Value::Array(sems).to_string()
+}
+
+/// A `getGradeList` response containing `semesters`, or all the semesters if
+/// `semesters` is empty
+pub fn mock_grade_response(semesters: &[&str]) -> String {
+    let selected: Vec<_> = SEMESTERS
+        .iter()
+        .filter(|s| semesters.is_empty() || semesters.contains(&s.1))
+        .map(|s| s.0)
+        .collect();
+    let gpa = if selected.len() == SEMESTERS.len() {
+        MOCK_GPA
+    } else {
+        MOCK_SEM_GPA
+    };
+
+    let sems: Vec<_> = selected
+        .iter()
+        .map(|&id| {
+            let scores: Vec<_> = SCORES
+                .iter()
+                .filter(|s| s.0 == id)
+                .map(|&(_, name, score, credits)| {
+                    json!({
+                        "courseNameCh": name,
+                        "scoreCh": score,
+                        "credits": credits,
+                    })
+                })
+                .collect();
+            json!({ "id": id, "scores": scores })
+        })
+        .collect();
+    let credits: f64 = SCORES
+        .iter()
+        .filter(|s| selected.contains(&s.0))
+        .map(|s| s.3)
+        .sum();
+
+    json!({
+        "overview": {
+            "gpa": gpa,
+            "passedCredits": credits,
+            "failedCredits": 0.0,
+            "passedCourseCount": SCORES.iter().filter(|s| selected.contains(&s.0)).count(),
+        },
+        "semesters": sems,
+    })
+    .to_string()
+}
+
+/// The semester id to name map built from
+/// [`mock_semesters_response`](fn.mock_semesters_response.html)
+pub fn mock_sem_map() -> HashMap<usize, String> {
+    SEMESTERS
+        .iter()
+        .map(|&(id, zh, ..)| (id, zh.to_owned()))
+        .collect()
+}
+
+/// The grade `get_grade` would return for `semesters` against the mock
+/// responses
+pub fn mock_grade(semesters: &[&str]) -> Grade {
+    extract_grade(
+        mock_grade_response(&[]),
+        mock_grade_response(semesters),
+        mock_sem_map(),
+    )
+    .expect("mock responses should be well-formed")
+}
+
+/// Extracts a grade from `getGradeList` responses of all semesters and of the
+/// selected semesters
+pub fn extract_grade(
+    all: String,
+    sem: String,
+    sem_map: HashMap<usize, String>,
+) -> Result<Grade, crate::Error> {
+    crate::extract_grade(all, sem, sem_map)
+}
+
+/// Asserts two grades are equal, comparing floating point numbers with a
+/// tolerance
+pub fn assert_grade_eq(left: &Grade, right: &Grade) {
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    fn close_gpa(a: Option<f64>, b: Option<f64>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => close(a, b),
+            (a, b) => a == b,
+        }
+    }
+
+    assert!(
+        close_gpa(left.gpa, right.gpa),
+        "gpa: {:?} != {:?}",
+        left.gpa,
+        right.gpa
+    );
+    assert!(
+        close_gpa(left.sem_gpa, right.sem_gpa),
+        "sem_gpa: {:?} != {:?}",
+        left.sem_gpa,
+        right.sem_gpa
+    );
+    assert!(
+        close(left.credits, right.credits),
+        "credits: {} != {}",
+        left.credits,
+        right.credits
+    );
+    assert_eq!(left.scores.len(), right.scores.len(), "semester count");
+    for (ls, rs) in left.scores.iter().zip(right.scores.iter()) {
+        assert_eq!(ls.name, rs.name, "semester name");
+        assert_eq!(
+            ls.courses.len(),
+            rs.courses.len(),
+            "course count of {}",
+            ls.name
+        );
+        for (l, r) in ls.courses.iter().zip(rs.courses.iter()) {
+            assert_eq!(
+                (&l.name, &l.score),
+                (&r.name, &r.score),
+                "course in {}",
+                ls.name
+            );
+            assert!(
+                close(l.credits, r.credits),
+                "credits of {}: {} != {}",
+                l.name,
+                l.credits,
+                r.credits
+            );
+        }
+    }
+}
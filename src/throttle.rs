@@ -0,0 +1,127 @@
+//! Spacing of logins to passport.ustc.edu.cn
+//!
+//! The passport server starts asking for captchas after frequent logins, so
+//! all the logins in this process go through a shared
+//! [`LoginThrottle`](struct.LoginThrottle.html).
+
+use once_cell::sync::Lazy;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Default minimum interval between two logins
+pub const DEFAULT_MIN_LOGIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default time to wait before logging in again after a captcha is requested
+pub const DEFAULT_CAPTCHA_COOLDOWN: Duration = Duration::from_secs(30 * 60);
+
+static THROTTLE: Lazy<LoginThrottle> = Lazy::new(LoginThrottle::default);
+
+/// The throttle used by [get_grade](../fn.get_grade.html)
+pub fn login_throttle() -> &'static LoginThrottle {
+    &THROTTLE
+}
+
+/// Enforces a minimum interval between logins
+#[derive(Debug)]
+pub struct LoginThrottle {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    min_interval: Duration,
+    captcha_cooldown: Duration,
+    next_login: Option<Instant>,
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        LoginThrottle::new(DEFAULT_MIN_LOGIN_INTERVAL, DEFAULT_CAPTCHA_COOLDOWN)
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(min_interval: Duration, captcha_cooldown: Duration) -> Self {
+        LoginThrottle {
+            inner: Mutex::new(Inner {
+                min_interval,
+                captcha_cooldown,
+                next_login: None,
+            }),
+        }
+    }
+
+    pub fn set_min_interval(&self, min_interval: Duration) {
+        self.lock().min_interval = min_interval;
+    }
+
+    pub fn set_captcha_cooldown(&self, captcha_cooldown: Duration) {
+        self.lock().captcha_cooldown = captcha_cooldown;
+    }
+
+    /// Reserves a login at `now`, returning how long the caller should wait
+    /// before actually logging in
+    pub fn reserve(&self, now: Instant) -> Duration {
+        let mut inner = self.lock();
+        let at = match inner.next_login {
+            Some(next) if next > now => next,
+            _ => now,
+        };
+        inner.next_login = Some(at + inner.min_interval);
+        at - now
+    }
+
+    /// Records that the passport server asked for a captcha at `now`, so that
+    /// the next login is delayed by the captcha cooldown
+    pub fn captcha_detected(&self, now: Instant) {
+        let mut inner = self.lock();
+        let until = now + inner.captcha_cooldown;
+        inner.next_login = Some(inner.next_login.map_or(until, |next| next.max(until)));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(Duration::from_secs(60), Duration::from_secs(600))
+    }
+
+    #[test]
+    fn rapid_logins_are_spaced() {
+        let throttle = throttle();
+        let now = Instant::now();
+
+        assert_eq!(throttle.reserve(now), Duration::from_secs(0));
+        assert_eq!(throttle.reserve(now), Duration::from_secs(60));
+        assert_eq!(
+            throttle.reserve(now + Duration::from_secs(1)),
+            Duration::from_secs(119)
+        );
+    }
+
+    #[test]
+    fn spaced_logins_do_not_wait() {
+        let throttle = throttle();
+        let now = Instant::now();
+
+        assert_eq!(throttle.reserve(now), Duration::from_secs(0));
+        let later = now + Duration::from_secs(61);
+        assert_eq!(throttle.reserve(later), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn captcha_backs_off() {
+        let throttle = throttle();
+        let now = Instant::now();
+
+        throttle.reserve(now);
+        throttle.captcha_detected(now);
+        assert_eq!(throttle.reserve(now), Duration::from_secs(600));
+    }
+}